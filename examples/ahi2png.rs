@@ -9,7 +9,7 @@ use std::path::Path;
 
 fn convert_image(image: &ahi::Image, output_path: &Path) -> io::Result<()> {
     let rgba_data = image.rgba_data(ahi::Palette::default());
-    let output_file = File::create(&output_path)?;
+    let output_file = File::create(output_path)?;
     let mut encoder =
         png::Encoder::new(output_file, image.width(), image.height());
     encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
@@ -32,7 +32,7 @@ fn main() -> io::Result<()> {
 
     let path_arg = env::args().nth(1).unwrap();
     let input_path = Path::new(&path_arg);
-    let input_file = File::open(&input_path)?;
+    let input_file = File::open(input_path)?;
     let collection = ahi::Collection::read(input_file)?;
     if collection.images.len() == 1 {
         convert_image(
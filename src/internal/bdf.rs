@@ -0,0 +1,619 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use crate::internal::color::Color;
+use crate::internal::font::{Font, Glyph};
+use crate::internal::image::Image;
+use std::cmp::{max, min};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::io::{self, BufRead, BufReader, Error, ErrorKind, Read, Write};
+
+// ========================================================================= //
+
+/// The BDF glyph name used for the font's default glyph, which is written
+/// without an encoding.
+const DEFAULT_GLYPH_NAME: &str = ".notdef";
+
+/// The key used to report the default glyph from `Font::write_bdf`, matching
+/// the tag used for it by `Font::to_collection`.
+const DEFAULT_GLYPH_KEY: &str = "def";
+
+/// The largest magnitude permitted for a BDF size, offset, or metric, so
+/// that glyph images stay reasonably sized and computed edges and offsets
+/// can't overflow.
+const MAX_BDF_VALUE: i64 = 0xFFFF;
+
+// ========================================================================= //
+
+impl Font {
+    /// Reads a font from a BDF (Glyph Bitmap Distribution Format) file.  Set
+    /// pixels in the glyph bitmaps become pixels of the given color, and
+    /// unset pixels become transparent.
+    ///
+    /// The font's baseline is taken from the `FONT_ASCENT` property, and its
+    /// glyph height (and line spacing) from the sum of `FONT_ASCENT` and
+    /// `FONT_DESCENT`; if these properties are missing, the
+    /// `FONTBOUNDINGBOX` is used instead.  Each glyph's image and top offset
+    /// come from its `BBX` and `BITMAP`, and its left and right edges from
+    /// its `BBX` x-offset and `DWIDTH`.  An unencoded glyph named `.notdef`
+    /// becomes the font's default glyph; failing that, the glyph named by the
+    /// `DEFAULT_CHAR` property is also used as the default glyph.  Other
+    /// unencoded glyphs are ignored.
+    pub fn read_bdf<R: Read>(reader: R, color: Color) -> io::Result<Font> {
+        let mut lines = BdfLines::new(reader);
+        let line = lines.next_line()?;
+        if line.keyword != "STARTFONT" {
+            return Err(line.error("expected STARTFONT"));
+        }
+        let mut name = String::new();
+        let mut family_name = None;
+        let mut bounding_box = None;
+        let mut font_dwidth = None;
+        let mut ascent = None;
+        let mut descent = None;
+        let mut default_char = None;
+        let mut glyphs: Vec<(i64, String, BdfGlyph)> = Vec::new();
+        loop {
+            let line = lines.next_line()?;
+            match line.keyword.as_str() {
+                "FONT" => name = line.rest.clone(),
+                "FONTBOUNDINGBOX" => bounding_box = Some(line.metrics::<4>()?),
+                "DWIDTH" => font_dwidth = Some(line.metrics::<1>()?[0]),
+                "FONT_ASCENT" => ascent = Some(line.metrics::<1>()?[0]),
+                "FONT_DESCENT" => descent = Some(line.metrics::<1>()?[0]),
+                "DEFAULT_CHAR" => default_char = Some(line.ints::<1>()?[0]),
+                "FAMILY_NAME" => family_name = Some(line.string()),
+                "STARTCHAR" => {
+                    let glyph_name = line.rest.clone();
+                    let (encoding, glyph) =
+                        BdfGlyph::read(&mut lines, font_dwidth)?;
+                    glyphs.push((encoding, glyph_name, glyph));
+                }
+                "ENDFONT" => break,
+                _ => {}
+            }
+        }
+        let (ascent, descent) = match (ascent, descent, bounding_box) {
+            (Some(ascent), Some(descent), _) => (ascent, descent),
+            (ascent, descent, Some([_, height, _, y_offset])) => (
+                ascent.unwrap_or(height + y_offset),
+                descent.unwrap_or(-y_offset),
+            ),
+            _ => {
+                let msg = "BDF file has no FONT_ASCENT/FONT_DESCENT \
+                           properties or FONTBOUNDINGBOX";
+                return Err(Error::new(ErrorKind::InvalidData, msg));
+            }
+        };
+        let height = max(0, ascent + descent) as u32;
+        let mut font = Font::with_glyph_height(height);
+        font.set_name(family_name.unwrap_or(name));
+        font.set_baseline(ascent as i32);
+        let mut has_default_glyph = false;
+        for (encoding, glyph_name, glyph) in glyphs {
+            let glyph = glyph.to_glyph(ascent, color);
+            if encoding < 0 {
+                if glyph_name == DEFAULT_GLYPH_NAME {
+                    font.set_default_glyph(glyph);
+                    has_default_glyph = true;
+                }
+                continue;
+            }
+            if !has_default_glyph && Some(encoding) == default_char {
+                font.set_default_glyph(glyph.clone());
+            }
+            match u32::try_from(encoding).ok().and_then(char::from_u32) {
+                Some(chr) => font.set_char_glyph(chr, glyph),
+                None => {
+                    let msg = format!("invalid BDF encoding: {}", encoding);
+                    return Err(Error::new(ErrorKind::InvalidData, msg));
+                }
+            }
+        }
+        Ok(font)
+    }
+
+    /// Writes the font to a BDF (Glyph Bitmap Distribution Format) file.
+    /// Since BDF glyphs are monochrome, every non-transparent pixel is
+    /// written as a set pixel.  Returns the keys of any glyphs that use more
+    /// than one non-transparent color (and so can't be represented exactly),
+    /// with the default glyph reported as `"def"`.
+    ///
+    /// The default glyph is written as an unencoded glyph named `.notdef`.
+    /// Multi-character glyph keys, the kerning table, the line spacing, and
+    /// palettes have no BDF equivalent, and are omitted.
+    #[allow(clippy::write_with_newline)]
+    pub fn write_bdf<W: Write>(
+        &self,
+        mut writer: W,
+    ) -> io::Result<BTreeSet<String>> {
+        let name = if self.name().is_empty() { "ahi" } else { self.name() };
+        if name.contains(['\n', '\r', '"']) {
+            let msg = format!("can't write {:?} as a BDF font name", name);
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        let glyphs: Vec<(Option<char>, &Glyph)> =
+            Some((None, self.default_glyph()))
+                .into_iter()
+                .chain(self.chars().map(|chr| (Some(chr), &self[chr])))
+                .collect();
+        let ascent = self.baseline();
+        let descent = self.glyph_height() as i32 - self.baseline();
+        let mut bounds: Option<(i32, i32, i32, i32)> = None;
+        for &(_, glyph) in glyphs.iter() {
+            let image = glyph.image();
+            if image.width() == 0 || image.height() == 0 {
+                continue;
+            }
+            let (x0, y0) = (-glyph.left_edge(), bbx_y_offset(glyph, ascent));
+            let x1 = x0 + image.width() as i32;
+            let y1 = y0 + image.height() as i32;
+            bounds = Some(match bounds {
+                None => (x0, y0, x1, y1),
+                Some((a0, b0, a1, b1)) => {
+                    (min(a0, x0), min(b0, y0), max(a1, x1), max(b1, y1))
+                }
+            });
+        }
+        let (x0, y0, x1, y1) = bounds.unwrap_or((0, 0, 0, 0));
+        write!(writer, "STARTFONT 2.1\n")?;
+        write!(writer, "FONT {}\n", name)?;
+        write!(writer, "SIZE {} 72 72\n", self.glyph_height())?;
+        write!(
+            writer,
+            "FONTBOUNDINGBOX {} {} {} {}\n",
+            x1 - x0,
+            y1 - y0,
+            x0,
+            y0
+        )?;
+        write!(writer, "STARTPROPERTIES 3\n")?;
+        write!(writer, "FAMILY_NAME \"{}\"\n", name)?;
+        write!(writer, "FONT_ASCENT {}\n", ascent)?;
+        write!(writer, "FONT_DESCENT {}\n", descent)?;
+        write!(writer, "ENDPROPERTIES\n")?;
+        write!(writer, "CHARS {}\n", glyphs.len())?;
+        let mut multicolor = BTreeSet::new();
+        for (chr, glyph) in glyphs {
+            let image = glyph.image();
+            match chr {
+                Some(chr) => {
+                    write!(writer, "STARTCHAR U+{:04X}\n", chr as u32)?;
+                    write!(writer, "ENCODING {}\n", chr as u32)?;
+                }
+                None => {
+                    write!(writer, "STARTCHAR {}\n", DEFAULT_GLYPH_NAME)?;
+                    write!(writer, "ENCODING -1\n")?;
+                }
+            }
+            let advance = glyph.right_edge() - glyph.left_edge();
+            let swidth = match self.glyph_height() {
+                0 => 0,
+                height => advance * 1000 / height as i32,
+            };
+            write!(writer, "SWIDTH {} 0\n", swidth)?;
+            write!(writer, "DWIDTH {} 0\n", advance)?;
+            write!(
+                writer,
+                "BBX {} {} {} {}\n",
+                image.width(),
+                image.height(),
+                -glyph.left_edge(),
+                bbx_y_offset(glyph, ascent)
+            )?;
+            write!(writer, "BITMAP\n")?;
+            let mut colors = BTreeSet::new();
+            // A zero-width bitmap has no hex digits on its rows, so we omit
+            // the (blank) row lines entirely.
+            let num_rows = if image.width() == 0 { 0 } else { image.height() };
+            for row in 0..num_rows {
+                let mut bytes = vec![0u8; image.width().div_ceil(8) as usize];
+                for col in 0..image.width() {
+                    let color = image[(col, row)];
+                    if color != Color::C0 {
+                        bytes[(col / 8) as usize] |= 0x80 >> (col % 8);
+                        colors.insert(color.to_byte());
+                    }
+                }
+                for byte in bytes {
+                    write!(writer, "{:02X}", byte)?;
+                }
+                write!(writer, "\n")?;
+            }
+            write!(writer, "ENDCHAR\n")?;
+            if colors.len() > 1 {
+                multicolor.insert(match chr {
+                    Some(chr) => chr.to_string(),
+                    None => DEFAULT_GLYPH_KEY.to_string(),
+                });
+            }
+        }
+        write!(writer, "ENDFONT\n")?;
+        Ok(multicolor)
+    }
+}
+
+/// Returns the BDF `BBX` y-offset for the given glyph: the distance from the
+/// baseline up to the bottom of the glyph's image.
+fn bbx_y_offset(glyph: &Glyph, ascent: i32) -> i32 {
+    ascent - glyph.top() - glyph.image().height() as i32
+}
+
+// ========================================================================= //
+
+/// A glyph as read from a BDF file, before being converted into a `Glyph`.
+struct BdfGlyph {
+    dwidth: i64,
+    bbx: [i64; 4],
+    rows: Vec<Vec<u8>>,
+}
+
+impl BdfGlyph {
+    /// Reads a glyph, starting just after its `STARTCHAR` line and ending
+    /// after its `ENDCHAR` line, and returns it along with its encoding.
+    fn read<R: Read>(
+        lines: &mut BdfLines<R>,
+        font_dwidth: Option<i64>,
+    ) -> io::Result<(i64, BdfGlyph)> {
+        let mut encoding = None;
+        let mut dwidth = font_dwidth;
+        let mut bbx = None;
+        loop {
+            let line = lines.next_line()?;
+            match line.keyword.as_str() {
+                "ENCODING" => encoding = Some(line.ints::<1>()?[0]),
+                "DWIDTH" => dwidth = Some(line.metrics::<1>()?[0]),
+                "BBX" => bbx = Some(line.metrics::<4>()?),
+                "BITMAP" => {
+                    let (encoding, dwidth, bbx) = match (encoding, dwidth, bbx)
+                    {
+                        (Some(e), Some(d), Some(b)) => (e, d, b),
+                        _ => {
+                            return Err(line.error(
+                                "glyph is missing ENCODING, DWIDTH, or BBX",
+                            ));
+                        }
+                    };
+                    if bbx[0] < 0 || bbx[1] < 0 {
+                        return Err(line.error("negative BBX size"));
+                    }
+                    let num_rows = if bbx[0] == 0 { 0 } else { bbx[1] };
+                    let mut rows = Vec::new();
+                    for _ in 0..num_rows {
+                        let line = lines.next_line()?;
+                        rows.push(line.hex_row(bbx[0] as usize)?);
+                    }
+                    let line = lines.next_line()?;
+                    if line.keyword != "ENDCHAR" {
+                        return Err(line.error("expected ENDCHAR"));
+                    }
+                    return Ok((encoding, BdfGlyph { dwidth, bbx, rows }));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Converts this into a `Glyph`.  The casts here can't overflow, since
+    /// `BdfLine::metrics` limits the magnitude of every value involved.
+    fn to_glyph(&self, ascent: i64, color: Color) -> Glyph {
+        let [width, height, x_offset, y_offset] = self.bbx;
+        let mut image = Image::new(width as u32, height as u32);
+        for (row, bytes) in self.rows.iter().enumerate() {
+            for col in 0..(width as usize) {
+                if bytes[col / 8] & (0x80 >> (col % 8)) != 0 {
+                    image[(col as u32, row as u32)] = color;
+                }
+            }
+        }
+        let left = -x_offset as i32;
+        let mut glyph = Glyph::new(image, left, left + self.dwidth as i32);
+        glyph.set_top((ascent - y_offset - height) as i32);
+        glyph
+    }
+}
+
+// ========================================================================= //
+
+/// Reads the non-blank, non-comment lines of a BDF file.
+struct BdfLines<R: Read> {
+    lines: io::Lines<BufReader<R>>,
+    number: usize,
+}
+
+impl<R: Read> BdfLines<R> {
+    fn new(reader: R) -> BdfLines<R> {
+        BdfLines { lines: BufReader::new(reader).lines(), number: 0 }
+    }
+
+    fn next_line(&mut self) -> io::Result<BdfLine> {
+        loop {
+            self.number += 1;
+            let line = match self.lines.next() {
+                Some(line) => line?,
+                None => {
+                    let msg = "unexpected end of BDF file";
+                    return Err(Error::new(ErrorKind::UnexpectedEof, msg));
+                }
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (keyword, rest) = match line.find(char::is_whitespace) {
+                Some(index) => (&line[..index], line[index..].trim_start()),
+                None => (line, ""),
+            };
+            if keyword == "COMMENT" {
+                continue;
+            }
+            return Ok(BdfLine {
+                number: self.number,
+                keyword: keyword.to_string(),
+                rest: rest.to_string(),
+            });
+        }
+    }
+}
+
+/// A single line of a BDF file, split into its keyword and the rest of the
+/// line.
+struct BdfLine {
+    number: usize,
+    keyword: String,
+    rest: String,
+}
+
+impl BdfLine {
+    fn error(&self, msg: &str) -> Error {
+        let msg = format!("line {}: {}", self.number, msg);
+        Error::new(ErrorKind::InvalidData, msg)
+    }
+
+    /// Parses the first `N` whitespace-separated integers on the line.
+    fn ints<const N: usize>(&self) -> io::Result<[i64; N]> {
+        let mut values = [0; N];
+        let mut words = self.rest.split_whitespace();
+        for value in values.iter_mut() {
+            *value =
+                words.next().and_then(|word| word.parse().ok()).ok_or_else(
+                    || self.error(&format!("expected {} integer(s)", N)),
+                )?;
+        }
+        Ok(values)
+    }
+
+    /// Parses the first `N` whitespace-separated integers on the line, each
+    /// of which must be no larger in magnitude than `MAX_BDF_VALUE`.
+    fn metrics<const N: usize>(&self) -> io::Result<[i64; N]> {
+        let values = self.ints::<N>()?;
+        let range = -MAX_BDF_VALUE..=MAX_BDF_VALUE;
+        if let Some(value) = values.iter().find(|value| !range.contains(value))
+        {
+            return Err(self.error(&format!("value out of range: {}", value)));
+        }
+        Ok(values)
+    }
+
+    /// Returns the rest of the line as a string, removing surrounding double
+    /// quotes (if any).
+    fn string(&self) -> String {
+        let rest = self.rest.as_str();
+        match rest.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+            Some(inner) => inner.replace("\"\"", "\""),
+            None => rest.to_string(),
+        }
+    }
+
+    /// Parses this line as a row of bitmap data at least `width` pixels
+    /// wide.
+    fn hex_row(&self, width: usize) -> io::Result<Vec<u8>> {
+        let line = format!("{}{}", self.keyword, self.rest);
+        let num_bytes = width.div_ceil(8);
+        if line.len() < 2 * num_bytes {
+            return Err(self.error("bitmap row is too short"));
+        }
+        (0..num_bytes)
+            .map(|index| {
+                let digits = line.get((2 * index)..(2 * index + 2));
+                digits
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .ok_or_else(|| self.error("invalid hex in bitmap row"))
+            })
+            .collect()
+    }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BDF: &str = "STARTFONT 2.1\n\
+        FONT Tiny\n\
+        SIZE 4 72 72\n\
+        FONTBOUNDINGBOX 9 4 0 -1\n\
+        STARTPROPERTIES 3\n\
+        FAMILY_NAME \"Tiny\"\n\
+        FONT_ASCENT 3\n\
+        FONT_DESCENT 1\n\
+        ENDPROPERTIES\n\
+        CHARS 3\n\
+        STARTCHAR .notdef\n\
+        ENCODING -1\n\
+        SWIDTH 750 0\n\
+        DWIDTH 3 0\n\
+        BBX 2 3 0 0\n\
+        BITMAP\n\
+        C0\n\
+        C0\n\
+        C0\n\
+        ENDCHAR\n\
+        STARTCHAR U+002C\n\
+        ENCODING 44\n\
+        SWIDTH 500 0\n\
+        DWIDTH 2 0\n\
+        BBX 1 2 0 -1\n\
+        BITMAP\n\
+        80\n\
+        80\n\
+        ENDCHAR\n\
+        STARTCHAR U+0057\n\
+        ENCODING 87\n\
+        SWIDTH 2500 0\n\
+        DWIDTH 10 0\n\
+        BBX 9 3 0 0\n\
+        BITMAP\n\
+        8880\n\
+        5500\n\
+        2200\n\
+        ENDCHAR\n\
+        ENDFONT\n";
+
+    #[test]
+    fn read_bdf() {
+        let font = Font::read_bdf(BDF.as_bytes(), Color::C5).unwrap();
+        assert_eq!(font.name(), "Tiny");
+        assert_eq!(font.glyph_height(), 4);
+        assert_eq!(font.baseline(), 3);
+        assert_eq!(font.chars().collect::<String>(), ",W");
+        assert_eq!(font.default_glyph().right_edge(), 3);
+        assert_eq!(font[','].top(), 2);
+        assert_eq!(font[','].image()[(0, 1)], Color::C5);
+        assert_eq!(font['W'].image().width(), 9);
+        assert_eq!(font['W'].image()[(8, 0)], Color::C5);
+        assert_eq!(font['W'].image()[(7, 0)], Color::C0);
+        assert_eq!(font['W'].right_edge(), 10);
+    }
+
+    #[test]
+    fn bdf_round_trip() {
+        let font = Font::read_bdf(BDF.as_bytes(), Color::C1).unwrap();
+        let mut output = Vec::<u8>::new();
+        let multicolor = font.write_bdf(&mut output).unwrap();
+        assert!(multicolor.is_empty());
+        assert_eq!(String::from_utf8(output).unwrap(), BDF);
+    }
+
+    #[test]
+    fn write_bdf_reports_multicolor_glyphs() {
+        let mut font = Font::with_glyph_height(2);
+        let mut image = Image::new(2, 2);
+        image[(0, 0)] = Color::C2;
+        image[(1, 1)] = Color::C3;
+        font.set_char_glyph('x', Glyph::new(image, -1, 2));
+        let mut image = Image::new(1, 2);
+        image.fill_rect(0, 0, 1, 2, Color::C4);
+        font.set_char_glyph('l', Glyph::new(image, 0, 2));
+        let mut output = Vec::<u8>::new();
+        let multicolor = font.write_bdf(&mut output).unwrap();
+        assert_eq!(multicolor.into_iter().collect::<Vec<_>>(), vec!["x"]);
+        let copy = Font::read_bdf(&output as &[u8], Color::C7).unwrap();
+        assert_eq!(copy.name(), "ahi");
+        assert_eq!(copy['x'].left_edge(), -1);
+        assert_eq!(copy['x'].image()[(1, 1)], Color::C7);
+        assert_eq!(copy['x'].image()[(1, 0)], Color::C0);
+    }
+
+    #[test]
+    fn read_bdf_with_default_char_property() {
+        let input = "STARTFONT 2.1\n\
+            COMMENT hand-written\n\
+            FONT -misc-fixed-medium-r-normal--2-20-75-75-c-20-iso10646-1\n\
+            FONTBOUNDINGBOX 1 2 0 0\n\
+            STARTPROPERTIES 1\n\
+            DEFAULT_CHAR 63\n\
+            ENDPROPERTIES\n\
+            CHARS 1\n\
+            STARTCHAR question\n\
+            ENCODING 63\n\
+            DWIDTH 2 0\n\
+            BBX 1 2 0 0\n\
+            BITMAP\n\
+            80\n\
+            00\n\
+            ENDCHAR\n\
+            ENDFONT\n";
+        let font = Font::read_bdf(input.as_bytes(), Color::C1).unwrap();
+        assert!(font.name().starts_with("-misc-fixed"));
+        assert_eq!((font.glyph_height(), font.baseline()), (2, 2));
+        assert_eq!(font.default_glyph().image()[(0, 0)], Color::C1);
+        assert_eq!(font['?'].right_edge(), 2);
+    }
+
+    #[test]
+    fn read_invalid_bdf() {
+        let input = "STARTFONT 2.1\n\
+            FONTBOUNDINGBOX 1 1 0 0\n\
+            STARTCHAR A\n\
+            ENCODING 65\n\
+            DWIDTH 1 0\n\
+            BBX 1 1 0 0\n\
+            BITMAP\n\
+            ZZ\n\
+            ENDCHAR\n\
+            ENDFONT\n";
+        let error = Font::read_bdf(input.as_bytes(), Color::C1).err().unwrap();
+        assert_eq!(error.to_string(), "line 8: invalid hex in bitmap row");
+        let error = Font::read_bdf(&b"STARTFONT 2.1\n"[..], Color::C1);
+        assert_eq!(error.err().unwrap().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn read_bdf_with_out_of_range_values() {
+        let input = "STARTFONT 2.1\n\
+            FONTBOUNDINGBOX 1 1 0 0\n\
+            STARTCHAR A\n\
+            ENCODING 65\n\
+            DWIDTH 1 0\n\
+            BBX 1 4294967296 0 0\n\
+            BITMAP\n\
+            ENDCHAR\n\
+            ENDFONT\n";
+        let error = Font::read_bdf(input.as_bytes(), Color::C1).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "line 6: value out of range: 4294967296"
+        );
+        let input = "STARTFONT 2.1\n\
+            FONT_ASCENT 2147483647\n\
+            FONT_DESCENT 2147483647\n\
+            ENDFONT\n";
+        let error = Font::read_bdf(input.as_bytes(), Color::C1).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "line 2: value out of range: 2147483647"
+        );
+        let input = "STARTFONT 2.1\n\
+            FONTBOUNDINGBOX 1 1 0 0\n\
+            STARTCHAR A\n\
+            ENCODING 65\n\
+            DWIDTH -9223372036854775808 0\n\
+            ENDFONT\n";
+        let error = Font::read_bdf(input.as_bytes(), Color::C1).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "line 5: value out of range: -9223372036854775808"
+        );
+    }
+}
+
+// ========================================================================= //
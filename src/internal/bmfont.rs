@@ -0,0 +1,493 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use crate::internal::font::{Font, Glyph};
+use crate::internal::image::Image;
+use std::cmp::max;
+use std::convert::TryFrom;
+use std::io::{self, BufRead, BufReader, Error, ErrorKind, Read, Write};
+
+// ========================================================================= //
+
+/// The char id that BMFont uses for the glyph drawn for missing characters.
+const INVALID_CHAR_ID: i64 = -1;
+
+/// The number of transparent pixels left between glyphs in the atlas.
+const ATLAS_SPACING: u32 = 1;
+
+// ========================================================================= //
+
+impl Font {
+    /// Writes this font as an AngelCode BMFont text descriptor (`.fnt` file),
+    /// and returns the atlas image that the descriptor refers to.  Every
+    /// character glyph is packed into the atlas, which is described as a
+    /// single page stored in `page_file` (typically an AHI file containing
+    /// just the returned image).  The default glyph is written with char id
+    /// -1, which BMFont uses for missing characters.  Multi-character glyph
+    /// keys have no BMFont equivalent, and are omitted.
+    ///
+    /// Each glyph's `xoffset` is the negation of its left edge, its
+    /// `xadvance` is the distance from its left edge to its right edge, and
+    /// its `yoffset` is its top offset.  The font's baseline becomes `base`,
+    /// its line spacing becomes `lineHeight`, and its glyph height becomes
+    /// the `size` field.
+    ///
+    /// Returns an error if the font name or `page_file` contains a double
+    /// quote or newline, which the format can't represent.
+    #[allow(clippy::write_with_newline)]
+    pub fn write_bmfont<W: Write>(
+        &self,
+        mut writer: W,
+        page_file: &str,
+    ) -> io::Result<Image> {
+        check_quotable(self.name())?;
+        check_quotable(page_file)?;
+        let glyphs: Vec<(i64, &Glyph)> =
+            Some((INVALID_CHAR_ID, self.default_glyph()))
+                .into_iter()
+                .chain(self.chars().map(|chr| (chr as i64, &self[chr])))
+                .collect();
+        let (atlas, positions) = pack_atlas(&glyphs);
+        write!(
+            writer,
+            "info face=\"{}\" size={} bold=0 italic=0 charset=\"\" \
+             unicode=1 stretchH=100 smooth=0 aa=1 padding=0,0,0,0 \
+             spacing={},{}\n",
+            self.name(),
+            self.glyph_height(),
+            ATLAS_SPACING,
+            ATLAS_SPACING
+        )?;
+        write!(
+            writer,
+            "common lineHeight={} base={} scaleW={} scaleH={} pages=1 \
+             packed=0\n",
+            self.line_spacing(),
+            self.baseline(),
+            atlas.width(),
+            atlas.height()
+        )?;
+        write!(writer, "page id=0 file=\"{}\"\n", page_file)?;
+        write!(writer, "chars count={}\n", glyphs.len())?;
+        for (&(id, glyph), &(x, y)) in glyphs.iter().zip(positions.iter()) {
+            write!(
+                writer,
+                "char id={} x={} y={} width={} height={} xoffset={} \
+                 yoffset={} xadvance={} page=0 chnl=15\n",
+                id,
+                x,
+                y,
+                glyph.image().width(),
+                glyph.image().height(),
+                -glyph.left_edge(),
+                glyph.top(),
+                glyph.right_edge() - glyph.left_edge()
+            )?;
+        }
+        let kerning: Vec<((char, char), i32)> = self.kerning_pairs().collect();
+        if !kerning.is_empty() {
+            write!(writer, "kernings count={}\n", kerning.len())?;
+            for ((first, second), amount) in kerning {
+                write!(
+                    writer,
+                    "kerning first={} second={} amount={}\n",
+                    first as u32, second as u32, amount
+                )?;
+            }
+        }
+        Ok(atlas)
+    }
+
+    /// Reads a font from an AngelCode BMFont text descriptor (`.fnt` file),
+    /// taking glyph images from the given pages (indexed by BMFont page id,
+    /// e.g. the images of an AHI collection).  This is the inverse of
+    /// `write_bmfont`.  If the descriptor has no glyph with char id -1, the
+    /// font's default glyph is a zero-width space.  Channel and padding
+    /// information is ignored.
+    pub fn read_bmfont<R: Read>(
+        reader: R,
+        pages: &[Image],
+    ) -> io::Result<Font> {
+        let mut font = Font::with_glyph_height(0);
+        let mut size = None;
+        let mut line_height = None;
+        let mut has_default_glyph = false;
+        for (index, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let line = BmfLine::parse(index + 1, &line)?;
+            match line.tag.as_str() {
+                "info" => {
+                    if let Some(name) = line.get("face") {
+                        font.set_name(name);
+                    }
+                    if line.get("size").is_some() {
+                        size = Some(line.i32("size")?.unsigned_abs());
+                    }
+                }
+                "common" => {
+                    line_height = Some(line.uint("lineHeight")?);
+                    font.set_baseline(line.i32("base")?);
+                }
+                "char" => {
+                    let id = line.int("id")?;
+                    let page = line.uint("page")? as usize;
+                    let page = pages.get(page).ok_or_else(|| {
+                        line.error(format!("no such page: {}", page))
+                    })?;
+                    let (x, y) = (line.uint("x")?, line.uint("y")?);
+                    let width = line.uint("width")?;
+                    let height = line.uint("height")?;
+                    if x as u64 + width as u64 > page.width() as u64
+                        || y as u64 + height as u64 > page.height() as u64
+                    {
+                        return Err(line.error("glyph lies outside its page"));
+                    }
+                    let mut image = Image::new(width, height);
+                    image.draw(page, -(x as i32), -(y as i32));
+                    let xoffset = line.i32("xoffset")?;
+                    let xadvance = line.i32("xadvance")?;
+                    let out_of_range =
+                        || line.error("glyph edges are out of range");
+                    let left =
+                        xoffset.checked_neg().ok_or_else(out_of_range)?;
+                    let right =
+                        left.checked_add(xadvance).ok_or_else(out_of_range)?;
+                    let mut glyph = Glyph::new(image, left, right);
+                    glyph.set_top(line.i32("yoffset")?);
+                    if id == INVALID_CHAR_ID {
+                        font.set_default_glyph(glyph);
+                        has_default_glyph = true;
+                    } else {
+                        font.set_char_glyph(line.char("id")?, glyph);
+                    }
+                }
+                "kerning" => {
+                    let first = line.char("first")?;
+                    let second = line.char("second")?;
+                    let amount = line.i32("amount")?;
+                    font.set_kerning(first, second, amount);
+                }
+                _ => {}
+            }
+        }
+        let line_height = match line_height {
+            Some(line_height) => line_height,
+            None => {
+                let msg = "BMFont file has no \"common\" line";
+                return Err(Error::new(ErrorKind::InvalidData, msg));
+            }
+        };
+        font.set_glyph_height(size.unwrap_or(line_height));
+        font.set_line_spacing(line_height);
+        if !has_default_glyph {
+            let height = font.glyph_height();
+            font.set_default_glyph(Glyph::new(Image::new(0, height), 0, 0));
+        }
+        Ok(font)
+    }
+}
+
+// ========================================================================= //
+
+/// Packs the images of the given glyphs into a single atlas image, returning
+/// the atlas along with the position of each glyph's image within it.  Images
+/// are placed in rows from tallest to shortest, with the atlas width chosen
+/// to make it roughly square.
+fn pack_atlas(glyphs: &[(i64, &Glyph)]) -> (Image, Vec<(u32, u32)>) {
+    let area: u64 = glyphs
+        .iter()
+        .map(|(_, glyph)| {
+            let image = glyph.image();
+            (image.width() as u64 + ATLAS_SPACING as u64)
+                * (image.height() as u64 + ATLAS_SPACING as u64)
+        })
+        .sum();
+    let max_width =
+        glyphs.iter().map(|(_, glyph)| glyph.image().width()).fold(0, max);
+    let atlas_width = max(max_width, (area as f64).sqrt().ceil() as u32);
+    let mut order: Vec<usize> = (0..glyphs.len()).collect();
+    order.sort_by_key(|&index| {
+        std::cmp::Reverse(glyphs[index].1.image().height())
+    });
+    let mut positions = vec![(0, 0); glyphs.len()];
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    for index in order {
+        let image = glyphs[index].1.image();
+        if image.width() == 0 || image.height() == 0 {
+            continue;
+        }
+        if x > 0 && x + image.width() > atlas_width {
+            x = 0;
+            y += row_height + ATLAS_SPACING;
+            row_height = 0;
+        }
+        positions[index] = (x, y);
+        x += image.width() + ATLAS_SPACING;
+        row_height = max(row_height, image.height());
+    }
+    let mut atlas = Image::new(atlas_width, y + row_height);
+    for ((_, glyph), &(x, y)) in glyphs.iter().zip(positions.iter()) {
+        atlas.draw(glyph.image(), x as i32, y as i32);
+    }
+    (atlas, positions)
+}
+
+fn check_quotable(value: &str) -> io::Result<()> {
+    if value.contains(['"', '\n', '\r']) {
+        let msg = format!("can't write {:?} as a BMFont string", value);
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
+    }
+    Ok(())
+}
+
+// ========================================================================= //
+
+/// A single parsed line of a BMFont text descriptor, consisting of a tag
+/// followed by `key=value` pairs.
+struct BmfLine {
+    number: usize,
+    tag: String,
+    fields: Vec<(String, String)>,
+}
+
+impl BmfLine {
+    fn parse(number: usize, line: &str) -> io::Result<BmfLine> {
+        let mut rest = line.trim_start();
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let tag = rest[..end].to_string();
+        rest = rest[end..].trim_start();
+        let mut fields = Vec::new();
+        while !rest.is_empty() {
+            let equals = match rest.find('=') {
+                Some(equals) => equals,
+                None => {
+                    let msg = format!("line {}: expected '='", number);
+                    return Err(Error::new(ErrorKind::InvalidData, msg));
+                }
+            };
+            let key = rest[..equals].to_string();
+            rest = &rest[(equals + 1)..];
+            let value = if let Some(quoted) = rest.strip_prefix('"') {
+                let end = match quoted.find('"') {
+                    Some(end) => end,
+                    None => {
+                        let msg =
+                            format!("line {}: unterminated string", number);
+                        return Err(Error::new(ErrorKind::InvalidData, msg));
+                    }
+                };
+                rest = &quoted[(end + 1)..];
+                quoted[..end].to_string()
+            } else {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                let value = rest[..end].to_string();
+                rest = &rest[end..];
+                value
+            };
+            fields.push((key, value));
+            rest = rest.trim_start();
+        }
+        Ok(BmfLine { number, tag, fields })
+    }
+
+    fn error<S: AsRef<str>>(&self, msg: S) -> Error {
+        let msg = format!("line {}: {}", self.number, msg.as_ref());
+        Error::new(ErrorKind::InvalidData, msg)
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(other, _)| other == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn int(&self, key: &str) -> io::Result<i64> {
+        let value = self.get(key).ok_or_else(|| {
+            self.error(format!(
+                "missing {:?} field on {:?} line",
+                key, self.tag
+            ))
+        })?;
+        value.parse().map_err(|_| {
+            self.error(format!("invalid value for {:?}: {:?}", key, value))
+        })
+    }
+
+    fn i32(&self, key: &str) -> io::Result<i32> {
+        let value = self.int(key)?;
+        i32::try_from(value).map_err(|_| {
+            let msg =
+                format!("value for {:?} is out of range: {}", key, value);
+            self.error(msg)
+        })
+    }
+
+    fn uint(&self, key: &str) -> io::Result<u32> {
+        let value = self.int(key)?;
+        if value < 0 || value > (u32::MAX as i64) {
+            let msg =
+                format!("value for {:?} is out of range: {}", key, value);
+            return Err(self.error(msg));
+        }
+        Ok(value as u32)
+    }
+
+    fn char(&self, key: &str) -> io::Result<char> {
+        let value = self.int(key)?;
+        u32::try_from(value).ok().and_then(char::from_u32).ok_or_else(|| {
+            self.error(format!("invalid char id for {:?}: {}", key, value))
+        })
+    }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::color::Color;
+    use crate::internal::testutil::{font_from_rows, GlyphRows};
+
+    const GLYPHS: &[GlyphRows] = &[
+        ("def", 0, 3, &["11", "11", "11"]),
+        ("i", 0, 2, &["2", "2"]),
+        (".", -1, 1, &["3"]),
+        (" ", 0, 2, &["", "", ""]),
+    ];
+
+    #[test]
+    fn write_bmfont() {
+        let mut font = font_from_rows(3, 2, GLYPHS);
+        font.set_name("Tiny");
+        font.set_line_spacing(4);
+        font['.'].set_top(1);
+        font.set_kerning('i', '.', -1);
+        let mut output = Vec::<u8>::new();
+        let atlas = font.write_bmfont(&mut output, "tiny.ahi").unwrap();
+        let expected = "info face=\"Tiny\" size=3 bold=0 italic=0 \
+            charset=\"\" unicode=1 stretchH=100 smooth=0 aa=1 \
+            padding=0,0,0,0 spacing=1,1\n\
+            common lineHeight=4 base=2 scaleW=6 scaleH=3 pages=1 packed=0\n\
+            page id=0 file=\"tiny.ahi\"\n\
+            chars count=4\n\
+            char id=-1 x=0 y=0 width=2 height=3 xoffset=0 yoffset=0 \
+            xadvance=3 page=0 chnl=15\n\
+            char id=32 x=0 y=0 width=0 height=3 xoffset=0 yoffset=0 \
+            xadvance=2 page=0 chnl=15\n\
+            char id=46 x=5 y=0 width=1 height=1 xoffset=1 yoffset=1 \
+            xadvance=2 page=0 chnl=15\n\
+            char id=105 x=3 y=0 width=1 height=2 xoffset=0 yoffset=0 \
+            xadvance=2 page=0 chnl=15\n\
+            kernings count=1\n\
+            kerning first=105 second=46 amount=-1\n";
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+        assert_eq!((atlas.width(), atlas.height()), (6, 3));
+        assert_eq!(atlas[(1, 2)], Color::C1);
+        assert_eq!(atlas[(3, 1)], Color::C2);
+        assert_eq!(atlas[(5, 0)], Color::C3);
+    }
+
+    #[test]
+    fn bmfont_round_trip() {
+        let mut font = font_from_rows(3, 2, GLYPHS);
+        font.set_name("Tiny");
+        font.set_line_spacing(4);
+        font['.'].set_top(1);
+        font.set_kerning('i', '.', -1);
+        let mut output = Vec::<u8>::new();
+        let atlas = font.write_bmfont(&mut output, "tiny.ahi").unwrap();
+        let copy = Font::read_bmfont(&output as &[u8], &[atlas]).unwrap();
+        assert_eq!(copy.name(), "Tiny");
+        assert_eq!(copy.glyph_height(), 3);
+        assert_eq!(copy.baseline(), 2);
+        assert_eq!(copy.line_spacing(), 4);
+        assert_eq!(copy.chars().collect::<String>(), " .i");
+        assert_eq!(copy.get_kerning('i', '.'), -1);
+        for chr in font.chars() {
+            assert_eq!(copy[chr].left_edge(), font[chr].left_edge());
+            assert_eq!(copy[chr].right_edge(), font[chr].right_edge());
+            assert_eq!(copy[chr].top(), font[chr].top());
+        }
+        assert_eq!(copy.render("i.?").width(), font.render("i.?").width());
+        assert_eq!(copy['.'].image()[(0, 0)], Color::C3);
+        assert_eq!(copy.default_glyph().image()[(1, 2)], Color::C1);
+    }
+
+    #[test]
+    fn read_bmfont_without_default_glyph() {
+        let input: &[u8] = b"info face=\"Hand Made\" size=-2\n\
+            common lineHeight=3 base=2 scaleW=2 scaleH=2 pages=1\n\
+            page id=0 file=\"atlas.ahi\"\n\
+            chars count=1\n\
+            char id=65   x=1 y=0 width=1 height=2 xoffset=1 yoffset=0 \
+            xadvance=3 page=0 chnl=15\n";
+        let mut page = Image::new(2, 2);
+        page[(1, 1)] = Color::C5;
+        let font = Font::read_bmfont(input, &[page]).unwrap();
+        assert_eq!(font.name(), "Hand Made");
+        assert_eq!(font.glyph_height(), 2);
+        assert_eq!(font.line_spacing(), 3);
+        assert_eq!(font.default_glyph().image().height(), 2);
+        assert_eq!(font['A'].left_edge(), -1);
+        assert_eq!(font['A'].right_edge(), 2);
+        assert_eq!(font['A'].image()[(0, 1)], Color::C5);
+    }
+
+    #[test]
+    fn read_invalid_bmfont() {
+        let pages = vec![Image::new(2, 2)];
+        let input: &[u8] = b"common lineHeight=3 base=2\n\
+            char id=65 x=1 y=0 width=2 height=2 xoffset=0 yoffset=0 \
+            xadvance=3 page=0\n";
+        let error = Font::read_bmfont(input, &pages).err().unwrap();
+        assert_eq!(error.to_string(), "line 2: glyph lies outside its page");
+        let input: &[u8] = b"common lineHeight=3\n";
+        let error = Font::read_bmfont(input, &pages).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "line 1: missing \"base\" field on \"common\" line"
+        );
+        let input: &[u8] = b"info face=\"Unterminated\n";
+        assert!(Font::read_bmfont(input, &pages).is_err());
+        let input: &[u8] = b"common lineHeight=3 base=4294967298\n";
+        let error = Font::read_bmfont(input, &pages).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "line 1: value for \"base\" is out of range: 4294967298"
+        );
+        let input: &[u8] = b"common lineHeight=3 base=2\n\
+            char id=65 x=0 y=0 width=1 height=1 xoffset=-2147483648 \
+            yoffset=0 xadvance=3 page=0\n";
+        let error = Font::read_bmfont(input, &pages).err().unwrap();
+        assert_eq!(error.to_string(), "line 2: glyph edges are out of range");
+    }
+
+    #[test]
+    fn write_bmfont_with_unquotable_name() {
+        let mut font = font_from_rows(3, 2, GLYPHS);
+        font.set_name("\"Tiny\"");
+        let mut output = Vec::<u8>::new();
+        let error = font.write_bmfont(&mut output, "tiny.ahi").err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(output.is_empty());
+    }
+}
+
+// ========================================================================= //
@@ -17,18 +17,22 @@
 // | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
 // +--------------------------------------------------------------------------+
 
+use crate::internal::color::Color;
+use crate::internal::error::ParseErrorKind;
 use crate::internal::image::Image;
+use crate::internal::options::{ReadOptions, WriteOptions};
 use crate::internal::palette::Palette;
 use crate::internal::util::{
-    read_exactly, read_header_uint, read_hex_u32, read_list_of_i16s,
-    read_quoted_string,
+    read_exactly, read_u16, write_comment, write_list_of_i16s,
+    write_quoted_string, write_u16, TextReader,
 };
 use std::io::{self, Error, ErrorKind, Read, Write};
 
 // ========================================================================= //
 
-// TODO: Support BHI format, which is a binary encoding of an AHI file, with
-// compressed image data.
+// The BHI format is a binary encoding of an AHI file, with compressed
+// image data.  All multi-byte integers are big-endian.  Image pixels are
+// packed two to a byte (first pixel in the high nibble), in row-major order.
 //
 // Header:
 // +------------+---------+-------+--------------+------------+
@@ -52,6 +56,12 @@ use std::io::{self, Error, ErrorKind, Read, Write};
 // +-----------+
 //
 // Image (repeated num_images times):
+// Comment (if flag 8 is set):
+// +--------+--------------+
+// | u16    | u8 x length  |
+// +--------+--------------+
+// | length | utf-8        |
+// +--------+--------------+
 // Tag (if flag 2 is set):
 // +--------+--------------+
 // | u16    | u8 x length  |
@@ -77,9 +87,51 @@ use std::io::{self, Error, ErrorKind, Read, Write};
 // | image data                    |
 // +-------------------------------+
 
+const BHI_MAGIC: &[u8] = b"\x1Bbhi";
+
+const MAX_AHI_VERSION: u32 = 1;
+
 const FLAG_INDIVIDUAL_DIMENSIONS: u32 = 1;
 const FLAG_STRING_TAGS: u32 = 2;
 const FLAG_METADATA_INTS: u32 = 4;
+const ALL_FLAGS: u32 =
+    FLAG_INDIVIDUAL_DIMENSIONS | FLAG_STRING_TAGS | FLAG_METADATA_INTS;
+
+/// BHI-only flag indicating that each image has a (possibly empty) comment.
+/// AHI files store comments as `#` lines instead, so this is not an AHI
+/// flag.
+const BHI_FLAG_COMMENTS: u32 = 8;
+
+// ========================================================================= //
+
+/// Describes the format of an AHI or BHI file that was read.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FormatInfo {
+    version: u32,
+    flags: u32,
+    binary: bool,
+}
+
+impl FormatInfo {
+    /// Returns the format version of the file.  For BHI files, this is the
+    /// BHI version rather than an AHI version.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns the format flags of the file (a combination of the
+    /// `Collection::FLAG_*` constants).  This is always zero for version 0
+    /// AHI files.
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Returns true if the file was in the binary BHI encoding, or false if
+    /// it was in the text AHI encoding.
+    pub fn is_binary(&self) -> bool {
+        self.binary
+    }
+}
 
 // ========================================================================= //
 
@@ -92,99 +144,250 @@ pub struct Collection {
 }
 
 impl Collection {
+    /// AHI format flag indicating that each image has its own dimensions,
+    /// rather than all images sharing the same width and height.
+    pub const FLAG_INDIVIDUAL_DIMENSIONS: u32 = FLAG_INDIVIDUAL_DIMENSIONS;
+    /// AHI format flag indicating that each image has a string tag.
+    pub const FLAG_STRING_TAGS: u32 = FLAG_STRING_TAGS;
+    /// AHI format flag indicating that each image has a list of integer
+    /// metadata.
+    pub const FLAG_METADATA_INTS: u32 = FLAG_METADATA_INTS;
+
     /// Returns a new, empty collection.
     pub fn new() -> Collection {
         Collection { palettes: Vec::new(), images: Vec::new() }
     }
 
-    /// Reads a collection from an AHI file.
-    pub fn read<R: Read>(mut reader: R) -> io::Result<Collection> {
-        read_exactly(reader.by_ref(), b"ahi")?;
-        let version = read_header_uint(reader.by_ref(), b' ')?;
+    /// Reads a collection from an AHI or BHI file, automatically detecting
+    /// which encoding is used.
+    pub fn read<R: Read>(reader: R) -> io::Result<Collection> {
+        Collection::read_with_options(reader, &ReadOptions::new())
+    }
+
+    /// Reads a collection from an AHI or BHI file, using the given options
+    /// for parsing AHI data.
+    pub fn read_with_options<R: Read>(
+        reader: R,
+        options: &ReadOptions,
+    ) -> io::Result<Collection> {
+        Ok(Collection::read_with_format(reader, options)?.0)
+    }
+
+    /// Reads a collection from an AHI or BHI file, using the given options
+    /// for parsing AHI data, and also returns the format version and flags
+    /// that the file used.  This is useful for writing the collection back
+    /// out in the same format (see `WriteOptions::from_format`).
+    pub fn read_with_format<R: Read>(
+        mut reader: R,
+        options: &ReadOptions,
+    ) -> io::Result<(Collection, FormatInfo)> {
+        let mut first = [0u8];
+        reader.read_exact(&mut first)?;
+        let reader = (&first as &[u8]).chain(reader);
+        if first[0] == BHI_MAGIC[0] {
+            Collection::read_bhi_with_format(reader)
+        } else {
+            Collection::read_ahi(reader, options)
+        }
+    }
+
+    fn read_ahi<R: Read>(
+        reader: R,
+        options: &ReadOptions,
+    ) -> io::Result<(Collection, FormatInfo)> {
+        let mut reader = TextReader::with_options(reader, options);
+        reader.read_exactly(b"ahi")?;
+        let version = reader.read_header_uint(b' ')?;
         if version != 0 && version != 1 {
-            let msg = format!("unsupported AHI version: {}", version);
-            return Err(Error::new(ErrorKind::InvalidData, msg));
+            let kind = ParseErrorKind::UnsupportedVersion(version);
+            return Err(reader.error(kind));
         }
         let flags = if version == 1 {
-            read_exactly(reader.by_ref(), b"f")?;
-            read_hex_u32(reader.by_ref(), b' ')?
+            reader.read_exactly(b"f")?;
+            reader.read_hex_u32(b' ')?
         } else {
             0
         };
         let num_palettes = if version == 1 {
-            read_exactly(reader.by_ref(), b"p")?;
-            read_header_uint(reader.by_ref(), b' ')? as usize
+            reader.read_exactly(b"p")?;
+            reader.read_header_uint(b' ')? as usize
         } else {
             0
         };
         let (num_images, global_width, global_height) = if version == 0 {
-            read_exactly(reader.by_ref(), b"w")?;
-            let width = read_header_uint(reader.by_ref(), b' ')?;
-            read_exactly(reader.by_ref(), b"h")?;
-            let height = read_header_uint(reader.by_ref(), b' ')?;
-            read_exactly(reader.by_ref(), b"n")?;
-            let num_images = read_header_uint(reader.by_ref(), b'\n')?;
+            reader.read_exactly(b"w")?;
+            let width = reader.read_header_uint(b' ')?;
+            reader.read_exactly(b"h")?;
+            let height = reader.read_header_uint(b' ')?;
+            reader.read_exactly(b"n")?;
+            let num_images = reader.read_header_uint(b'\n')?;
             (num_images as usize, width, height)
         } else if flags & FLAG_INDIVIDUAL_DIMENSIONS != 0 {
-            read_exactly(reader.by_ref(), b"i")?;
-            let num_images = read_header_uint(reader.by_ref(), b'\n')?;
+            reader.read_exactly(b"i")?;
+            let num_images = reader.read_header_uint(b'\n')?;
             (num_images as usize, 0, 0)
         } else {
-            read_exactly(reader.by_ref(), b"i")?;
-            let num_images = read_header_uint(reader.by_ref(), b' ')?;
-            read_exactly(reader.by_ref(), b"w")?;
-            let width = read_header_uint(reader.by_ref(), b' ')?;
-            read_exactly(reader.by_ref(), b"h")?;
-            let height = read_header_uint(reader.by_ref(), b'\n')?;
+            reader.read_exactly(b"i")?;
+            let num_images = reader.read_header_uint(b' ')?;
+            reader.read_exactly(b"w")?;
+            let width = reader.read_header_uint(b' ')?;
+            reader.read_exactly(b"h")?;
+            let height = reader.read_header_uint(b'\n')?;
             (num_images as usize, width, height)
         };
 
         let mut palettes = Vec::with_capacity(num_palettes);
         if num_palettes > 0 {
-            read_exactly(reader.by_ref(), b"\n")?;
+            reader.read_newline()?;
+        }
+        for index in 0..num_palettes {
+            reader.set_item("palette", index);
+            palettes.push(Palette::read(&mut reader)?);
+        }
+
+        let mut images = Vec::with_capacity(num_images);
+        for index in 0..num_images {
+            reader.set_item("image", index);
+            reader.read_newline()?;
+            let comment = if version == 1 {
+                reader.read_comments()?
+            } else {
+                String::new()
+            };
+            let tag = if flags & FLAG_STRING_TAGS != 0 {
+                let tag = reader.read_quoted_string()?;
+                reader.read_newline()?;
+                tag
+            } else {
+                String::new()
+            };
+            let metadata = if flags & FLAG_METADATA_INTS != 0 {
+                let metadata = reader.read_list_of_i16s()?;
+                reader.read_newline()?;
+                metadata
+            } else {
+                Vec::new()
+            };
+            let (width, height) = if flags & FLAG_INDIVIDUAL_DIMENSIONS != 0 {
+                reader.read_exactly(b"w")?;
+                let width = reader.read_header_uint(b' ')?;
+                reader.read_exactly(b"h")?;
+                let height = reader.read_header_uint(b'\n')?;
+                (width, height)
+            } else {
+                (global_width, global_height)
+            };
+            let mut image = Image::read(&mut reader, width, height)?;
+            image.set_comment(comment);
+            image.set_tag(tag);
+            image.set_metadata(metadata);
+            images.push(image);
+        }
+
+        let format = FormatInfo { version, flags, binary: false };
+        Ok((Collection { palettes, images }, format))
+    }
+
+    /// Reads a collection from a BHI file.
+    pub fn read_bhi<R: Read>(reader: R) -> io::Result<Collection> {
+        Ok(Collection::read_bhi_with_format(reader)?.0)
+    }
+
+    fn read_bhi_with_format<R: Read>(
+        mut reader: R,
+    ) -> io::Result<(Collection, FormatInfo)> {
+        read_exactly(reader.by_ref(), BHI_MAGIC)?;
+        let version = read_u16(reader.by_ref())?;
+        if version != 0 {
+            let msg = format!("unsupported BHI version: {}", version);
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        let flags = read_u16(reader.by_ref())? as u32;
+        if flags & !(ALL_FLAGS | BHI_FLAG_COMMENTS) != 0 {
+            let msg = format!("unsupported BHI flags: {:X}", flags);
+            return Err(Error::new(ErrorKind::InvalidData, msg));
         }
+        let num_palettes = read_u16(reader.by_ref())? as usize;
+        let num_images = read_u16(reader.by_ref())? as usize;
+        let (global_width, global_height) =
+            if flags & FLAG_INDIVIDUAL_DIMENSIONS != 0 {
+                (0, 0)
+            } else {
+                let width = read_u16(reader.by_ref())? as u32;
+                let height = read_u16(reader.by_ref())? as u32;
+                (width, height)
+            };
+
+        let mut palettes = Vec::with_capacity(num_palettes);
         for _ in 0..num_palettes {
-            palettes.push(Palette::read(reader.by_ref())?);
+            let mut rgba = [(0u8, 0u8, 0u8, 0u8); 16];
+            for entry in rgba.iter_mut() {
+                let mut buffer = [0u8; 4];
+                reader.read_exact(&mut buffer)?;
+                *entry = (buffer[0], buffer[1], buffer[2], buffer[3]);
+            }
+            palettes.push(Palette::new(rgba));
         }
 
         let mut images = Vec::with_capacity(num_images);
         for _ in 0..num_images {
-            read_exactly(reader.by_ref(), b"\n")?;
+            let comment = if flags & BHI_FLAG_COMMENTS != 0 {
+                read_bhi_string(reader.by_ref(), "comment")?
+            } else {
+                String::new()
+            };
             let tag = if flags & FLAG_STRING_TAGS != 0 {
-                let tag = read_quoted_string(reader.by_ref())?;
-                read_exactly(reader.by_ref(), b"\n")?;
-                tag
+                read_bhi_string(reader.by_ref(), "tag")?
             } else {
                 String::new()
             };
             let metadata = if flags & FLAG_METADATA_INTS != 0 {
-                let metadata = read_list_of_i16s(reader.by_ref())?;
-                read_exactly(reader.by_ref(), b"\n")?;
+                let length = read_u16(reader.by_ref())? as usize;
+                let mut metadata = Vec::with_capacity(length);
+                for _ in 0..length {
+                    metadata.push(read_u16(reader.by_ref())? as i16);
+                }
                 metadata
             } else {
                 Vec::new()
             };
             let (width, height) = if flags & FLAG_INDIVIDUAL_DIMENSIONS != 0 {
-                read_exactly(reader.by_ref(), b"w")?;
-                let width = read_header_uint(reader.by_ref(), b' ')?;
-                read_exactly(reader.by_ref(), b"h")?;
-                let height = read_header_uint(reader.by_ref(), b'\n')?;
+                let width = read_u16(reader.by_ref())? as u32;
+                let height = read_u16(reader.by_ref())? as u32;
                 (width, height)
             } else {
                 (global_width, global_height)
             };
-            let mut image = Image::read(reader.by_ref(), width, height)?;
+            // Read the packed pixels before allocating the image, so that
+            // a header claiming huge images can't make us allocate more
+            // than the input actually contains.
+            let num_bytes = (width as u64 * height as u64).div_ceil(2);
+            let mut data = Vec::new();
+            reader.by_ref().take(num_bytes).read_to_end(&mut data)?;
+            if (data.len() as u64) < num_bytes {
+                let msg = "unexpected end of BHI file";
+                return Err(Error::new(ErrorKind::UnexpectedEof, msg));
+            }
+            let mut image = Image::new(width, height);
+            for (index, pixel) in image.pixels.iter_mut().enumerate() {
+                let byte = data[index / 2];
+                let nibble = if index % 2 == 0 { byte >> 4 } else { byte };
+                *pixel = Color::from_nibble(nibble);
+            }
+            image.set_comment(comment);
             image.set_tag(tag);
             image.set_metadata(metadata);
             images.push(image);
         }
 
-        Ok(Collection { palettes, images })
+        let format =
+            FormatInfo { version: version as u32, flags, binary: true };
+        Ok((Collection { palettes, images }, format))
     }
 
-    /// Writes a collection to an AHI file, automatically choosing the lowest
-    /// format version possible.
-    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+    /// Returns the common size of all images in the collection (if any), and
+    /// the format flags needed to represent this collection.
+    fn format_flags(&self) -> (Option<(u32, u32)>, u32) {
         let global_size = if self.images.is_empty() {
             Some((0, 0))
         } else {
@@ -198,6 +401,10 @@ impl Collection {
             }
             size
         };
+        let mut flags = 0;
+        if global_size.is_none() {
+            flags |= FLAG_INDIVIDUAL_DIMENSIONS;
+        }
         let mut has_string_tags = false;
         for image in self.images.iter() {
             if !image.tag().is_empty() {
@@ -205,6 +412,9 @@ impl Collection {
                 break;
             }
         }
+        if has_string_tags {
+            flags |= FLAG_STRING_TAGS;
+        }
         let mut has_metadata = false;
         for image in self.images.iter() {
             if !image.metadata().is_empty() {
@@ -212,15 +422,111 @@ impl Collection {
                 break;
             }
         }
-        let version = if self.palettes.is_empty()
-            && global_size.is_some()
-            && !has_string_tags
-            && !has_metadata
+        if has_metadata {
+            flags |= FLAG_METADATA_INTS;
+        }
+        (global_size, flags)
+    }
+
+    /// Chooses the format flags to use when writing this collection with the
+    /// given options (including the BHI-only comments flag, if writing BHI).
+    fn choose_flags(&self, options: &WriteOptions) -> io::Result<u32> {
+        let (format_name, allowed_flags) = if options.binary {
+            ("BHI", ALL_FLAGS | BHI_FLAG_COMMENTS)
+        } else {
+            ("AHI", ALL_FLAGS)
+        };
+        let (_, mut needed_flags) = self.format_flags();
+        if options.binary
+            && self.images.iter().any(|image| !image.comment().is_empty())
         {
-            0
+            needed_flags |= BHI_FLAG_COMMENTS;
+        }
+        let mut flags = options.flags.unwrap_or(needed_flags);
+        if flags & !allowed_flags != 0 {
+            let msg =
+                format!("unsupported {} flags: {:X}", format_name, flags);
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        if flags & needed_flags != needed_flags {
+            if options.error_if_unrepresentable {
+                let msg = format!(
+                    "collection requires {} flags {:X}, but only {:X} are \
+                     allowed",
+                    format_name, needed_flags, flags
+                );
+                return Err(Error::new(ErrorKind::InvalidInput, msg));
+            }
+            flags |= needed_flags;
+        }
+        Ok(flags)
+    }
+
+    /// Chooses the AHI format version and flags to use when writing this
+    /// collection with the given options.
+    fn choose_format(&self, options: &WriteOptions) -> io::Result<(u32, u32)> {
+        if options.min_version > options.max_version {
+            let msg = format!(
+                "minimum AHI version ({}) is greater than maximum ({})",
+                options.min_version, options.max_version
+            );
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        if options.min_version > MAX_AHI_VERSION {
+            let msg =
+                format!("unsupported AHI version: {}", options.min_version);
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        let flags = self.choose_flags(options)?;
+        let has_comments =
+            self.images.iter().any(|image| !image.comment().is_empty());
+        let needed_version =
+            if self.palettes.is_empty() && flags == 0 && !has_comments {
+                0
+            } else {
+                1
+            };
+        let mut version = options.min_version.max(needed_version);
+        if version > options.max_version {
+            if options.error_if_unrepresentable {
+                let msg = format!(
+                    "collection requires AHI version {}, but maximum is {}",
+                    needed_version, options.max_version
+                );
+                return Err(Error::new(ErrorKind::InvalidInput, msg));
+            }
+            version = needed_version;
+        }
+        Ok((version, flags))
+    }
+
+    /// Writes a collection to an AHI file, automatically choosing the lowest
+    /// format version possible.
+    pub fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        self.write_with_options(writer, &WriteOptions::new())
+    }
+
+    /// Writes a collection to an AHI file, choosing the format version and
+    /// flags according to the given options.  If the options request the
+    /// binary encoding, writes a BHI file instead (see
+    /// `write_bhi_with_options`).
+    #[allow(clippy::write_with_newline)]
+    pub fn write_with_options<W: Write>(
+        &self,
+        mut writer: W,
+        options: &WriteOptions,
+    ) -> io::Result<()> {
+        if options.binary {
+            return self.write_bhi_with_options(writer, options);
+        }
+        let (version, flags) = self.choose_format(options)?;
+        let global_size = if flags & FLAG_INDIVIDUAL_DIMENSIONS != 0 {
+            None
         } else {
-            1
+            self.format_flags().0
         };
+        let has_string_tags = flags & FLAG_STRING_TAGS != 0;
+        let has_metadata = flags & FLAG_METADATA_INTS != 0;
         if version == 0 {
             let (width, height) = global_size.unwrap();
             write!(
@@ -231,16 +537,6 @@ impl Collection {
                 self.images.len()
             )?;
         } else {
-            let mut flags = 0;
-            if global_size.is_none() {
-                flags |= FLAG_INDIVIDUAL_DIMENSIONS;
-            }
-            if has_string_tags {
-                flags |= FLAG_STRING_TAGS;
-            }
-            if has_metadata {
-                flags |= FLAG_METADATA_INTS;
-            }
             write!(
                 writer,
                 "ahi1 f{:X} p{} i{}",
@@ -261,26 +557,14 @@ impl Collection {
         }
         for image in self.images.iter() {
             write!(writer, "\n")?;
+            write_comment(writer.by_ref(), image.comment())?;
             if has_string_tags {
-                let mut escaped = String::new();
-                for chr in image.tag().chars() {
-                    escaped
-                        .push_str(&chr.escape_default().collect::<String>());
-                }
-                write!(writer, "\"{}\"\n", escaped)?;
+                write_quoted_string(writer.by_ref(), image.tag())?;
+                write!(writer, "\n")?;
             }
             if has_metadata {
-                write!(writer, "[")?;
-                let mut first = true;
-                for &value in image.metadata().iter() {
-                    if first {
-                        first = false;
-                    } else {
-                        write!(writer, ", ")?;
-                    }
-                    write!(writer, "{}", value)?;
-                }
-                write!(writer, "]\n")?;
+                write_list_of_i16s(writer.by_ref(), image.metadata())?;
+                write!(writer, "\n")?;
             }
             if global_size.is_none() {
                 write!(writer, "w{} h{}\n", image.width(), image.height())?;
@@ -289,6 +573,92 @@ impl Collection {
         }
         Ok(())
     }
+
+    /// Writes a collection to a BHI file.  Unlike version 0 AHI files, BHI
+    /// files preserve image comments, but each comment (like each tag) must
+    /// be at most 65535 bytes long.
+    pub fn write_bhi<W: Write>(&self, writer: W) -> io::Result<()> {
+        self.write_bhi_with_options(writer, &WriteOptions::new().binary(true))
+    }
+
+    /// Writes a collection to a BHI file, choosing the format flags according
+    /// to the given options.  Since BHI has only one version, the version
+    /// options (which apply to AHI) are ignored.
+    pub fn write_bhi_with_options<W: Write>(
+        &self,
+        mut writer: W,
+        options: &WriteOptions,
+    ) -> io::Result<()> {
+        let flags = self.choose_flags(&options.binary(true))?;
+        let global_size = if flags & FLAG_INDIVIDUAL_DIMENSIONS != 0 {
+            None
+        } else {
+            self.format_flags().0
+        };
+        writer.write_all(BHI_MAGIC)?;
+        write_u16(writer.by_ref(), 0)?;
+        write_u16(writer.by_ref(), flags)?;
+        write_u16(writer.by_ref(), self.palettes.len() as u32)?;
+        write_u16(writer.by_ref(), self.images.len() as u32)?;
+        if let Some((width, height)) = global_size {
+            write_u16(writer.by_ref(), width)?;
+            write_u16(writer.by_ref(), height)?;
+        }
+        for palette in self.palettes.iter() {
+            for index in 0..16 {
+                let (r, g, b, a) = palette[Color::from_nibble(index)];
+                writer.write_all(&[r, g, b, a])?;
+            }
+        }
+        for image in self.images.iter() {
+            if flags & BHI_FLAG_COMMENTS != 0 {
+                write_bhi_string(writer.by_ref(), image.comment())?;
+            }
+            if flags & FLAG_STRING_TAGS != 0 {
+                write_bhi_string(writer.by_ref(), image.tag())?;
+            }
+            if flags & FLAG_METADATA_INTS != 0 {
+                let metadata = image.metadata();
+                write_u16(writer.by_ref(), metadata.len() as u32)?;
+                for &value in metadata.iter() {
+                    writer.write_all(&value.to_be_bytes())?;
+                }
+            }
+            if global_size.is_none() {
+                write_u16(writer.by_ref(), image.width())?;
+                write_u16(writer.by_ref(), image.height())?;
+            }
+            let mut data = Vec::with_capacity(image.pixels.len().div_ceil(2));
+            for pair in image.pixels.chunks(2) {
+                let high = pair[0] as u8;
+                let low = if pair.len() > 1 { pair[1] as u8 } else { 0 };
+                data.push((high << 4) | low);
+            }
+            writer.write_all(&data)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Collection {
+    fn default() -> Collection {
+        Collection::new()
+    }
+}
+
+fn read_bhi_string<R: Read>(mut reader: R, what: &str) -> io::Result<String> {
+    let length = read_u16(reader.by_ref())? as usize;
+    let mut buffer = vec![0u8; length];
+    reader.read_exact(&mut buffer)?;
+    String::from_utf8(buffer).map_err(|_| {
+        let msg = format!("invalid UTF-8 in image {}", what);
+        Error::new(ErrorKind::InvalidData, msg)
+    })
+}
+
+fn write_bhi_string<W: Write>(mut writer: W, string: &str) -> io::Result<()> {
+    write_u16(writer.by_ref(), string.len() as u32)?;
+    writer.write_all(string.as_bytes())
 }
 
 // ========================================================================= //
@@ -296,7 +666,7 @@ impl Collection {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::internal::color::Color;
+    use crate::internal::error::ParseError;
 
     #[test]
     fn read_empty_v0_collection() {
@@ -453,6 +823,143 @@ mod tests {
         assert_eq!(collection.images[1].height(), 2);
     }
 
+    #[test]
+    fn read_collection_with_invalid_pixel() {
+        let input: &[u8] = b"ahi0 w2 h2 n2\n\
+                             \n\
+                             20\n\
+                             5D\n\
+                             \n\
+                             E0\n\
+                             0G\n";
+        let error = Collection::read(input).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let error = ParseError::from_io_error(&error).unwrap();
+        assert_eq!(error.line(), 7);
+        assert_eq!(error.column(), 2);
+        assert_eq!(error.index(), Some(1));
+        assert_eq!(error.kind(), &ParseErrorKind::InvalidPixel(b'G'));
+        assert_eq!(
+            error.to_string(),
+            "line 7, column 2 (image 1): invalid pixel character: 'G'"
+        );
+    }
+
+    #[test]
+    fn read_collection_with_missing_newline() {
+        let input: &[u8] = b"ahi0 w2 h1 n1\n\
+                             \n\
+                             200\n";
+        let error = Collection::read(input).err().unwrap();
+        let error = ParseError::from_io_error(&error).unwrap();
+        assert_eq!((error.line(), error.column()), (3, 3));
+        assert_eq!(
+            error.kind(),
+            &ParseErrorKind::UnexpectedByte {
+                expected: "\n".to_string(),
+                found: b'0'
+            }
+        );
+    }
+
+    #[test]
+    fn read_truncated_collection() {
+        let input: &[u8] = b"ahi0 w2 h2 n1\n\
+                             \n\
+                             20\n";
+        let error = Collection::read(input).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        let error = ParseError::from_io_error(&error).unwrap();
+        assert_eq!((error.line(), error.column()), (4, 1));
+        assert_eq!(error.index(), Some(0));
+    }
+
+    #[test]
+    fn read_collection_with_unsupported_version() {
+        let input: &[u8] = b"ahi7 f0 p0 i0 w0 h0\n";
+        let error = Collection::read(input).err().unwrap();
+        let error = ParseError::from_io_error(&error).unwrap();
+        assert_eq!(error.index(), None);
+        assert_eq!(error.kind(), &ParseErrorKind::UnsupportedVersion(7));
+    }
+
+    #[test]
+    fn read_collection_leniently() {
+        let input: &[u8] = b"ahi1 f2 p1 i2 w2 h2 \r\n\
+              \r\n\
+              0;1;2;3;4;5;6;7;8;9;A;B;C;D;E;F\r\n\
+              \r\n\
+              \"foo\"  \r\n\
+              2a\r\n\
+              5D\r\n\
+              \r\n\
+              \"bar\"\r\n\
+              e0\t\r\n\
+              0E";
+        assert!(Collection::read(input).is_err());
+        let options = ReadOptions::lenient();
+        let collection =
+            Collection::read_with_options(input, &options).unwrap();
+        assert_eq!(collection.palettes.len(), 1);
+        assert_eq!(collection.images.len(), 2);
+        assert_eq!(collection.images[0].tag(), "foo");
+        assert_eq!(collection.images[0][(1, 0)], Color::Ca);
+        assert_eq!(collection.images[1].tag(), "bar");
+        assert_eq!(collection.images[1][(0, 0)], Color::Ce);
+        assert_eq!(collection.images[1][(1, 1)], Color::Ce);
+    }
+
+    #[test]
+    fn read_collection_with_only_some_leniencies() {
+        let input: &[u8] = b"ahi0 w2 h1 n1\r\n\
+                             \r\n\
+                             2a\r\n";
+        let options = ReadOptions::new().allow_crlf(true);
+        let error =
+            Collection::read_with_options(input, &options).err().unwrap();
+        let error = ParseError::from_io_error(&error).unwrap();
+        assert_eq!(error.kind(), &ParseErrorKind::InvalidPixel(b'a'));
+        let options = options.allow_lowercase_hex(true);
+        let collection =
+            Collection::read_with_options(input, &options).unwrap();
+        assert_eq!(collection.images[0][(1, 0)], Color::Ca);
+    }
+
+    #[test]
+    fn read_v1_collection_with_comments() {
+        let input: &[u8] = b"ahi1 f2 p0 i2 w2 h1\n\
+              \n\
+              # Idle animation\n\
+              \"foo\"\n\
+              00\n\
+              \n\
+              # frames 3-5 are the\n\
+              #\n\
+              #hurt animation\n\
+              \"bar\"\n\
+              00\n";
+        let collection = Collection::read(input).unwrap();
+        assert_eq!(collection.images.len(), 2);
+        assert_eq!(collection.images[0].comment(), "Idle animation");
+        assert_eq!(collection.images[0].tag(), "foo");
+        assert_eq!(
+            collection.images[1].comment(),
+            "frames 3-5 are the\n\nhurt animation"
+        );
+        assert_eq!(collection.images[1].tag(), "bar");
+    }
+
+    #[test]
+    fn read_v0_collection_with_comment() {
+        let input: &[u8] = b"ahi0 w2 h1 n1\n\
+                             \n\
+                             # Not allowed in v0\n\
+                             00\n";
+        let error = Collection::read(input).err().unwrap();
+        let error = ParseError::from_io_error(&error).unwrap();
+        assert_eq!(error.kind(), &ParseErrorKind::InvalidPixel(b'#'));
+    }
+
     #[test]
     fn write_empty_collection() {
         let mut output = Vec::<u8>::new();
@@ -609,6 +1116,281 @@ mod tests {
               0\n";
         assert_eq!(&output as &[u8], expected);
     }
+
+    #[test]
+    fn write_bhi_collection_with_same_sized_images() {
+        let mut collection = Collection::new();
+        let mut image0 = Image::new(3, 1);
+        image0[(0, 0)] = Color::C2;
+        image0[(1, 0)] = Color::C5;
+        image0[(2, 0)] = Color::Cd;
+        collection.images.push(image0);
+        let mut image1 = Image::new(3, 1);
+        image1[(2, 0)] = Color::Ce;
+        collection.images.push(image1);
+        let mut output = Vec::<u8>::new();
+        collection.write_bhi(&mut output).unwrap();
+        let expected: &[u8] = b"\x1Bbhi\x00\x00\x00\x00\x00\x00\x00\x02\
+              \x00\x03\x00\x01\
+              \x25\xD0\
+              \x00\xE0";
+        assert_eq!(&output as &[u8], expected);
+    }
+
+    #[test]
+    fn read_bhi_collection_with_all_flags() {
+        let input: &[u8] = b"\x1Bbhi\x00\x00\x00\x07\x00\x00\x00\x02\
+              \x00\x00\
+              \x00\x03\x00\x01\xFF\xFE\x00\x03\
+              \x00\x03\x00\x01\
+              \x02\x30\
+              \x00\x03foo\
+              \x00\x00\
+              \x00\x01\x00\x02\
+              \xF0";
+        let collection = Collection::read(input).unwrap();
+        assert_eq!(collection.images.len(), 2);
+        assert_eq!(collection.images[0].tag(), "");
+        assert_eq!(collection.images[0].metadata(), &[1, -2, 3]);
+        assert_eq!(collection.images[0].width(), 3);
+        assert_eq!(collection.images[0].height(), 1);
+        assert_eq!(collection.images[0][(1, 0)], Color::C2);
+        assert_eq!(collection.images[0][(2, 0)], Color::C3);
+        assert_eq!(collection.images[1].tag(), "foo");
+        assert_eq!(collection.images[1].metadata(), &[]);
+        assert_eq!(collection.images[1].width(), 1);
+        assert_eq!(collection.images[1].height(), 2);
+        assert_eq!(collection.images[1][(0, 0)], Color::Cf);
+        assert_eq!(collection.images[1][(0, 1)], Color::C0);
+    }
+
+    #[test]
+    fn bhi_round_trip_with_palettes_tags_and_metadata() {
+        let mut collection = Collection::new();
+        collection.palettes.push(Palette::default().clone());
+        collection.palettes.push(Palette::new([(1, 2, 3, 4); 16]));
+        let mut image0 = Image::new(5, 3);
+        image0.fill_rect(1, 1, 3, 2, Color::Cb);
+        image0.set_tag("Snowman\u{2603}");
+        collection.images.push(image0);
+        let mut image1 = Image::new(2, 7);
+        image1.fill_rect(0, 3, 2, 1, Color::C7);
+        image1.set_metadata(vec![-32768, 0, 32767]);
+        collection.images.push(image1);
+        let mut output = Vec::<u8>::new();
+        collection.write_bhi(&mut output).unwrap();
+        let collection = Collection::read(&output as &[u8]).unwrap();
+        assert_eq!(collection.palettes.len(), 2);
+        assert_eq!(collection.palettes[0][Color::C2], (127, 0, 0, 255));
+        assert_eq!(collection.palettes[1][Color::Cf], (1, 2, 3, 4));
+        assert_eq!(collection.images.len(), 2);
+        assert_eq!(collection.images[0].tag(), "Snowman\u{2603}");
+        assert_eq!(collection.images[0].width(), 5);
+        assert_eq!(collection.images[0][(3, 2)], Color::Cb);
+        assert_eq!(collection.images[0][(4, 2)], Color::C0);
+        assert_eq!(collection.images[1].metadata(), &[-32768, 0, 32767]);
+        assert_eq!(collection.images[1].height(), 7);
+        assert_eq!(collection.images[1][(1, 3)], Color::C7);
+    }
+
+    #[test]
+    fn write_collection_with_comments() {
+        let mut collection = Collection::new();
+        collection.images.push(Image::new(2, 1));
+        collection.images[0].set_comment("frames 3-5 are the\n\nhurt one");
+        collection.images.push(Image::new(2, 1));
+        let mut output = Vec::<u8>::new();
+        collection.write(&mut output).unwrap();
+        let expected: &[u8] = b"ahi1 f0 p0 i2 w2 h1\n\
+              \n\
+              # frames 3-5 are the\n\
+              #\n\
+              # hurt one\n\
+              00\n\
+              \n\
+              00\n";
+        assert_eq!(&output as &[u8], expected);
+        let collection = Collection::read(&output as &[u8]).unwrap();
+        assert_eq!(
+            collection.images[0].comment(),
+            "frames 3-5 are the\n\nhurt one"
+        );
+        assert_eq!(collection.images[1].comment(), "");
+    }
+
+    #[test]
+    fn bhi_round_trip_preserves_comments() {
+        let input: &[u8] = b"ahi1 f0 p0 i2 w2 h1\n\
+              \n\
+              # Idle\n\
+              #\n\
+              # frame\n\
+              10\n\
+              \n\
+              01\n";
+        let collection = Collection::read(input).unwrap();
+        let mut bhi = Vec::<u8>::new();
+        collection.write_bhi(&mut bhi).unwrap();
+        assert_eq!(&bhi[4..8], b"\x00\x00\x00\x08");
+        let collection = Collection::read(&bhi as &[u8]).unwrap();
+        assert_eq!(collection.images[0].comment(), "Idle\n\nframe");
+        assert_eq!(collection.images[1].comment(), "");
+        let mut output = Vec::<u8>::new();
+        collection.write(&mut output).unwrap();
+        assert_eq!(&output as &[u8], input);
+    }
+
+    #[test]
+    fn write_bhi_with_too_long_comment() {
+        let mut collection = Collection::new();
+        collection.images.push(Image::new(1, 1));
+        collection.images[0].set_comment("x".repeat(0x10000));
+        let error = collection.write_bhi(Vec::<u8>::new()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn read_truncated_bhi() {
+        let input: &[u8] = b"\x1Bbhi\x00\x00\x00\x00\x00\x00\x00\x02\
+              \x00\x03\x00\x02\
+              \x12\x34\x56\
+              \x78\x9A";
+        let error = Collection::read_bhi(input).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert!(Collection::read_bhi(&input[..12]).is_err());
+    }
+
+    #[test]
+    fn read_oversized_bhi() {
+        let input: &[u8] = b"\x1Bbhi\x00\x00\x00\x00\x00\x00\xFF\xFF\
+              \xFF\xFF\xFF\xFF\
+              \x12\x34\x56\x78";
+        let error = Collection::read_bhi(input).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        let input: &[u8] = b"\x1Bbhi\x00\x00\x00\x01\x00\x00\x00\x01\
+              \xFF\xFF\xFF\xFF";
+        let error = Collection::read_bhi(input).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn read_bhi_with_unsupported_flags() {
+        let input: &[u8] = b"\x1Bbhi\x00\x00\x00\x10\x00\x00\x00\x00";
+        let error = Collection::read_bhi(input).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn read_collection_format() {
+        let input: &[u8] = b"ahi0 w0 h0 n0\n";
+        let (_, format) =
+            Collection::read_with_format(input, &ReadOptions::new()).unwrap();
+        assert_eq!((format.version(), format.flags()), (0, 0));
+        assert!(!format.is_binary());
+        let input: &[u8] = b"ahi1 f6 p0 i0 w0 h0\n";
+        let (_, format) =
+            Collection::read_with_format(input, &ReadOptions::new()).unwrap();
+        assert_eq!((format.version(), format.flags()), (1, 6));
+        let input: &[u8] = b"\x1Bbhi\x00\x00\x00\x02\x00\x00\x00\x00\
+              \x00\x00\x00\x00";
+        let (_, format) =
+            Collection::read_with_format(input, &ReadOptions::new()).unwrap();
+        assert_eq!((format.version(), format.flags()), (0, 2));
+        assert!(format.is_binary());
+    }
+
+    #[test]
+    fn write_collection_with_min_version() {
+        let mut collection = Collection::new();
+        collection.images.push(Image::new(2, 1));
+        let options = WriteOptions::new().min_version(1);
+        let mut output = Vec::<u8>::new();
+        collection.write_with_options(&mut output, &options).unwrap();
+        let expected: &[u8] = b"ahi1 f0 p0 i1 w2 h1\n\
+              \n\
+              00\n";
+        assert_eq!(&output as &[u8], expected);
+    }
+
+    #[test]
+    fn write_collection_with_explicit_flags() {
+        let mut collection = Collection::new();
+        collection.images.push(Image::new(2, 1));
+        collection.images[0].set_tag("foo");
+        let options =
+            WriteOptions::new().flags(Collection::FLAG_METADATA_INTS);
+        let mut output = Vec::<u8>::new();
+        collection.write_with_options(&mut output, &options).unwrap();
+        let expected: &[u8] = b"ahi1 f6 p0 i1 w2 h1\n\
+              \n\
+              \"foo\"\n\
+              []\n\
+              00\n";
+        assert_eq!(&output as &[u8], expected);
+        let options = options.error_if_unrepresentable(true);
+        let error = collection
+            .write_with_options(Vec::<u8>::new(), &options)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn write_collection_with_max_version() {
+        let mut collection = Collection::new();
+        collection.images.push(Image::new(2, 1));
+        collection.images[0].set_metadata(vec![1]);
+        let options = WriteOptions::new().max_version(0);
+        let mut output = Vec::<u8>::new();
+        collection.write_with_options(&mut output, &options).unwrap();
+        assert!(output.starts_with(b"ahi1 f4 "));
+        let options = options.error_if_unrepresentable(true);
+        let error = collection
+            .write_with_options(Vec::<u8>::new(), &options)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        collection.images[0].set_metadata(vec![]);
+        let mut output = Vec::<u8>::new();
+        collection.write_with_options(&mut output, &options).unwrap();
+        assert!(output.starts_with(b"ahi0 "));
+    }
+
+    #[test]
+    fn write_collection_in_format_it_was_read_with() {
+        let input: &[u8] = b"ahi1 f1 p0 i1\n\
+              \n\
+              w2 h1\n\
+              00\n";
+        let (collection, format) =
+            Collection::read_with_format(input, &ReadOptions::new()).unwrap();
+        let mut output = Vec::<u8>::new();
+        collection.write(&mut output).unwrap();
+        assert!(output.starts_with(b"ahi0 "));
+        let options = WriteOptions::from_format(&format);
+        let mut output = Vec::<u8>::new();
+        collection.write_with_options(&mut output, &options).unwrap();
+        assert_eq!(&output as &[u8], input);
+    }
+
+    #[test]
+    fn write_bhi_collection_in_format_it_was_read_with() {
+        let mut collection = Collection::new();
+        collection.images.push(Image::new(1, 1));
+        collection.images[0][(0, 0)] = Color::C5;
+        let options = WriteOptions::new()
+            .binary(true)
+            .flags(Collection::FLAG_STRING_TAGS);
+        let mut input = Vec::<u8>::new();
+        collection.write_with_options(&mut input, &options).unwrap();
+        assert!(input.starts_with(b"\x1Bbhi\x00\x00\x00\x02"));
+        let (collection, format) =
+            Collection::read_with_format(&input as &[u8], &ReadOptions::new())
+                .unwrap();
+        assert!(format.is_binary());
+        let options = WriteOptions::from_format(&format);
+        let mut output = Vec::<u8>::new();
+        collection.write_with_options(&mut output, &options).unwrap();
+        assert_eq!(output, input);
+    }
 }
 
 // ========================================================================= //
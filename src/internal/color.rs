@@ -17,8 +17,6 @@
 // | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
 // +--------------------------------------------------------------------------+
 
-use std::io::{self, Error, ErrorKind};
-
 // ========================================================================= //
 
 /// Represents a pixel color for an ASCII Hex Image.
@@ -80,31 +78,46 @@ impl Color {
         (b"0123456789ABCDEF")[self as usize]
     }
 
-    pub(crate) fn from_byte(byte: u8) -> io::Result<Color> {
+    pub(crate) fn from_nibble(nibble: u8) -> Color {
+        match nibble & 0xf {
+            0x0 => Color::C0,
+            0x1 => Color::C1,
+            0x2 => Color::C2,
+            0x3 => Color::C3,
+            0x4 => Color::C4,
+            0x5 => Color::C5,
+            0x6 => Color::C6,
+            0x7 => Color::C7,
+            0x8 => Color::C8,
+            0x9 => Color::C9,
+            0xa => Color::Ca,
+            0xb => Color::Cb,
+            0xc => Color::Cc,
+            0xd => Color::Cd,
+            0xe => Color::Ce,
+            _ => Color::Cf,
+        }
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> Option<Color> {
         match byte {
-            b'0' => Ok(Color::C0),
-            b'1' => Ok(Color::C1),
-            b'2' => Ok(Color::C2),
-            b'3' => Ok(Color::C3),
-            b'4' => Ok(Color::C4),
-            b'5' => Ok(Color::C5),
-            b'6' => Ok(Color::C6),
-            b'7' => Ok(Color::C7),
-            b'8' => Ok(Color::C8),
-            b'9' => Ok(Color::C9),
-            b'A' => Ok(Color::Ca),
-            b'B' => Ok(Color::Cb),
-            b'C' => Ok(Color::Cc),
-            b'D' => Ok(Color::Cd),
-            b'E' => Ok(Color::Ce),
-            b'F' => Ok(Color::Cf),
-            _ => {
-                let msg = format!(
-                    "invalid pixel character: '{}'",
-                    String::from_utf8_lossy(&[byte])
-                );
-                Err(Error::new(ErrorKind::InvalidData, msg))
-            }
+            b'0' => Some(Color::C0),
+            b'1' => Some(Color::C1),
+            b'2' => Some(Color::C2),
+            b'3' => Some(Color::C3),
+            b'4' => Some(Color::C4),
+            b'5' => Some(Color::C5),
+            b'6' => Some(Color::C6),
+            b'7' => Some(Color::C7),
+            b'8' => Some(Color::C8),
+            b'9' => Some(Color::C9),
+            b'A' => Some(Color::Ca),
+            b'B' => Some(Color::Cb),
+            b'C' => Some(Color::Cc),
+            b'D' => Some(Color::Cd),
+            b'E' => Some(Color::Ce),
+            b'F' => Some(Color::Cf),
+            _ => None,
         }
     }
 }
@@ -139,6 +152,13 @@ mod tests {
             assert_eq!(Color::from_byte(color.to_byte()).unwrap(), color);
         }
     }
+
+    #[test]
+    fn color_nibble_round_trip() {
+        for nibble in 0..16 {
+            assert_eq!(Color::from_nibble(nibble) as u8, nibble);
+        }
+    }
 }
 
 // ========================================================================= //
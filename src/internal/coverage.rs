@@ -0,0 +1,372 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use crate::internal::font::Font;
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+// ========================================================================= //
+
+/// The Unicode blocks known to `UnicodeBlock`, in code point order.  This
+/// is a selection of the blocks most likely to matter for bitmap fonts (not
+/// every block in the Unicode standard), so rarer scripts are reported as
+/// being in no known block.
+const BLOCKS: &[(&str, u32, u32)] = &[
+    ("Basic Latin", 0x0000, 0x007F),
+    ("Latin-1 Supplement", 0x0080, 0x00FF),
+    ("Latin Extended-A", 0x0100, 0x017F),
+    ("Latin Extended-B", 0x0180, 0x024F),
+    ("IPA Extensions", 0x0250, 0x02AF),
+    ("Spacing Modifier Letters", 0x02B0, 0x02FF),
+    ("Combining Diacritical Marks", 0x0300, 0x036F),
+    ("Greek and Coptic", 0x0370, 0x03FF),
+    ("Cyrillic", 0x0400, 0x04FF),
+    ("Cyrillic Supplement", 0x0500, 0x052F),
+    ("Armenian", 0x0530, 0x058F),
+    ("Hebrew", 0x0590, 0x05FF),
+    ("Arabic", 0x0600, 0x06FF),
+    ("Syriac", 0x0700, 0x074F),
+    ("Arabic Supplement", 0x0750, 0x077F),
+    ("Thaana", 0x0780, 0x07BF),
+    ("Devanagari", 0x0900, 0x097F),
+    ("Bengali", 0x0980, 0x09FF),
+    ("Gurmukhi", 0x0A00, 0x0A7F),
+    ("Gujarati", 0x0A80, 0x0AFF),
+    ("Tamil", 0x0B80, 0x0BFF),
+    ("Telugu", 0x0C00, 0x0C7F),
+    ("Kannada", 0x0C80, 0x0CFF),
+    ("Malayalam", 0x0D00, 0x0D7F),
+    ("Thai", 0x0E00, 0x0E7F),
+    ("Lao", 0x0E80, 0x0EFF),
+    ("Tibetan", 0x0F00, 0x0FFF),
+    ("Georgian", 0x10A0, 0x10FF),
+    ("Hangul Jamo", 0x1100, 0x11FF),
+    ("Ethiopic", 0x1200, 0x137F),
+    ("Cherokee", 0x13A0, 0x13FF),
+    ("Khmer", 0x1780, 0x17FF),
+    ("Mongolian", 0x1800, 0x18AF),
+    ("Phonetic Extensions", 0x1D00, 0x1D7F),
+    ("Combining Diacritical Marks Supplement", 0x1DC0, 0x1DFF),
+    ("Latin Extended Additional", 0x1E00, 0x1EFF),
+    ("Greek Extended", 0x1F00, 0x1FFF),
+    ("General Punctuation", 0x2000, 0x206F),
+    ("Superscripts and Subscripts", 0x2070, 0x209F),
+    ("Currency Symbols", 0x20A0, 0x20CF),
+    ("Combining Diacritical Marks for Symbols", 0x20D0, 0x20FF),
+    ("Letterlike Symbols", 0x2100, 0x214F),
+    ("Number Forms", 0x2150, 0x218F),
+    ("Arrows", 0x2190, 0x21FF),
+    ("Mathematical Operators", 0x2200, 0x22FF),
+    ("Miscellaneous Technical", 0x2300, 0x23FF),
+    ("Control Pictures", 0x2400, 0x243F),
+    ("Enclosed Alphanumerics", 0x2460, 0x24FF),
+    ("Box Drawing", 0x2500, 0x257F),
+    ("Block Elements", 0x2580, 0x259F),
+    ("Geometric Shapes", 0x25A0, 0x25FF),
+    ("Miscellaneous Symbols", 0x2600, 0x26FF),
+    ("Dingbats", 0x2700, 0x27BF),
+    ("Miscellaneous Mathematical Symbols-A", 0x27C0, 0x27EF),
+    ("Supplemental Arrows-A", 0x27F0, 0x27FF),
+    ("Braille Patterns", 0x2800, 0x28FF),
+    ("Supplemental Arrows-B", 0x2900, 0x297F),
+    ("Miscellaneous Mathematical Symbols-B", 0x2980, 0x29FF),
+    ("Supplemental Mathematical Operators", 0x2A00, 0x2AFF),
+    ("Miscellaneous Symbols and Arrows", 0x2B00, 0x2BFF),
+    ("Latin Extended-C", 0x2C60, 0x2C7F),
+    ("Supplemental Punctuation", 0x2E00, 0x2E7F),
+    ("CJK Symbols and Punctuation", 0x3000, 0x303F),
+    ("Hiragana", 0x3040, 0x309F),
+    ("Katakana", 0x30A0, 0x30FF),
+    ("Bopomofo", 0x3100, 0x312F),
+    ("Hangul Compatibility Jamo", 0x3130, 0x318F),
+    ("Katakana Phonetic Extensions", 0x31F0, 0x31FF),
+    ("Enclosed CJK Letters and Months", 0x3200, 0x32FF),
+    ("CJK Compatibility", 0x3300, 0x33FF),
+    ("CJK Unified Ideographs Extension A", 0x3400, 0x4DBF),
+    ("CJK Unified Ideographs", 0x4E00, 0x9FFF),
+    ("Latin Extended-D", 0xA720, 0xA7FF),
+    ("Hangul Syllables", 0xAC00, 0xD7AF),
+    ("Private Use Area", 0xE000, 0xF8FF),
+    ("CJK Compatibility Ideographs", 0xF900, 0xFAFF),
+    ("Alphabetic Presentation Forms", 0xFB00, 0xFB4F),
+    ("Arabic Presentation Forms-A", 0xFB50, 0xFDFF),
+    ("Variation Selectors", 0xFE00, 0xFE0F),
+    ("CJK Compatibility Forms", 0xFE30, 0xFE4F),
+    ("Arabic Presentation Forms-B", 0xFE70, 0xFEFF),
+    ("Halfwidth and Fullwidth Forms", 0xFF00, 0xFFEF),
+    ("Specials", 0xFFF0, 0xFFFF),
+    ("Mathematical Alphanumeric Symbols", 0x1D400, 0x1D7FF),
+    ("Mahjong Tiles", 0x1F000, 0x1F02F),
+    ("Domino Tiles", 0x1F030, 0x1F09F),
+    ("Playing Cards", 0x1F0A0, 0x1F0FF),
+    ("Enclosed Alphanumeric Supplement", 0x1F100, 0x1F1FF),
+    ("Enclosed Ideographic Supplement", 0x1F200, 0x1F2FF),
+    ("Miscellaneous Symbols and Pictographs", 0x1F300, 0x1F5FF),
+    ("Emoticons", 0x1F600, 0x1F64F),
+    ("Ornamental Dingbats", 0x1F650, 0x1F67F),
+    ("Transport and Map Symbols", 0x1F680, 0x1F6FF),
+    ("Alchemical Symbols", 0x1F700, 0x1F77F),
+    ("Geometric Shapes Extended", 0x1F780, 0x1F7FF),
+    ("Supplemental Arrows-C", 0x1F800, 0x1F8FF),
+    ("Supplemental Symbols and Pictographs", 0x1F900, 0x1F9FF),
+    ("Chess Symbols", 0x1FA00, 0x1FA6F),
+    ("Symbols and Pictographs Extended-A", 0x1FA70, 0x1FAFF),
+    ("Symbols for Legacy Computing", 0x1FB00, 0x1FBFF),
+];
+
+/// A named range of Unicode code points, such as "Basic Latin" or
+/// "Cyrillic".  Only a selection of commonly-used blocks is known (including
+/// the major scripts, symbol, and emoji blocks), not every block in the
+/// Unicode standard.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UnicodeBlock {
+    name: &'static str,
+    first: u32,
+    last: u32,
+}
+
+impl UnicodeBlock {
+    /// Returns the known block with the given name (e.g. `"Cyrillic"`), if
+    /// any.  Names are matched case-insensitively.
+    pub fn by_name(name: &str) -> Option<UnicodeBlock> {
+        BLOCKS
+            .iter()
+            .find(|&&(block, _, _)| block.eq_ignore_ascii_case(name))
+            .map(|&(name, first, last)| UnicodeBlock { name, first, last })
+    }
+
+    /// Returns the known block containing the given character, if any.
+    pub fn of(chr: char) -> Option<UnicodeBlock> {
+        let code = chr as u32;
+        BLOCKS
+            .iter()
+            .find(|&&(_, first, last)| first <= code && code <= last)
+            .map(|&(name, first, last)| UnicodeBlock { name, first, last })
+    }
+
+    /// Returns all known blocks, in code point order.
+    pub fn all() -> Vec<UnicodeBlock> {
+        BLOCKS
+            .iter()
+            .map(|&(name, first, last)| UnicodeBlock { name, first, last })
+            .collect()
+    }
+
+    /// Returns the name of this block.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the range of characters in this block.
+    pub fn range(&self) -> RangeInclusive<char> {
+        // Known blocks never start or end within the surrogate range.
+        let first = char::from_u32(self.first).unwrap();
+        let last = char::from_u32(self.last).unwrap();
+        first..=last
+    }
+}
+
+// ========================================================================= //
+
+/// The characters from one Unicode block that are missing from a set of
+/// fonts, as part of a `CoverageReport`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlockCoverage {
+    block: Option<UnicodeBlock>,
+    missing: BTreeMap<char, usize>,
+}
+
+impl BlockCoverage {
+    /// Returns the block, or `None` for characters that aren't in any known
+    /// block.
+    pub fn block(&self) -> Option<UnicodeBlock> {
+        self.block
+    }
+
+    /// Returns the missing characters in this block, each mapped to the
+    /// number of times it appeared in the checked input.
+    pub fn missing(&self) -> &BTreeMap<char, usize> {
+        &self.missing
+    }
+}
+
+/// A report of which characters are missing from a set of fonts (and so
+/// would be drawn with the default glyph), grouped by Unicode block.
+///
+/// Control characters (such as `\n`) are never reported as missing, since
+/// they aren't normally drawn.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CoverageReport {
+    num_checked: usize,
+    blocks: Vec<BlockCoverage>,
+}
+
+impl CoverageReport {
+    /// Checks every character of the given text against the fonts.  A
+    /// character is covered if any of the fonts has a glyph for it.  Each
+    /// missing character is reported with the number of times it appears in
+    /// the text.
+    pub fn for_text(fonts: &[&Font], text: &str) -> CoverageReport {
+        CoverageReport::check(fonts, text.chars())
+    }
+
+    /// Checks every character in the given Unicode blocks against the fonts.
+    /// Each missing character is reported with a count of one.  Note that
+    /// blocks may include code points that are not assigned to any
+    /// character.
+    pub fn for_blocks(
+        fonts: &[&Font],
+        blocks: &[UnicodeBlock],
+    ) -> CoverageReport {
+        let chars = blocks.iter().flat_map(UnicodeBlock::range);
+        CoverageReport::check(fonts, chars)
+    }
+
+    fn check<I: Iterator<Item = char>>(
+        fonts: &[&Font],
+        chars: I,
+    ) -> CoverageReport {
+        let mut checked = BTreeMap::<char, bool>::new();
+        let mut missing = BTreeMap::<char, usize>::new();
+        for chr in chars.filter(|chr| !chr.is_control()) {
+            let covered = *checked.entry(chr).or_insert_with(|| {
+                fonts.iter().any(|font| font.get_char_glyph(chr).is_some())
+            });
+            if !covered {
+                *missing.entry(chr).or_insert(0) += 1;
+            }
+        }
+        let mut blocks: Vec<BlockCoverage> = Vec::new();
+        for (chr, count) in missing {
+            let block = UnicodeBlock::of(chr);
+            match blocks.iter_mut().find(|coverage| coverage.block == block) {
+                Some(coverage) => {
+                    coverage.missing.insert(chr, count);
+                }
+                None => {
+                    let mut missing = BTreeMap::new();
+                    missing.insert(chr, count);
+                    blocks.push(BlockCoverage { block, missing });
+                }
+            }
+        }
+        // Characters in no known block go last (the sort is stable, so known
+        // blocks stay in code point order).
+        blocks.sort_by_key(|coverage| coverage.block.is_none());
+        CoverageReport { num_checked: checked.len(), blocks }
+    }
+
+    /// Returns the number of distinct characters that were checked.
+    pub fn num_checked(&self) -> usize {
+        self.num_checked
+    }
+
+    /// Returns the number of distinct characters that are missing.
+    pub fn num_missing(&self) -> usize {
+        self.blocks.iter().map(|coverage| coverage.missing.len()).sum()
+    }
+
+    /// Returns true if no characters are missing.
+    pub fn is_complete(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Returns the missing characters, grouped by block, with known blocks in
+    /// code point order followed by a group (with no block) for any missing
+    /// characters that aren't in a known block.
+    pub fn blocks(&self) -> &[BlockCoverage] {
+        &self.blocks
+    }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::testutil::{font_from_rows, GlyphRows};
+
+    #[test]
+    fn look_up_blocks() {
+        let block = UnicodeBlock::by_name("cyrillic").unwrap();
+        assert_eq!(block.name(), "Cyrillic");
+        assert_eq!(block.range(), '\u{400}'..='\u{4FF}');
+        assert_eq!(UnicodeBlock::of('\u{416}'), Some(block));
+        assert_eq!(UnicodeBlock::of('A').unwrap().name(), "Basic Latin");
+        assert_eq!(UnicodeBlock::of('\u{1F600}').unwrap().name(), "Emoticons");
+        assert_eq!(UnicodeBlock::of('\u{7C0}'), None);
+        assert!(UnicodeBlock::by_name("Klingon").is_none());
+    }
+
+    #[test]
+    fn coverage_of_text() {
+        let latin = font_from_rows(
+            1,
+            1,
+            &[
+                ("H", 0, 2, &["0"]),
+                ("e", 0, 2, &["0"]),
+                ("l", 0, 2, &["0"]),
+                ("o", 0, 2, &["0"]),
+                (",", 0, 2, &["0"]),
+                (" ", 0, 2, &["0"]),
+            ],
+        );
+        let symbols = font_from_rows(1, 1, &[("\u{2603}", 0, 2, &["0"])]);
+        let text = "Hello, \u{2603}!\nПривет, Пётр! \u{7C0}\u{1F600}";
+        let report = CoverageReport::for_text(&[&latin, &symbols], text);
+        assert!(!report.is_complete());
+        assert_eq!(report.num_checked(), 17);
+        assert_eq!(report.num_missing(), 10);
+        let blocks = report.blocks();
+        assert_eq!(blocks.len(), 4);
+        assert_eq!(blocks[0].block().unwrap().name(), "Basic Latin");
+        assert_eq!(blocks[0].missing().get(&'!'), Some(&2));
+        assert_eq!(blocks[1].block().unwrap().name(), "Cyrillic");
+        assert_eq!(blocks[1].missing().get(&'П'), Some(&2));
+        assert_eq!(blocks[1].missing().len(), 7);
+        assert_eq!(blocks[2].block().unwrap().name(), "Emoticons");
+        assert_eq!(blocks[2].missing().get(&'\u{1F600}'), Some(&1));
+        assert_eq!(blocks[3].block(), None);
+        assert_eq!(blocks[3].missing().get(&'\u{7C0}'), Some(&1));
+
+        let report = CoverageReport::for_text(&[&latin], "Hello");
+        assert!(report.is_complete());
+    }
+
+    #[test]
+    fn coverage_of_blocks() {
+        let keys: Vec<String> = (' '..='~').map(String::from).collect();
+        let glyphs: Vec<GlyphRows> =
+            keys.iter().map(|key| (key.as_str(), 0, 2, &["0"][..])).collect();
+        let font = font_from_rows(1, 1, &glyphs);
+        let blocks = [
+            UnicodeBlock::by_name("Basic Latin").unwrap(),
+            UnicodeBlock::by_name("Latin-1 Supplement").unwrap(),
+        ];
+        let report = CoverageReport::for_blocks(&[&font], &blocks);
+        assert_eq!(report.num_checked(), 95 + 96);
+        assert_eq!(report.num_missing(), 96);
+        assert_eq!(report.blocks().len(), 1);
+        assert_eq!(report.blocks()[0].block(), Some(blocks[1]));
+        assert_eq!(report.blocks()[0].missing().get(&'\u{E9}'), Some(&1));
+    }
+}
+
+// ========================================================================= //
@@ -0,0 +1,256 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use std::error;
+use std::fmt;
+use std::io;
+
+// ========================================================================= //
+
+/// The specific problem that caused a `ParseError`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    /// The file ended before parsing was complete.
+    UnexpectedEof,
+    /// A specific sequence of bytes was expected, but a different byte was
+    /// found.
+    UnexpectedByte {
+        /// The bytes that were expected.
+        expected: String,
+        /// The byte that was actually found.
+        found: u8,
+    },
+    /// The header specified a format version that isn't supported.
+    UnsupportedVersion(u32),
+    /// An integer field was empty.
+    MissingInteger,
+    /// A minus sign appeared somewhere other than the start of an integer.
+    MisplacedMinusSign,
+    /// A decimal integer field contained a non-digit byte.
+    InvalidIntegerByte(u8),
+    /// An integer field had a value outside the permitted range.
+    ValueOutOfRange,
+    /// An integer field that must be nonnegative was negative.
+    NegativeValue(i32),
+    /// A hex literal contained a non-hex-digit byte.
+    InvalidHexDigit(u8),
+    /// A hex literal was empty.
+    MissingHexLiteral,
+    /// A hex literal had too many digits.
+    HexLiteralTooLarge,
+    /// A palette color had too many hex digits.
+    TooManyPaletteDigits,
+    /// A backslash in a quoted literal was followed by an invalid byte.
+    InvalidCharEscape(u8),
+    /// A quoted literal contained a byte that isn't printable ASCII.
+    InvalidCharLiteralByte(u8),
+    /// A `\u{...}` escape didn't specify a valid Unicode scalar value.
+    InvalidUnicodeValue(u32),
+    /// A quoted char literal was empty.
+    EmptyCharLiteral,
+    /// A double-quoted glyph key in a font file was empty.
+    EmptyGlyphKey,
+    /// An image row contained a byte that isn't a valid pixel color.
+    InvalidPixel(u8),
+    /// A comment line wasn't valid UTF-8.
+    InvalidUtf8,
+    /// An AHF family file had more than one face with the same name.
+    DuplicateFaceName(String),
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseErrorKind::UnexpectedEof => {
+                write!(f, "unexpected end of file")
+            }
+            ParseErrorKind::UnexpectedByte { ref expected, found } => write!(
+                f,
+                "expected '{}', found '{}'",
+                expected.escape_default(),
+                (found as char).escape_default()
+            ),
+            ParseErrorKind::UnsupportedVersion(version) => {
+                write!(f, "unsupported version: {}", version)
+            }
+            ParseErrorKind::MissingInteger => {
+                write!(f, "missing integer field")
+            }
+            ParseErrorKind::MisplacedMinusSign => {
+                write!(f, "misplaced minus sign in integer field")
+            }
+            ParseErrorKind::InvalidIntegerByte(byte) => write!(
+                f,
+                "invalid byte in integer field: '{}'",
+                (byte as char).escape_default()
+            ),
+            ParseErrorKind::ValueOutOfRange => {
+                write!(f, "integer value is out of range")
+            }
+            ParseErrorKind::NegativeValue(value) => {
+                write!(f, "value must be nonnegative (was {})", value)
+            }
+            ParseErrorKind::InvalidHexDigit(byte) => write!(
+                f,
+                "invalid hex digit: '{}'",
+                (byte as char).escape_default()
+            ),
+            ParseErrorKind::MissingHexLiteral => {
+                write!(f, "missing hex literal")
+            }
+            ParseErrorKind::HexLiteralTooLarge => {
+                write!(f, "hex literal is too large")
+            }
+            ParseErrorKind::TooManyPaletteDigits => {
+                write!(f, "too many digits in palette color")
+            }
+            ParseErrorKind::InvalidCharEscape(byte) => write!(
+                f,
+                "invalid char escape: '{}'",
+                (byte as char).escape_default()
+            ),
+            ParseErrorKind::InvalidCharLiteralByte(byte) => {
+                write!(f, "invalid char literal byte: {}", byte)
+            }
+            ParseErrorKind::InvalidUnicodeValue(value) => {
+                write!(f, "invalid unicode value: {:X}", value)
+            }
+            ParseErrorKind::EmptyCharLiteral => {
+                write!(f, "empty char literal")
+            }
+            ParseErrorKind::EmptyGlyphKey => write!(f, "empty glyph key"),
+            ParseErrorKind::InvalidPixel(byte) => write!(
+                f,
+                "invalid pixel character: '{}'",
+                (byte as char).escape_default()
+            ),
+            ParseErrorKind::InvalidUtf8 => write!(f, "invalid UTF-8"),
+            ParseErrorKind::DuplicateFaceName(ref name) => {
+                write!(f, "duplicate face name: {:?}", name)
+            }
+        }
+    }
+}
+
+// ========================================================================= //
+
+/// An error encountered while parsing an AHI or AHF file, along with the
+/// position in the file at which it occurred.
+///
+/// The `Collection` and `Font` read functions return `io::Error` values; when
+/// the failure was caused by malformed data, the `io::Error` wraps a
+/// `ParseError`, which can be retrieved with `ParseError::from_io_error`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseError {
+    line: usize,
+    column: usize,
+    item: Option<(&'static str, usize)>,
+    face: Option<usize>,
+    kind: ParseErrorKind,
+}
+
+impl ParseError {
+    pub(crate) fn new(
+        line: usize,
+        column: usize,
+        item: Option<(&'static str, usize)>,
+        kind: ParseErrorKind,
+    ) -> ParseError {
+        ParseError { line, column, item, face: None, kind }
+    }
+
+    /// Returns this error, marked as having occurred within the given face
+    /// of an AHF family file.
+    pub(crate) fn in_face(mut self, index: usize) -> ParseError {
+        self.face = Some(index);
+        self
+    }
+
+    /// Returns the `ParseError` wrapped by the given `io::Error`, if any.
+    pub fn from_io_error(error: &io::Error) -> Option<&ParseError> {
+        error.get_ref().and_then(|inner| inner.downcast_ref::<ParseError>())
+    }
+
+    /// Returns the line number (starting from 1) at which the error occurred.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Returns the column number (starting from 1) at which the error
+    /// occurred.  Columns are counted in bytes.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Returns the index of the image (for AHI files) or glyph (for AHF
+    /// files) that was being parsed when the error occurred, or `None` if the
+    /// error occurred outside of any image or glyph (e.g. in the header).
+    /// Glyphs are numbered in file order, with the default glyph being glyph
+    /// 0.
+    pub fn index(&self) -> Option<usize> {
+        self.item.map(|(_, index)| index)
+    }
+
+    /// Returns the index (starting from 0) of the face that was being parsed
+    /// when the error occurred, for errors within an AHF family file, or
+    /// `None` otherwise.
+    pub fn face(&self) -> Option<usize> {
+        self.face
+    }
+
+    /// Returns the kind of error that occurred.
+    pub fn kind(&self) -> &ParseErrorKind {
+        &self.kind
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)?;
+        match (self.face, self.item) {
+            (Some(face), Some(("face", _))) | (Some(face), None) => {
+                write!(f, " (face {})", face)?;
+            }
+            (Some(face), Some((noun, index))) => {
+                write!(f, " (face {}, {} {})", face, noun, index)?;
+            }
+            (None, Some((noun, index))) => {
+                write!(f, " ({} {})", noun, index)?;
+            }
+            (None, None) => {}
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl From<ParseError> for io::Error {
+    fn from(error: ParseError) -> io::Error {
+        let kind = if error.kind == ParseErrorKind::UnexpectedEof {
+            io::ErrorKind::UnexpectedEof
+        } else {
+            io::ErrorKind::InvalidData
+        };
+        io::Error::new(kind, error)
+    }
+}
+
+// ========================================================================= //
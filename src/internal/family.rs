@@ -0,0 +1,438 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use crate::internal::error::{ParseError, ParseErrorKind};
+use crate::internal::font::Font;
+use crate::internal::options::ReadOptions;
+use crate::internal::util::{write_quoted_string, TextReader};
+use std::io::{self, Read, Write};
+
+// ========================================================================= //
+
+const STYLE_BOLD: u32 = 1;
+const STYLE_ITALIC: u32 = 2;
+
+// ========================================================================= //
+
+/// The style of a face within a `FontFamily`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct FontStyle {
+    bold: bool,
+    italic: bool,
+}
+
+impl FontStyle {
+    /// Returns the regular (neither bold nor italic) style.
+    pub fn new() -> FontStyle {
+        FontStyle::default()
+    }
+
+    /// Sets whether this style is bold.
+    pub fn bold(mut self, bold: bool) -> FontStyle {
+        self.bold = bold;
+        self
+    }
+
+    /// Sets whether this style is italic.
+    pub fn italic(mut self, italic: bool) -> FontStyle {
+        self.italic = italic;
+        self
+    }
+
+    /// Returns true if this style is bold.
+    pub fn is_bold(&self) -> bool {
+        self.bold
+    }
+
+    /// Returns true if this style is italic.
+    pub fn is_italic(&self) -> bool {
+        self.italic
+    }
+
+    fn to_flags(self) -> u32 {
+        let mut flags = 0;
+        if self.bold {
+            flags |= STYLE_BOLD;
+        }
+        if self.italic {
+            flags |= STYLE_ITALIC;
+        }
+        flags
+    }
+
+    fn from_flags(flags: u32) -> FontStyle {
+        FontStyle {
+            bold: flags & STYLE_BOLD != 0,
+            italic: flags & STYLE_ITALIC != 0,
+        }
+    }
+}
+
+// ========================================================================= //
+
+/// A single named face within a `FontFamily`.
+#[derive(Clone)]
+pub struct FontFace {
+    name: String,
+    style: FontStyle,
+    font: Font,
+}
+
+impl FontFace {
+    /// Returns the name of this face (e.g. `"bold"` or `"small"`).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the style of this face.
+    pub fn style(&self) -> FontStyle {
+        self.style
+    }
+
+    /// Sets the style of this face.
+    pub fn set_style(&mut self, style: FontStyle) {
+        self.style = style;
+    }
+
+    /// Returns the font for this face.
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
+    /// Returns a mutable reference to the font for this face.
+    pub fn font_mut(&mut self) -> &mut Font {
+        &mut self.font
+    }
+}
+
+// ========================================================================= //
+
+/// A collection of named fonts (such as the regular, bold, and small versions
+/// of a UI font) that are stored together in a single file.  Each face is a
+/// complete `Font`, with its own glyph height, baseline, and default glyph.
+#[derive(Clone)]
+pub struct FontFamily {
+    name: String,
+    faces: Vec<FontFace>,
+}
+
+impl FontFamily {
+    /// Creates a new, empty font family.
+    pub fn new() -> FontFamily {
+        FontFamily { name: String::new(), faces: Vec::new() }
+    }
+
+    /// Returns the name of this family (or empty string if it doesn't have
+    /// one).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets the name of this family.
+    pub fn set_name<S: Into<String>>(&mut self, name: S) {
+        self.name = name.into();
+    }
+
+    /// Returns the faces in this family, in the order they were added.
+    pub fn faces(&self) -> &[FontFace] {
+        &self.faces
+    }
+
+    /// Adds a face to this family.  If the family already has a face with
+    /// the given name, that face is replaced (keeping its position);
+    /// otherwise, the new face is added at the end.
+    pub fn add_face<S: Into<String>>(
+        &mut self,
+        name: S,
+        style: FontStyle,
+        font: Font,
+    ) {
+        let face = FontFace { name: name.into(), style, font };
+        match self.faces.iter_mut().find(|other| other.name == face.name) {
+            Some(other) => *other = face,
+            None => self.faces.push(face),
+        }
+    }
+
+    /// Removes the face with the given name from this family, returning its
+    /// font, if there was such a face.
+    pub fn remove_face(&mut self, name: &str) -> Option<Font> {
+        let index = self.faces.iter().position(|face| face.name == name)?;
+        Some(self.faces.remove(index).font)
+    }
+
+    /// Returns the font for the face with the given name, if any.
+    pub fn face(&self, name: &str) -> Option<&Font> {
+        self.faces.iter().find(|face| face.name == name).map(|face| &face.font)
+    }
+
+    /// Returns a mutable reference to the font for the face with the given
+    /// name, if any.
+    pub fn face_mut(&mut self, name: &str) -> Option<&mut Font> {
+        self.faces
+            .iter_mut()
+            .find(|face| face.name == name)
+            .map(|face| &mut face.font)
+    }
+
+    /// Returns the font for the first face with the given style, if any.
+    pub fn face_with_style(&self, style: FontStyle) -> Option<&Font> {
+        self.faces
+            .iter()
+            .find(|face| face.style == style)
+            .map(|face| &face.font)
+    }
+
+    /// Reads a font family from an AHF family file.  A plain AHF file is
+    /// also accepted, and is read as a family with a single regular face,
+    /// named after the font.
+    pub fn read<R: Read>(reader: R) -> io::Result<FontFamily> {
+        FontFamily::read_with_options(reader, &ReadOptions::new())
+    }
+
+    /// Reads a font family from an AHF family file (or plain AHF file),
+    /// using the given parsing options.
+    pub fn read_with_options<R: Read>(
+        reader: R,
+        options: &ReadOptions,
+    ) -> io::Result<FontFamily> {
+        let mut reader = TextReader::with_options(reader, options);
+        reader.read_exactly(b"ahf")?;
+        let mut family = FontFamily::new();
+        if reader.peek()? != Some(b'f') {
+            let font = Font::read_after_magic(&mut reader)?;
+            family.add_face(font.name().to_string(), FontStyle::new(), font);
+            return Ok(family);
+        }
+        reader.read_exactly(b"f")?;
+        let version = reader.read_header_uint(b' ')?;
+        if version > 0 {
+            let kind = ParseErrorKind::UnsupportedVersion(version);
+            return Err(reader.error(kind));
+        }
+        reader.read_exactly(b"n")?;
+        let num_faces = reader.read_header_uint(b'\n')?;
+        family.name = reader.read_quoted_string()?;
+        reader.read_newline()?;
+        for index in 0..(num_faces as usize) {
+            reader.set_item("face", index);
+            reader.read_newline()?;
+            reader.read_exactly(b"face ")?;
+            let name = reader.read_quoted_string()?;
+            if family.faces.iter().any(|face| face.name == name) {
+                let kind = ParseErrorKind::DuplicateFaceName(name);
+                return Err(reader.error(kind));
+            }
+            reader.read_exactly(b" f")?;
+            let style = FontStyle::from_flags(reader.read_hex_u32(b'\n')?);
+            reader.read_exactly(b"ahf")?;
+            let font = Font::read_after_magic(&mut reader)
+                .map_err(|error| in_face(error, index))?;
+            family.faces.push(FontFace { name, style, font });
+        }
+        Ok(family)
+    }
+
+    /// Writes the font family to an AHF family file.
+    #[allow(clippy::write_with_newline)]
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "ahff0 n{}\n", self.faces.len())?;
+        write_quoted_string(writer.by_ref(), &self.name)?;
+        write!(writer, "\n")?;
+        for face in self.faces.iter() {
+            write!(writer, "\nface ")?;
+            write_quoted_string(writer.by_ref(), &face.name)?;
+            write!(writer, " f{:X}\n", face.style.to_flags())?;
+            face.font.write(writer.by_ref())?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for FontFamily {
+    fn default() -> FontFamily {
+        FontFamily::new()
+    }
+}
+
+/// Marks a parse error from reading a face's font as having occurred within
+/// that face, since the glyph index in the error refers only to the font.
+fn in_face(error: io::Error, index: usize) -> io::Error {
+    match ParseError::from_io_error(&error) {
+        Some(parse_error) => parse_error.clone().in_face(index).into(),
+        None => error,
+    }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAMILY: &[u8] = b"ahff0 n2\n\
+        \"UI\"\n\
+        \n\
+        face \"regular\" f0\n\
+        ahf0 h1 b1 n1\n\
+        \n\
+        def w0 l0 r0\n\
+        \n\
+        \n\
+        'a' w1 l0 r2\n\
+        1\n\
+        \n\
+        face \"bold\" f1\n\
+        ahf1 f1 p0 h2 b1 s3 n0\n\
+        \"UI Bold\"\n\
+        \n\
+        def w1 l0 r2\n\
+        1\n\
+        1\n";
+
+    #[test]
+    fn read_font_family() {
+        let family = FontFamily::read(FAMILY).expect("failed to read family");
+        assert_eq!(family.name(), "UI");
+        assert_eq!(family.faces().len(), 2);
+        assert_eq!(family.faces()[0].name(), "regular");
+        assert_eq!(family.faces()[1].name(), "bold");
+        let regular = family.face("regular").unwrap();
+        assert_eq!(regular.glyph_height(), 1);
+        assert_eq!(regular.chars().collect::<Vec<char>>(), vec!['a']);
+        let bold = family.face_with_style(FontStyle::new().bold(true));
+        let bold = bold.unwrap();
+        assert_eq!(bold.name(), "UI Bold");
+        assert_eq!(bold.glyph_height(), 2);
+        assert_eq!(bold.line_spacing(), 3);
+        assert!(family.face("small").is_none());
+        assert!(family
+            .face_with_style(FontStyle::new().italic(true))
+            .is_none());
+    }
+
+    #[test]
+    fn font_family_round_trip() {
+        let family = FontFamily::read(FAMILY).expect("failed to read family");
+        let mut output = Vec::<u8>::new();
+        family.write(&mut output).expect("failed to write family");
+        assert_eq!(&output as &[u8], FAMILY);
+    }
+
+    #[test]
+    fn read_plain_font_as_family() {
+        let input: &[u8] = b"ahf1 f1 p0 h1 b1 s1 n0\n\
+            \"Mono\"\n\
+            \n\
+            def w0 l0 r0\n\
+            \n";
+        let family = FontFamily::read(input).expect("failed to read family");
+        assert_eq!(family.faces().len(), 1);
+        assert_eq!(family.faces()[0].name(), "Mono");
+        assert_eq!(family.faces()[0].style(), FontStyle::new());
+    }
+
+    #[test]
+    fn add_and_remove_faces() {
+        let mut family = FontFamily::new();
+        family.add_face(
+            "regular",
+            FontStyle::new(),
+            Font::with_glyph_height(1),
+        );
+        family.add_face("small", FontStyle::new(), Font::with_glyph_height(2));
+        family.add_face(
+            "regular",
+            FontStyle::new(),
+            Font::with_glyph_height(3),
+        );
+        let names: Vec<&str> =
+            family.faces().iter().map(FontFace::name).collect();
+        assert_eq!(names, vec!["regular", "small"]);
+        assert_eq!(family.face("regular").unwrap().glyph_height(), 3);
+        family.face_mut("small").unwrap().set_baseline(1);
+        assert_eq!(family.face("small").unwrap().baseline(), 1);
+        assert!(family.remove_face("regular").is_some());
+        assert!(family.remove_face("regular").is_none());
+        assert_eq!(family.faces().len(), 1);
+    }
+
+    #[test]
+    fn read_family_with_invalid_face() {
+        let input: &[u8] = b"ahff0 n1\n\
+            \"\"\n\
+            \n\
+            face \"bold\" fZ\n";
+        let error = FontFamily::read(input).err().unwrap();
+        let error = ParseError::from_io_error(&error).unwrap();
+        assert_eq!((error.line(), error.column()), (4, 14));
+        assert_eq!(error.index(), Some(0));
+        assert_eq!(error.kind(), &ParseErrorKind::InvalidHexDigit(b'Z'));
+    }
+
+    #[test]
+    fn read_family_with_duplicate_face() {
+        let input: &[u8] = b"ahff0 n2\n\
+            \"\"\n\
+            \n\
+            face \"a\" f0\n\
+            ahf0 h1 b1 n0\n\
+            \n\
+            def w0 l0 r0\n\
+            \n\
+            \n\
+            face \"a\" f1\n";
+        let error = FontFamily::read(input).err().unwrap();
+        let error = ParseError::from_io_error(&error).unwrap();
+        assert_eq!((error.line(), error.column()), (10, 8));
+        assert_eq!(error.index(), Some(1));
+        let kind = ParseErrorKind::DuplicateFaceName("a".to_string());
+        assert_eq!(error.kind(), &kind);
+    }
+
+    #[test]
+    fn read_family_with_invalid_glyph_in_second_face() {
+        let input: &[u8] = b"ahff0 n2\n\
+            \"\"\n\
+            \n\
+            face \"a\" f0\n\
+            ahf0 h1 b1 n0\n\
+            \n\
+            def w0 l0 r0\n\
+            \n\
+            \n\
+            face \"b\" f1\n\
+            ahf0 h1 b1 n0\n\
+            \n\
+            def w1 l0 r1\n\
+            Z\n";
+        let error = FontFamily::read(input).err().unwrap();
+        let error = ParseError::from_io_error(&error).unwrap();
+        assert_eq!((error.line(), error.column()), (14, 1));
+        assert_eq!(error.face(), Some(1));
+        assert_eq!(error.index(), Some(0));
+        assert_eq!(error.kind(), &ParseErrorKind::InvalidPixel(b'Z'));
+        assert_eq!(
+            error.to_string(),
+            "line 14, column 1 (face 1, glyph 0): invalid pixel character: 'Z'"
+        );
+    }
+}
+
+// ========================================================================= //
@@ -0,0 +1,1666 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use crate::internal::collect::Collection;
+use crate::internal::color::Color;
+use crate::internal::error::ParseErrorKind;
+use crate::internal::image::Image;
+use crate::internal::options::ReadOptions;
+use crate::internal::palette::Palette;
+use crate::internal::util::{
+    write_comment, write_list_of_i16s, write_quoted_char, write_quoted_string,
+    TextReader, MAX_HEADER_VALUE,
+};
+use std::cmp::{max, min};
+use std::collections::{btree_map, BTreeMap};
+use std::io::{self, Error, ErrorKind, Read, Write};
+use std::ops::Bound;
+use std::ops::Deref;
+use std::sync::{Arc, OnceLock};
+
+// ========================================================================= //
+
+const FLAG_FONT_NAME: u32 = 1;
+const FLAG_STRING_TAGS: u32 = 2;
+const FLAG_METADATA_INTS: u32 = 4;
+const FLAG_KERNING: u32 = 8;
+const FLAG_GLYPH_OFFSETS: u32 = 16;
+
+// ========================================================================= //
+
+/// An image for a single character in a font, along with spacing information.
+#[derive(Clone)]
+pub struct Glyph {
+    image: Image,
+    left: i32,
+    right: i32,
+    top: i32,
+    ink: OnceLock<Option<(u32, u32, u32, u32)>>,
+}
+
+impl Glyph {
+    /// Creates a new glyph with the given image and left/right edges.  The
+    /// glyph's top offset is initially zero.
+    pub fn new(image: Image, left: i32, right: i32) -> Glyph {
+        Glyph { image, left, right, top: 0, ink: OnceLock::new() }
+    }
+
+    /// Returns the image for this glyph.
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// Returns a mutable reference to the image for this glyph.
+    pub fn image_mut(&mut self) -> &mut Image {
+        self.ink = OnceLock::new();
+        &mut self.image
+    }
+
+    /// Returns the comment attached to this glyph (or empty string if it
+    /// doesn't have one).  This is the same as the comment on the glyph's
+    /// image.
+    pub fn comment(&self) -> &str {
+        self.image.comment()
+    }
+
+    /// Sets the comment attached to this glyph.
+    pub fn set_comment<S: Into<String>>(&mut self, comment: S) {
+        self.image.set_comment(comment);
+    }
+
+    /// Returns the string tag for this glyph (or empty string if it doesn't
+    /// have one).  This is the same as the tag on the glyph's image.
+    pub fn tag(&self) -> &str {
+        self.image.tag()
+    }
+
+    /// Sets the string tag for this glyph.
+    pub fn set_tag<S: Into<String>>(&mut self, tag: S) {
+        self.image.set_tag(tag);
+    }
+
+    /// Returns the integer metadata for this glyph (if any).  This is the
+    /// same as the metadata on the glyph's image.
+    pub fn metadata(&self) -> &[i16] {
+        self.image.metadata()
+    }
+
+    /// Sets the integer metadata for this glyph.
+    pub fn set_metadata(&mut self, metadata: Vec<i16>) {
+        self.image.set_metadata(metadata);
+    }
+
+    /// Returns the left edge of this glyph, in pixels.  This is the distance
+    /// (possibly negative) to the right of the left edge of this glyph's image
+    /// at which this glyph should start.  Normally this is zero, but can be
+    /// positive if the glyph needs extra space on the left, or negative if the
+    /// image should extend to the left (e.g. the tail on a lowercase j).
+    pub fn left_edge(&self) -> i32 {
+        self.left
+    }
+
+    /// Sets the left edge for this glyph.
+    pub fn set_left_edge(&mut self, left: i32) {
+        self.left = left;
+    }
+
+    /// Returns the right edge of this glyph, in pixels.  This is the distance
+    /// (possibly negative) to the right of the left edge of this glyph's image
+    /// at which the next glyph in the text should start, which may be slightly
+    /// different than the width of this glyph's image (e.g. if this is an
+    /// italic glyph whose image should extend a bit over that of the next
+    /// glyph).
+    pub fn right_edge(&self) -> i32 {
+        self.right
+    }
+
+    /// Sets the right edge for this glyph.
+    pub fn set_right_edge(&mut self, right: i32) {
+        self.right = right;
+    }
+
+    /// Returns the top offset of this glyph, in pixels.  This is the distance
+    /// (possibly negative) down from the top of the font's glyph cell at which
+    /// the top row of this glyph's image should be drawn.  Normally this is
+    /// zero, but can be positive for a short glyph (e.g. a comma, whose image
+    /// need only cover the rows near the baseline), or negative for a glyph
+    /// that must extend above the cell (e.g. an accented capital).
+    pub fn top(&self) -> i32 {
+        self.top
+    }
+
+    /// Sets the top offset for this glyph.
+    pub fn set_top(&mut self, top: i32) {
+        self.top = top;
+    }
+
+    /// Returns the ink bounds of this glyph: the smallest rectangle
+    /// containing all of the glyph image's non-transparent pixels, as `(x, y,
+    /// width, height)`.  The x-position is relative to the glyph's left edge,
+    /// and the y-position is relative to the top of the font's glyph cell
+    /// (that is, it includes the glyph's top offset).
+    /// Returns `None` if the glyph has no non-transparent pixels.  The result
+    /// is cached until the glyph's image is next modified.
+    pub fn ink_bounds(&self) -> Option<(i32, i32, u32, u32)> {
+        let ink = self.ink.get_or_init(|| {
+            let image = &self.image;
+            let mut bounds: Option<(u32, u32, u32, u32)> = None;
+            for row in 0..image.height() {
+                for col in 0..image.width() {
+                    if image[(col, row)] == Color::C0 {
+                        continue;
+                    }
+                    bounds = Some(match bounds {
+                        None => (col, row, col, row),
+                        Some((left, top, right, bottom)) => (
+                            min(left, col),
+                            min(top, row),
+                            max(right, col),
+                            max(bottom, row),
+                        ),
+                    });
+                }
+            }
+            bounds.map(|(left, top, right, bottom)| {
+                (left, top, right - left + 1, bottom - top + 1)
+            })
+        });
+        ink.map(|(x, y, width, height)| {
+            (x as i32 - self.left, y as i32 + self.top, width, height)
+        })
+    }
+}
+
+// ========================================================================= //
+
+/// Represents a mapping from characters to glyphs.
+#[derive(Clone)]
+pub struct Font {
+    name: String,
+    glyphs: BTreeMap<char, Arc<Glyph>>,
+    sequences: BTreeMap<String, Arc<Glyph>>,
+    default_glyph: Arc<Glyph>,
+    height: u32,
+    baseline: i32,
+    line_spacing: u32,
+    palettes: Vec<Palette>,
+    kerning: BTreeMap<(char, char), i32>,
+    metrics: OnceLock<(i32, i32)>,
+}
+
+impl Font {
+    /// Creates a new, empty font whose glyphs have the given height, in
+    /// pixels.  The initial default glyph will be a zero-width space, and the
+    /// initial baseline will be equal to the height.
+    pub fn with_glyph_height(height: u32) -> Font {
+        Font {
+            name: String::new(),
+            glyphs: BTreeMap::new(),
+            sequences: BTreeMap::new(),
+            default_glyph: Arc::new(Glyph::new(Image::new(0, height), 0, 0)),
+            height,
+            baseline: height as i32,
+            line_spacing: height,
+            palettes: Vec::new(),
+            kerning: BTreeMap::new(),
+            metrics: OnceLock::new(),
+        }
+    }
+
+    /// Returns the name of this font (or empty string if it doesn't have
+    /// one).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets the name of this font.
+    pub fn set_name<S: Into<String>>(&mut self, name: S) {
+        self.name = name.into();
+    }
+
+    /// Returns the height of this font's glyph cell, in pixels.  A glyph
+    /// whose top offset is zero and whose image has this height exactly
+    /// covers the cell; other glyphs may be shorter or taller, and may be
+    /// offset vertically within (or beyond) the cell.
+    pub fn glyph_height(&self) -> u32 {
+        self.height
+    }
+
+    /// Sets the height of this font's glyph cell, in pixels.  This doesn't
+    /// change any of the glyphs' images.
+    pub fn set_glyph_height(&mut self, height: u32) {
+        self.height = height;
+    }
+
+    /// Returns the baseline height for this font, measured in pixels down from
+    /// the top of the glyph.  The baseline is the line on which e.g. most
+    /// numerals and capital letters sit, and below which e.g. the tail on a
+    /// lowercase 'g' or 'y' extends.
+    pub fn baseline(&self) -> i32 {
+        self.baseline
+    }
+
+    /// Sets the baseline value for this font, measured in pixels down from the
+    /// top of the glyph.  It is customary for the baseline to be in the range
+    /// (0, `height`], but note that this is not actually required.
+    pub fn set_baseline(&mut self, baseline: i32) {
+        self.invalidate_metrics();
+        self.baseline = baseline;
+    }
+
+    /// Returns the line spacing for this font, in pixels.  This is the
+    /// vertical distance between the baselines of successive lines of text.
+    /// For a new font, this is initially equal to the glyph height.
+    pub fn line_spacing(&self) -> u32 {
+        self.line_spacing
+    }
+
+    /// Sets the line spacing for this font, in pixels.
+    pub fn set_line_spacing(&mut self, line_spacing: u32) {
+        self.line_spacing = line_spacing;
+    }
+
+    /// Returns the palettes embedded in this font.
+    pub fn palettes(&self) -> &[Palette] {
+        &self.palettes
+    }
+
+    /// Returns a mutable reference to the palettes embedded in this font.
+    pub fn palettes_mut(&mut self) -> &mut Vec<Palette> {
+        &mut self.palettes
+    }
+
+    /// Gets the glyph for the given character, if any.  If you instead want to
+    /// get the default glyph for characters that have no glyph, use the index
+    /// operator.
+    pub fn get_char_glyph(&self, chr: char) -> Option<&Glyph> {
+        match self.glyphs.get(&chr) {
+            Some(glyph) => Some(glyph.deref()),
+            None => None,
+        }
+    }
+
+    /// Gets a mutable reference to the glyph for the given character, if any.
+    pub fn get_char_glyph_mut(&mut self, chr: char) -> Option<&mut Glyph> {
+        self.invalidate_metrics();
+        match self.glyphs.get_mut(&chr) {
+            Some(glyph) => Some(Arc::make_mut(glyph)),
+            None => None,
+        }
+    }
+
+    /// Sets the glyph for the given character.
+    pub fn set_char_glyph(&mut self, chr: char, glyph: Glyph) {
+        self.invalidate_metrics();
+        self.glyphs.insert(chr, Arc::new(glyph));
+    }
+
+    /// Removes the glyph for the given character from the font.  After calling
+    /// this, the font's default glyph will be used for this character.
+    pub fn remove_char_glyph(&mut self, chr: char) {
+        self.invalidate_metrics();
+        self.glyphs.remove(&chr);
+    }
+
+    /// Gets the glyph for the given key, if any.  A key is either a single
+    /// character (in which case this is the same as `get_char_glyph`) or a
+    /// multi-character sequence, such as a ligature (`"fi"`) or a combining
+    /// sequence (`"e\u{301}"`).  When text is drawn, the longest key that
+    /// matches at each position is used.
+    pub fn get_str_glyph(&self, key: &str) -> Option<&Glyph> {
+        match single_char(key) {
+            Some(chr) => self.get_char_glyph(chr),
+            None => self.sequences.get(key).map(Deref::deref),
+        }
+    }
+
+    /// Gets a mutable reference to the glyph for the given key, if any.
+    pub fn get_str_glyph_mut(&mut self, key: &str) -> Option<&mut Glyph> {
+        match single_char(key) {
+            Some(chr) => self.get_char_glyph_mut(chr),
+            None => {
+                self.invalidate_metrics();
+                self.sequences.get_mut(key).map(Arc::make_mut)
+            }
+        }
+    }
+
+    /// Sets the glyph for the given key.  Panics if the key is empty.
+    pub fn set_str_glyph(&mut self, key: &str, glyph: Glyph) {
+        assert!(!key.is_empty(), "glyph key must not be empty");
+        match single_char(key) {
+            Some(chr) => self.set_char_glyph(chr, glyph),
+            None => {
+                self.invalidate_metrics();
+                self.sequences.insert(key.to_string(), Arc::new(glyph));
+            }
+        }
+    }
+
+    /// Removes the glyph for the given key from the font.
+    pub fn remove_str_glyph(&mut self, key: &str) {
+        match single_char(key) {
+            Some(chr) => self.remove_char_glyph(chr),
+            None => {
+                self.invalidate_metrics();
+                self.sequences.remove(key);
+            }
+        }
+    }
+
+    /// Returns the longest key with a glyph in this font that `text` starts
+    /// with, as the length of the key in bytes along with its glyph, or
+    /// `None` if the first character of `text` has no glyph of its own.
+    pub(crate) fn match_glyph(&self, text: &str) -> Option<(usize, &Glyph)> {
+        let first = text.chars().next()?;
+        let start = first.to_string();
+        let end = char::from_u32(first as u32 + 1).map(String::from);
+        let candidates = match end {
+            Some(ref end) => self.sequences.range::<str, _>((
+                Bound::Included(start.as_str()),
+                Bound::Excluded(end.as_str()),
+            )),
+            None => self.sequences.range::<str, _>((
+                Bound::Included(start.as_str()),
+                Bound::Unbounded,
+            )),
+        };
+        let longest = candidates
+            .filter(|(key, _)| text.starts_with(key.as_str()))
+            .max_by_key(|(key, _)| key.len());
+        match longest {
+            Some((key, glyph)) => Some((key.len(), glyph.deref())),
+            None => self
+                .get_char_glyph(first)
+                .map(|glyph| (first.len_utf8(), glyph)),
+        }
+    }
+
+    /// Gets the default glyph for this font, which is used for characters that
+    /// don't have a glyph.
+    pub fn default_glyph(&self) -> &Glyph {
+        &self.default_glyph
+    }
+
+    /// Gets a mutable reference to the default glyph for this font.
+    pub fn default_glyph_mut(&mut self) -> &mut Glyph {
+        self.invalidate_metrics();
+        Arc::make_mut(&mut self.default_glyph)
+    }
+
+    /// Sets the default glyph for this font.
+    pub fn set_default_glyph(&mut self, glyph: Glyph) {
+        self.invalidate_metrics();
+        self.default_glyph = Arc::new(glyph);
+    }
+
+    /// Returns the maximum distance, in pixels, that the ink of any glyph in
+    /// this font (including the default glyph) extends above the baseline,
+    /// or zero if no glyph has ink above the baseline.
+    pub fn ascent(&self) -> i32 {
+        self.ink_metrics().0
+    }
+
+    /// Returns the maximum distance, in pixels, that the ink of any glyph in
+    /// this font (including the default glyph) extends below the baseline,
+    /// or zero if no glyph has ink below the baseline.
+    pub fn descent(&self) -> i32 {
+        self.ink_metrics().1
+    }
+
+    /// Returns how far, in pixels, the glyph cell and the images of all
+    /// glyphs extend above and below the baseline.  Unlike `ascent` and
+    /// `descent`, this counts whole glyph images rather than just their ink,
+    /// and is never less than the glyph cell, so it gives the line box needed
+    /// to draw any glyph without clipping.
+    pub(crate) fn image_extent(&self) -> (i32, i32) {
+        let mut top = 0;
+        let mut bottom = self.height as i32;
+        for glyph in self.all_glyphs() {
+            top = min(top, glyph.top());
+            bottom = max(bottom, glyph.top() + glyph.image().height() as i32);
+        }
+        (self.baseline - top, bottom - self.baseline)
+    }
+
+    /// Returns the recommended line height for this font, in pixels.  This is
+    /// the sum of the ascent and descent, which is the smallest distance
+    /// between baselines that keeps the ink of successive lines of text from
+    /// overlapping.
+    pub fn line_height(&self) -> u32 {
+        (self.ascent() + self.descent()) as u32
+    }
+
+    /// Returns the cached `(ascent, descent)` pair, computing it if
+    /// necessary.
+    fn ink_metrics(&self) -> (i32, i32) {
+        *self.metrics.get_or_init(|| {
+            let mut ascent = 0;
+            let mut descent = 0;
+            for glyph in self.all_glyphs() {
+                if let Some((_, y, _, height)) = glyph.ink_bounds() {
+                    ascent = max(ascent, self.baseline - y);
+                    descent = max(descent, y + height as i32 - self.baseline);
+                }
+            }
+            (ascent, descent)
+        })
+    }
+
+    /// Returns a copy of this font with every glyph (including the default
+    /// glyph) replaced by the result of `f`.  The new font has the same glyph
+    /// height as this one.
+    pub(crate) fn map_glyphs<F: FnMut(&Glyph) -> Glyph>(
+        &self,
+        mut f: F,
+    ) -> Font {
+        let default_glyph = Arc::new(f(&self.default_glyph));
+        let glyphs = self
+            .glyphs
+            .iter()
+            .map(|(&chr, glyph)| (chr, Arc::new(f(glyph))))
+            .collect();
+        let sequences = self
+            .sequences
+            .iter()
+            .map(|(key, glyph)| (key.clone(), Arc::new(f(glyph))))
+            .collect();
+        Font {
+            name: self.name.clone(),
+            glyphs,
+            sequences,
+            default_glyph,
+            height: self.height,
+            baseline: self.baseline,
+            line_spacing: self.line_spacing,
+            palettes: self.palettes.clone(),
+            kerning: self.kerning.clone(),
+            metrics: OnceLock::new(),
+        }
+    }
+
+    /// Returns an iterator over every glyph in the font, starting with the
+    /// default glyph.
+    fn all_glyphs(&self) -> impl Iterator<Item = &Arc<Glyph>> {
+        Some(&self.default_glyph)
+            .into_iter()
+            .chain(self.glyphs.values())
+            .chain(self.sequences.values())
+    }
+
+    fn invalidate_metrics(&mut self) {
+        self.metrics = OnceLock::new();
+    }
+
+    /// Returns the kerning adjustment, in pixels, to apply between the given
+    /// pair of characters when `right` immediately follows `left` in a
+    /// string.  A negative adjustment moves the two glyphs closer together.
+    /// Returns zero if no adjustment has been set for the pair.
+    pub fn get_kerning(&self, left: char, right: char) -> i32 {
+        self.kerning.get(&(left, right)).copied().unwrap_or(0)
+    }
+
+    /// Sets the kerning adjustment, in pixels, to apply between the given
+    /// pair of characters.  Setting an adjustment of zero removes the pair
+    /// from the font's kerning table.
+    pub fn set_kerning(&mut self, left: char, right: char, adjustment: i32) {
+        if adjustment == 0 {
+            self.kerning.remove(&(left, right));
+        } else {
+            self.kerning.insert((left, right), adjustment);
+        }
+    }
+
+    /// Returns an iterator over the character pairs in this font's kerning
+    /// table, along with their adjustments, in sorted order.
+    pub fn kerning_pairs(&self) -> KerningPairs<'_> {
+        KerningPairs { iter: self.kerning.iter() }
+    }
+
+    /// Returns an iterator over the characters that have glyphs in this font.
+    /// This doesn't include multi-character keys; see `sequences`.
+    pub fn chars(&self) -> Chars<'_> {
+        Chars { iter: self.glyphs.keys() }
+    }
+
+    /// Returns an iterator over the multi-character keys that have glyphs in
+    /// this font, in sorted order.
+    pub fn sequences(&self) -> Sequences<'_> {
+        Sequences { iter: self.sequences.keys() }
+    }
+
+    /// Reads a font from an AHF file.
+    pub fn read<R: Read>(reader: R) -> io::Result<Font> {
+        Font::read_with_options(reader, &ReadOptions::new())
+    }
+
+    /// Reads a font from an AHF file, using the given parsing options.
+    pub fn read_with_options<R: Read>(
+        reader: R,
+        options: &ReadOptions,
+    ) -> io::Result<Font> {
+        let mut reader = TextReader::with_options(reader, options);
+        reader.read_exactly(b"ahf")?;
+        Font::read_after_magic(&mut reader)
+    }
+
+    /// Reads the rest of an AHF font, starting just after the `ahf` at the
+    /// start of its header line.
+    pub(crate) fn read_after_magic<R: Read>(
+        reader: &mut TextReader<R>,
+    ) -> io::Result<Font> {
+        let version = reader.read_header_uint(b' ')?;
+        if version > 1 {
+            let kind = ParseErrorKind::UnsupportedVersion(version);
+            return Err(reader.error(kind));
+        }
+        let (flags, num_palettes) = if version == 1 {
+            reader.read_exactly(b"f")?;
+            let flags = reader.read_hex_u32(b' ')?;
+            reader.read_exactly(b"p")?;
+            let num_palettes = reader.read_header_uint(b' ')? as usize;
+            (flags, num_palettes)
+        } else {
+            (0, 0)
+        };
+        reader.read_exactly(b"h")?;
+        let height = reader.read_header_uint(b' ')?;
+        reader.read_exactly(b"b")?;
+        let baseline = reader.read_header_int(b' ')?;
+        let line_spacing = if version == 1 {
+            reader.read_exactly(b"s")?;
+            reader.read_header_uint(b' ')?
+        } else {
+            height
+        };
+        reader.read_exactly(b"n")?;
+        let num_glyphs = reader.read_header_uint(b'\n')?;
+        let name = if flags & FLAG_FONT_NAME != 0 {
+            let name = reader.read_quoted_string()?;
+            reader.read_newline()?;
+            name
+        } else {
+            String::new()
+        };
+
+        let mut palettes = Vec::with_capacity(num_palettes);
+        if num_palettes > 0 {
+            reader.read_newline()?;
+        }
+        for index in 0..num_palettes {
+            reader.set_item("palette", index);
+            palettes.push(Palette::read(reader)?);
+        }
+
+        reader.set_item("glyph", 0);
+        reader.read_newline()?;
+        let comment = reader.read_comments()?;
+        reader.read_exactly(b"def ")?;
+        let mut default_glyph = Font::read_glyph(reader, height, flags)?;
+        default_glyph.set_comment(comment);
+
+        let mut glyphs = BTreeMap::new();
+        let mut sequences = BTreeMap::new();
+        for index in 0..(num_glyphs as usize) {
+            reader.set_item("glyph", index + 1);
+            reader.read_newline()?;
+            let comment = reader.read_comments()?;
+            let key = if reader.peek()? == Some(b'"') {
+                let key = reader.read_quoted_string()?;
+                if key.is_empty() {
+                    return Err(reader.error(ParseErrorKind::EmptyGlyphKey));
+                }
+                key
+            } else {
+                reader.read_quoted_char()?.to_string()
+            };
+            reader.read_exactly(b" ")?;
+            let mut glyph = Font::read_glyph(reader, height, flags)?;
+            glyph.set_comment(comment);
+            match single_char(&key) {
+                Some(chr) => glyphs.insert(chr, Arc::new(glyph)),
+                None => sequences.insert(key, Arc::new(glyph)),
+            };
+        }
+
+        let mut kerning = BTreeMap::new();
+        if flags & FLAG_KERNING != 0 {
+            reader.set_item("kerning pair", 0);
+            reader.read_newline()?;
+            reader.read_exactly(b"kerning n")?;
+            let num_pairs = reader.read_header_uint(b'\n')?;
+            for index in 0..(num_pairs as usize) {
+                reader.set_item("kerning pair", index);
+                let left = reader.read_quoted_char()?;
+                reader.read_exactly(b" ")?;
+                let right = reader.read_quoted_char()?;
+                reader.read_exactly(b" ")?;
+                let adjustment = reader.read_header_int(b'\n')?;
+                kerning.insert((left, right), adjustment);
+            }
+        }
+        Ok(Font {
+            name,
+            glyphs,
+            sequences,
+            default_glyph: Arc::new(default_glyph),
+            height,
+            baseline,
+            line_spacing,
+            palettes,
+            kerning,
+            metrics: OnceLock::new(),
+        })
+    }
+
+    fn read_glyph<R: Read>(
+        reader: &mut TextReader<R>,
+        height: u32,
+        flags: u32,
+    ) -> io::Result<Glyph> {
+        reader.read_exactly(b"w")?;
+        let width = reader.read_header_uint(b' ')?;
+        let (height, top) = if flags & FLAG_GLYPH_OFFSETS != 0 {
+            reader.read_exactly(b"h")?;
+            let height = reader.read_header_uint(b' ')?;
+            reader.read_exactly(b"t")?;
+            let top = reader.read_header_int(b' ')?;
+            (height, top)
+        } else {
+            (height, 0)
+        };
+        reader.read_exactly(b"l")?;
+        let left = reader.read_header_int(b' ')?;
+        reader.read_exactly(b"r")?;
+        let right = reader.read_header_int(b'\n')?;
+        let tag = if flags & FLAG_STRING_TAGS != 0 {
+            let tag = reader.read_quoted_string()?;
+            reader.read_newline()?;
+            tag
+        } else {
+            String::new()
+        };
+        let metadata = if flags & FLAG_METADATA_INTS != 0 {
+            let metadata = reader.read_list_of_i16s()?;
+            reader.read_newline()?;
+            metadata
+        } else {
+            Vec::new()
+        };
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for _ in 0..height {
+            reader.read_pixel_row(width, &mut pixels)?;
+        }
+        let image = Image {
+            comment: String::new(),
+            tag,
+            metadata,
+            width,
+            height,
+            pixels: pixels.into_boxed_slice(),
+        };
+        let mut glyph = Glyph::new(image, left, right);
+        glyph.set_top(top);
+        Ok(glyph)
+    }
+
+    /// Converts this font into a collection of images, one per glyph.  The
+    /// first image is the default glyph, which is tagged `"def"` and has
+    /// metadata `[left, right, baseline, line_spacing, glyph_height, top,
+    /// ...]`.  Each subsequent image is tagged with its key (a character or
+    /// multi-character sequence), and has metadata `[left, right, top, ...]`.
+    /// In both cases, any metadata of the glyph itself follows these values.
+    /// Each image is the glyph's own image, so images may differ in height
+    /// if any glyph has its own height (see `Glyph::top`).  The font's
+    /// palettes become the collection's palettes.  The font name, glyph
+    /// string tags, and kerning table are not preserved.
+    ///
+    /// Returns an error if any edge, top offset, the baseline, the line
+    /// spacing, or the glyph height doesn't fit in an `i16`.
+    pub fn to_collection(&self) -> io::Result<Collection> {
+        let mut collection = Collection::new();
+        collection.palettes = self.palettes.clone();
+        let mut image = self.default_glyph.image().clone();
+        image.set_tag(DEFAULT_GLYPH_TAG);
+        let mut metadata = vec![
+            to_i16(self.default_glyph.left_edge())?,
+            to_i16(self.default_glyph.right_edge())?,
+            to_i16(self.baseline)?,
+            to_i16(self.line_spacing as i32)?,
+            to_i16(self.height as i32)?,
+            to_i16(self.default_glyph.top())?,
+        ];
+        metadata.extend_from_slice(self.default_glyph.metadata());
+        image.set_metadata(metadata);
+        collection.images.push(image);
+        let keys = self.glyphs.keys().map(|chr| chr.to_string());
+        let keys = keys.chain(self.sequences.keys().cloned());
+        let glyphs = self.glyphs.values().chain(self.sequences.values());
+        for (key, glyph) in keys.zip(glyphs) {
+            let mut image = glyph.image().clone();
+            image.set_tag(key);
+            let mut metadata = vec![
+                to_i16(glyph.left_edge())?,
+                to_i16(glyph.right_edge())?,
+                to_i16(glyph.top())?,
+            ];
+            metadata.extend_from_slice(glyph.metadata());
+            image.set_metadata(metadata);
+            collection.images.push(image);
+        }
+        Ok(collection)
+    }
+
+    /// Converts a collection of images back into a font.  The collection must
+    /// be laid out as produced by `to_collection`, with the default glyph
+    /// first.
+    pub fn from_collection(collection: &Collection) -> io::Result<Font> {
+        let mut images = collection.images.iter();
+        let default_image = match images.next() {
+            Some(image) if image.tag() == DEFAULT_GLYPH_TAG => image,
+            _ => {
+                let msg = "first image must be tagged as the default glyph";
+                return Err(Error::new(ErrorKind::InvalidData, msg));
+            }
+        };
+        let metadata = default_image.metadata();
+        if metadata.len() < 6 {
+            let msg = "default glyph metadata must have at least six values";
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        let (left, right, baseline) = (metadata[0], metadata[1], metadata[2]);
+        let (line_spacing, height, top) =
+            (metadata[3], metadata[4], metadata[5]);
+        let extra = &metadata[6..];
+        if line_spacing < 0 || height < 0 {
+            let msg = format!(
+                "invalid line spacing ({}) or glyph height ({})",
+                line_spacing, height
+            );
+            return Err(Error::new(ErrorKind::InvalidData, msg));
+        }
+        let mut font = Font::with_glyph_height(height as u32);
+        font.set_baseline(baseline as i32);
+        font.set_line_spacing(line_spacing as u32);
+        font.palettes = collection.palettes.clone();
+        let mut glyph = Glyph::new(
+            glyph_image(default_image, extra),
+            left as i32,
+            right as i32,
+        );
+        glyph.set_top(top as i32);
+        font.set_default_glyph(glyph);
+        for image in images {
+            let key = image.tag();
+            if key.is_empty() {
+                let msg = "glyph image has an empty tag";
+                return Err(Error::new(ErrorKind::InvalidData, msg));
+            }
+            if font.get_str_glyph(key).is_some() {
+                let msg = format!("duplicate glyph for {:?}", key);
+                return Err(Error::new(ErrorKind::InvalidData, msg));
+            }
+            let (left, right, top, extra) = match *image.metadata() {
+                [left, right, top, ref extra @ ..] => {
+                    (left, right, top, extra)
+                }
+                _ => {
+                    let msg = format!(
+                        "metadata for glyph {:?} must have at least three \
+                         values",
+                        key
+                    );
+                    return Err(Error::new(ErrorKind::InvalidData, msg));
+                }
+            };
+            let mut glyph = Glyph::new(
+                glyph_image(image, extra),
+                left as i32,
+                right as i32,
+            );
+            glyph.set_top(top as i32);
+            font.set_str_glyph(key, glyph);
+        }
+        Ok(font)
+    }
+
+    /// Writes the font to an AHF file.
+    ///
+    /// Returns an error if any kerning adjustment is larger in magnitude
+    /// than 65535, which the AHF format can't represent.
+    #[allow(clippy::write_with_newline)]
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for (&(left, right), &adjustment) in self.kerning.iter() {
+            if adjustment.unsigned_abs() > MAX_HEADER_VALUE as u32 {
+                let msg = format!(
+                    "kerning adjustment for {:?} {:?} is too large for an \
+                     AHF file: {}",
+                    left, right, adjustment
+                );
+                return Err(Error::new(ErrorKind::InvalidInput, msg));
+            }
+        }
+        let height = self.glyph_height();
+        let flags = self.format_flags();
+        if flags == 0
+            && self.palettes.is_empty()
+            && self.line_spacing == height
+        {
+            write!(
+                writer,
+                "ahf0 h{} b{} n{}\n",
+                height,
+                self.baseline(),
+                self.glyphs.len() + self.sequences.len()
+            )?;
+        } else {
+            write!(
+                writer,
+                "ahf1 f{:X} p{} h{} b{} s{} n{}\n",
+                flags,
+                self.palettes.len(),
+                height,
+                self.baseline(),
+                self.line_spacing,
+                self.glyphs.len() + self.sequences.len()
+            )?;
+            if flags & FLAG_FONT_NAME != 0 {
+                write_quoted_string(writer.by_ref(), &self.name)?;
+                write!(writer, "\n")?;
+            }
+        }
+        if !self.palettes.is_empty() {
+            write!(writer, "\n")?;
+            for palette in self.palettes.iter() {
+                palette.write(writer.by_ref())?;
+            }
+        }
+        write!(writer, "\n")?;
+        write_comment(writer.by_ref(), self.default_glyph.comment())?;
+        write!(writer, "def ")?;
+        Font::write_glyph(writer.by_ref(), &self.default_glyph, flags)?;
+        for (chr, glyph) in self.glyphs.iter() {
+            write!(writer, "\n")?;
+            write_comment(writer.by_ref(), glyph.comment())?;
+            write_quoted_char(writer.by_ref(), *chr)?;
+            write!(writer, " ")?;
+            Font::write_glyph(writer.by_ref(), glyph, flags)?;
+        }
+        for (key, glyph) in self.sequences.iter() {
+            write!(writer, "\n")?;
+            write_comment(writer.by_ref(), glyph.comment())?;
+            write_quoted_string(writer.by_ref(), key)?;
+            write!(writer, " ")?;
+            Font::write_glyph(writer.by_ref(), glyph, flags)?;
+        }
+        if flags & FLAG_KERNING != 0 {
+            write!(writer, "\nkerning n{}\n", self.kerning.len())?;
+            for (&(left, right), adjustment) in self.kerning.iter() {
+                write_quoted_char(writer.by_ref(), left)?;
+                write!(writer, " ")?;
+                write_quoted_char(writer.by_ref(), right)?;
+                write!(writer, " {}\n", adjustment)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the AHF version 1 format flags needed to represent this font.
+    fn format_flags(&self) -> u32 {
+        let mut flags = 0;
+        if !self.name.is_empty() {
+            flags |= FLAG_FONT_NAME;
+        }
+        if self.all_glyphs().any(|glyph| !glyph.tag().is_empty()) {
+            flags |= FLAG_STRING_TAGS;
+        }
+        if self.all_glyphs().any(|glyph| !glyph.metadata().is_empty()) {
+            flags |= FLAG_METADATA_INTS;
+        }
+        if !self.kerning.is_empty() {
+            flags |= FLAG_KERNING;
+        }
+        if self.all_glyphs().any(|glyph| {
+            glyph.top() != 0 || glyph.image().height() != self.height
+        }) {
+            flags |= FLAG_GLYPH_OFFSETS;
+        }
+        flags
+    }
+
+    #[allow(clippy::write_with_newline)]
+    fn write_glyph<W: Write>(
+        mut writer: W,
+        glyph: &Glyph,
+        flags: u32,
+    ) -> io::Result<()> {
+        let image = glyph.image();
+        let width = image.width();
+        let height = image.height();
+        write!(writer, "w{} ", width)?;
+        if flags & FLAG_GLYPH_OFFSETS != 0 {
+            write!(writer, "h{} t{} ", height, glyph.top())?;
+        }
+        write!(writer, "l{} r{}\n", glyph.left_edge(), glyph.right_edge())?;
+        if flags & FLAG_STRING_TAGS != 0 {
+            write_quoted_string(writer.by_ref(), glyph.tag())?;
+            write!(writer, "\n")?;
+        }
+        if flags & FLAG_METADATA_INTS != 0 {
+            write_list_of_i16s(writer.by_ref(), glyph.metadata())?;
+            write!(writer, "\n")?;
+        }
+        for row in 0..height {
+            for col in 0..width {
+                let color = image[(col, row)];
+                writer.write_all(&[color.to_byte()])?;
+            }
+            write!(writer, "\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the character in `key`, if it consists of exactly one character.
+fn single_char(key: &str) -> Option<char> {
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(chr), None) => Some(chr),
+        _ => None,
+    }
+}
+
+/// The image tag used for the default glyph by `Font::to_collection`.
+const DEFAULT_GLYPH_TAG: &str = "def";
+
+fn to_i16(value: i32) -> io::Result<i16> {
+    if value < (i16::MIN as i32) || value > (i16::MAX as i32) {
+        let msg = format!("value doesn't fit in image metadata: {}", value);
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
+    }
+    Ok(value as i16)
+}
+
+/// Returns a copy of a collection image with its tag removed and its metadata
+/// replaced, suitable for use as a glyph image.
+fn glyph_image(image: &Image, metadata: &[i16]) -> Image {
+    let mut image = image.clone();
+    image.set_tag("");
+    image.set_metadata(metadata.to_vec());
+    image
+}
+
+impl std::ops::Index<char> for Font {
+    type Output = Glyph;
+    fn index(&self, index: char) -> &Glyph {
+        self.glyphs.get(&index).unwrap_or(&self.default_glyph)
+    }
+}
+
+impl std::ops::IndexMut<char> for Font {
+    fn index_mut(&mut self, index: char) -> &mut Glyph {
+        self.invalidate_metrics();
+        Arc::make_mut(match self.glyphs.get_mut(&index) {
+            Some(glyph) => glyph,
+            None => &mut self.default_glyph,
+        })
+    }
+}
+
+// ========================================================================= //
+
+/// An iterator over a the characters that have glyphs in a font.
+pub struct Chars<'a> {
+    iter: btree_map::Keys<'a, char, Arc<Glyph>>,
+}
+
+impl<'a> Iterator for Chars<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        self.iter.next().copied()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for Chars<'a> {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+// ========================================================================= //
+
+/// An iterator over the multi-character glyph keys in a font.
+pub struct Sequences<'a> {
+    iter: btree_map::Keys<'a, String, Arc<Glyph>>,
+}
+
+impl<'a> Iterator for Sequences<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.iter.next().map(String::as_str)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for Sequences<'a> {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+// ========================================================================= //
+
+/// An iterator over the kerning pairs in a font, yielding
+/// `((left, right), adjustment)` values.
+pub struct KerningPairs<'a> {
+    iter: btree_map::Iter<'a, (char, char), i32>,
+}
+
+impl<'a> Iterator for KerningPairs<'a> {
+    type Item = ((char, char), i32);
+
+    fn next(&mut self) -> Option<((char, char), i32)> {
+        self.iter.next().map(|(&pair, &adjustment)| (pair, adjustment))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for KerningPairs<'a> {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::error::ParseError;
+
+    #[test]
+    fn read_font() {
+        let input: &[u8] = b"ahf0 h3 b2 n2\n\
+            \n\
+            def w3 l0 r4\n\
+            101\n\
+            010\n\
+            101\n\
+            \n\
+            '|' w1 l0 r2\n\
+            1\n\
+            1\n\
+            1\n\
+            \n\
+            '\\u{2603}' w2 l0 r4\n\
+            11\n\
+            11\n\
+            00\n";
+        let font = Font::read(input).expect("failed to read font");
+        assert_eq!(font.glyph_height(), 3);
+        assert_eq!(font.baseline(), 2);
+        assert_eq!(font.default_glyph().image().width(), 3);
+        assert_eq!(font.default_glyph().left_edge(), 0);
+        assert_eq!(font.default_glyph().right_edge(), 4);
+        assert_eq!(font['|'].image().width(), 1);
+        assert_eq!(font.chars().len(), 2);
+    }
+
+    #[test]
+    fn read_font_with_invalid_char_escape() {
+        let input: &[u8] = b"ahf0 h1 b1 n2\n\
+            \n\
+            def w1 l0 r1\n\
+            1\n\
+            \n\
+            'a' w1 l0 r2\n\
+            1\n\
+            \n\
+            '\\q' w1 l0 r2\n\
+            1\n";
+        let error = Font::read(input).err().unwrap();
+        let error = ParseError::from_io_error(&error).unwrap();
+        assert_eq!((error.line(), error.column()), (9, 3));
+        assert_eq!(error.index(), Some(2));
+        assert_eq!(error.kind(), &ParseErrorKind::InvalidCharEscape(b'q'));
+    }
+
+    #[test]
+    fn read_font_leniently() {
+        let input: &[u8] = b"ahf0 h2 b2 n1\r\n\
+            \r\n\
+            def w2 l0 r3 \r\n\
+            a1\r\n\
+            1b\r\n\
+            \r\n\
+            '|' w1 l0 r2\r\n\
+            1\r\n\
+            1";
+        assert!(Font::read(input).is_err());
+        let font = Font::read_with_options(input, &ReadOptions::lenient())
+            .expect("failed to read font");
+        assert_eq!(font.default_glyph().right_edge(), 3);
+        assert_eq!(font.default_glyph().image()[(0, 0)], Color::Ca);
+        assert_eq!(font['|'].image().height(), 2);
+    }
+
+    #[test]
+    fn write_font() {
+        let mut font = Font::with_glyph_height(3);
+        font.set_baseline(2);
+
+        let mut img_default = Image::new(3, 3);
+        img_default[(0, 0)] = Color::C1;
+        img_default[(2, 0)] = Color::C1;
+        img_default[(1, 1)] = Color::C1;
+        img_default[(0, 2)] = Color::C1;
+        img_default[(2, 2)] = Color::C1;
+        font.set_default_glyph(Glyph::new(img_default, 0, 4));
+
+        let mut img_snowman = Image::new(2, 3);
+        img_snowman[(0, 0)] = Color::C1;
+        img_snowman[(1, 0)] = Color::C1;
+        img_snowman[(0, 1)] = Color::C1;
+        img_snowman[(1, 1)] = Color::C1;
+        font.set_char_glyph('\u{2603}', Glyph::new(img_snowman, 0, 4));
+
+        let mut img_vbar = Image::new(1, 3);
+        img_vbar[(0, 0)] = Color::C1;
+        img_vbar[(0, 1)] = Color::C1;
+        img_vbar[(0, 2)] = Color::C1;
+        font.set_char_glyph('|', Glyph::new(img_vbar, 0, 2));
+
+        let mut output = Vec::<u8>::new();
+        font.write(&mut output).expect("failed to write font");
+        let mut expected = Vec::<u8>::new();
+        expected.extend_from_slice(
+            b"ahf0 h3 b2 n2\n\
+            \n\
+            def w3 l0 r4\n\
+            101\n\
+            010\n\
+            101\n\
+            \n\
+            '|' w1 l0 r2\n\
+            1\n\
+            1\n\
+            1\n\
+            \n\
+            '\\u{2603}' w2 l0 r4\n\
+            11\n\
+            11\n\
+            00\n",
+        );
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn font_comments_round_trip() {
+        let input: &[u8] = b"ahf0 h1 b1 n2\n\
+            \n\
+            # Shown for missing chars\n\
+            def w1 l0 r2\n\
+            1\n\
+            \n\
+            'a' w1 l0 r2\n\
+            1\n\
+            \n\
+            # Needs more\n\
+            # contrast\n\
+            'b' w1 l0 r2\n\
+            1\n";
+        let font = Font::read(input).expect("failed to read font");
+        assert_eq!(font.default_glyph().comment(), "Shown for missing chars");
+        assert_eq!(font['a'].comment(), "");
+        assert_eq!(font['b'].comment(), "Needs more\ncontrast");
+        let mut output = Vec::<u8>::new();
+        font.write(&mut output).expect("failed to write font");
+        assert_eq!(&output as &[u8], input);
+    }
+
+    #[test]
+    fn font_to_collection() {
+        let mut font = Font::with_glyph_height(2);
+        font.set_baseline(1);
+        font.set_default_glyph(Glyph::new(Image::new(2, 2), 0, 3));
+        let mut img_vbar = Image::new(1, 2);
+        img_vbar.fill_rect(0, 0, 1, 2, Color::C1);
+        font.set_char_glyph('|', Glyph::new(img_vbar, -1, 2));
+        font.set_char_glyph('a', Glyph::new(Image::new(3, 2), 0, 4));
+        let collection = font.to_collection().unwrap();
+        assert_eq!(collection.images.len(), 3);
+        assert_eq!(collection.images[0].tag(), "def");
+        assert_eq!(collection.images[0].metadata(), &[0, 3, 1, 2, 2, 0]);
+        assert_eq!(collection.images[1].tag(), "a");
+        assert_eq!(collection.images[1].metadata(), &[0, 4, 0]);
+        assert_eq!(collection.images[2].tag(), "|");
+        assert_eq!(collection.images[2].metadata(), &[-1, 2, 0]);
+        assert_eq!(collection.images[2][(0, 1)], Color::C1);
+
+        let mut output = Vec::<u8>::new();
+        collection.write(&mut output).unwrap();
+        let collection = Collection::read(&output as &[u8]).unwrap();
+        let font = Font::from_collection(&collection).unwrap();
+        assert_eq!(font.glyph_height(), 2);
+        assert_eq!(font.baseline(), 1);
+        assert_eq!(font.default_glyph().right_edge(), 3);
+        assert_eq!(font.chars().collect::<Vec<char>>(), vec!['a', '|']);
+        assert_eq!(font['|'].left_edge(), -1);
+        assert_eq!(font['|'].image()[(0, 1)], Color::C1);
+        assert_eq!(font['|'].image().tag(), "");
+        assert!(font['|'].image().metadata().is_empty());
+    }
+
+    #[test]
+    fn font_from_invalid_collection() {
+        let mut collection = Collection::new();
+        assert!(Font::from_collection(&collection).is_err());
+        collection.images.push(Image::new(1, 2));
+        collection.images[0].set_tag("def");
+        collection.images[0].set_metadata(vec![0, 1, 2, 2, 2]);
+        assert!(Font::from_collection(&collection).is_err());
+        collection.images[0].set_metadata(vec![0, 1, 2, 2, -2, 0]);
+        assert!(Font::from_collection(&collection).is_err());
+        collection.images[0].set_metadata(vec![0, 1, 2, 2, 2, 0]);
+        assert!(Font::from_collection(&collection).is_ok());
+        collection.images.push(Image::new(1, 2));
+        collection.images[1].set_tag("");
+        collection.images[1].set_metadata(vec![0, 1, 0]);
+        assert!(Font::from_collection(&collection).is_err());
+        collection.images[1].set_tag("ab");
+        assert!(Font::from_collection(&collection).is_ok());
+        collection.images[1].set_tag("a");
+        assert!(Font::from_collection(&collection).is_ok());
+        collection.images[1].set_metadata(vec![0, 1]);
+        assert!(Font::from_collection(&collection).is_err());
+    }
+
+    #[test]
+    fn read_v1_font() {
+        let input: &[u8] = b"ahf1 f7 p1 h2 b1 s3 n1\n\
+            \"Tiny Sans\"\n\
+            \n\
+            0;1;2;3;4;5;6;7;8;9;A;B;C;D;E;F\n\
+            \n\
+            def w1 l0 r2\n\
+            \"\"\n\
+            []\n\
+            1\n\
+            1\n\
+            \n\
+            'a' w2 l0 r3\n\
+            \"lowercase\"\n\
+            [5, -6]\n\
+            11\n\
+            01\n";
+        let font = Font::read(input).expect("failed to read font");
+        assert_eq!(font.name(), "Tiny Sans");
+        assert_eq!(font.glyph_height(), 2);
+        assert_eq!(font.baseline(), 1);
+        assert_eq!(font.line_spacing(), 3);
+        assert_eq!(font.palettes().len(), 1);
+        assert_eq!(font.default_glyph().tag(), "");
+        assert_eq!(font['a'].tag(), "lowercase");
+        assert_eq!(font['a'].metadata(), &[5, -6]);
+        assert_eq!(font['a'].image()[(1, 1)], Color::C1);
+    }
+
+    #[test]
+    fn read_v0_font_line_spacing() {
+        let input: &[u8] = b"ahf0 h2 b1 n0\n\
+            \n\
+            def w0 l0 r0\n\
+            \n\
+            \n";
+        let font = Font::read(input).expect("failed to read font");
+        assert_eq!(font.line_spacing(), 2);
+        assert_eq!(font.name(), "");
+    }
+
+    #[test]
+    fn write_v1_font() {
+        let mut font = Font::with_glyph_height(1);
+        font.set_line_spacing(2);
+        let mut output = Vec::<u8>::new();
+        font.write(&mut output).expect("failed to write font");
+        assert_eq!(
+            &output as &[u8],
+            b"ahf1 f0 p0 h1 b1 s2 n0\n\
+              \n\
+              def w0 l0 r0\n\
+              \n" as &[u8]
+        );
+
+        font.set_name("Tiny \u{2603}");
+        font.palettes_mut().push(Palette::new([(0, 0, 0, 255); 16]));
+        let mut glyph = Glyph::new(Image::new(1, 1), 0, 2);
+        glyph.set_metadata(vec![7]);
+        font.set_char_glyph('x', glyph);
+        let mut output = Vec::<u8>::new();
+        font.write(&mut output).expect("failed to write font");
+        let expected: &[u8] = b"ahf1 f5 p1 h1 b1 s2 n1\n\
+              \"Tiny \\u{2603}\"\n\
+              \n\
+              0;0;0;0;0;0;0;0;0;0;0;0;0;0;0;0\n\
+              \n\
+              def w0 l0 r0\n\
+              []\n\
+              \n\
+              \n\
+              'x' w1 l0 r2\n\
+              [7]\n\
+              0\n";
+        assert_eq!(&output as &[u8], expected);
+        let font = Font::read(expected).expect("failed to read font");
+        assert_eq!(font.name(), "Tiny \u{2603}");
+        assert_eq!(font['x'].metadata(), &[7]);
+    }
+
+    #[test]
+    fn font_kerning() {
+        let mut font = Font::with_glyph_height(1);
+        assert_eq!(font.get_kerning('A', 'V'), 0);
+        font.set_kerning('A', 'V', -2);
+        font.set_kerning('T', 'o', -1);
+        font.set_kerning('r', '.', 3);
+        assert_eq!(font.get_kerning('A', 'V'), -2);
+        assert_eq!(font.get_kerning('V', 'A'), 0);
+        font.set_kerning('r', '.', 0);
+        assert_eq!(font.get_kerning('r', '.'), 0);
+        assert_eq!(
+            font.kerning_pairs().collect::<Vec<_>>(),
+            vec![(('A', 'V'), -2), (('T', 'o'), -1)]
+        );
+    }
+
+    #[test]
+    fn kerning_round_trip() {
+        let mut font = Font::with_glyph_height(1);
+        font.set_kerning('A', 'V', -2);
+        font.set_kerning('\u{2603}', '\'', 1);
+        let mut output = Vec::<u8>::new();
+        font.write(&mut output).expect("failed to write font");
+        let expected: &[u8] = b"ahf1 f8 p0 h1 b1 s1 n0\n\
+              \n\
+              def w0 l0 r0\n\
+              \n\
+              \n\
+              kerning n2\n\
+              'A' 'V' -2\n\
+              '\\u{2603}' '\\'' 1\n";
+        assert_eq!(&output as &[u8], expected);
+        let font = Font::read(expected).expect("failed to read font");
+        assert_eq!(font.kerning_pairs().len(), 2);
+        assert_eq!(font.get_kerning('A', 'V'), -2);
+        assert_eq!(font.get_kerning('\u{2603}', '\''), 1);
+    }
+
+    #[test]
+    fn kerning_round_trip_at_limit() {
+        let mut font = Font::with_glyph_height(1);
+        font.set_kerning('a', 'b', 65535);
+        font.set_kerning('b', 'a', -65535);
+        let mut output = Vec::<u8>::new();
+        font.write(&mut output).unwrap();
+        let font = Font::read(&output as &[u8]).unwrap();
+        assert_eq!(font.get_kerning('a', 'b'), 65535);
+        assert_eq!(font.get_kerning('b', 'a'), -65535);
+
+        let mut font = Font::with_glyph_height(1);
+        font.set_kerning('a', 'b', 65536);
+        let mut output = Vec::<u8>::new();
+        let error = font.write(&mut output).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(output.is_empty());
+        font.set_kerning('a', 'b', i32::MIN);
+        assert!(font.write(Vec::<u8>::new()).is_err());
+    }
+
+    #[test]
+    fn read_font_with_invalid_kerning_pair() {
+        let input: &[u8] = b"ahf1 f8 p0 h1 b1 s1 n0\n\
+              \n\
+              def w0 l0 r0\n\
+              \n\
+              \n\
+              kerning n2\n\
+              'A' 'V' -2\n\
+              'T' 'o' x\n";
+        let error = Font::read(input).err().unwrap();
+        let error = ParseError::from_io_error(&error).unwrap();
+        assert_eq!((error.line(), error.column()), (8, 9));
+        assert_eq!(error.index(), Some(1));
+        assert_eq!(error.kind(), &ParseErrorKind::InvalidIntegerByte(b'x'));
+    }
+
+    #[test]
+    fn glyph_ink_bounds() {
+        let mut glyph = Glyph::new(Image::new(4, 5), -1, 3);
+        assert_eq!(glyph.ink_bounds(), None);
+        glyph.image_mut()[(1, 1)] = Color::C1;
+        glyph.image_mut()[(2, 3)] = Color::C5;
+        assert_eq!(glyph.ink_bounds(), Some((2, 1, 2, 3)));
+        glyph.set_left_edge(2);
+        assert_eq!(glyph.ink_bounds(), Some((-1, 1, 2, 3)));
+    }
+
+    #[test]
+    fn font_ascent_and_descent() {
+        let mut font = Font::with_glyph_height(6);
+        font.set_baseline(4);
+        assert_eq!((font.ascent(), font.descent()), (0, 0));
+        let mut image = Image::new(1, 6);
+        image.fill_rect(0, 1, 1, 3, Color::C1);
+        font.set_char_glyph('A', Glyph::new(image, 0, 2));
+        assert_eq!((font.ascent(), font.descent()), (3, 0));
+        let mut image = Image::new(1, 6);
+        image.fill_rect(0, 2, 1, 4, Color::C1);
+        font.set_char_glyph('g', Glyph::new(image, 0, 2));
+        assert_eq!((font.ascent(), font.descent()), (3, 2));
+        assert_eq!(font.line_height(), 5);
+
+        font['A'].image_mut()[(0, 0)] = Color::C1;
+        assert_eq!(font.ascent(), 4);
+        font.get_char_glyph_mut('g').unwrap().image_mut().clear();
+        assert_eq!((font.ascent(), font.descent()), (4, 0));
+        font.set_baseline(3);
+        assert_eq!((font.ascent(), font.descent()), (3, 1));
+    }
+
+    #[test]
+    fn cloned_font_metrics_are_independent() {
+        let mut font = Font::with_glyph_height(2);
+        let mut image = Image::new(1, 2);
+        image[(0, 1)] = Color::C1;
+        font.set_char_glyph('.', Glyph::new(image, 0, 2));
+        assert_eq!(font.ascent(), 1);
+        let mut copy = font.clone();
+        copy['.'].image_mut()[(0, 0)] = Color::C1;
+        assert_eq!(copy.ascent(), 2);
+        assert_eq!(font.ascent(), 1);
+    }
+
+    #[test]
+    fn multi_char_glyph_keys() {
+        let mut font = Font::with_glyph_height(1);
+        font.set_char_glyph('f', Glyph::new(Image::new(1, 1), 0, 2));
+        font.set_str_glyph("fi", Glyph::new(Image::new(2, 1), 0, 3));
+        font.set_str_glyph("ffi", Glyph::new(Image::new(3, 1), 0, 4));
+        font.set_str_glyph("x", Glyph::new(Image::new(1, 1), 0, 5));
+        assert_eq!(font.chars().collect::<String>(), "fx");
+        assert_eq!(font.sequences().collect::<Vec<_>>(), vec!["ffi", "fi"]);
+        assert_eq!(font.get_str_glyph("x").unwrap().right_edge(), 5);
+        assert_eq!(font.get_str_glyph("fi").unwrap().right_edge(), 3);
+        assert!(font.get_str_glyph("if").is_none());
+
+        let len = |font: &Font, text| font.match_glyph(text).map(|m| m.0);
+        assert_eq!(len(&font, "ffix"), Some(3));
+        assert_eq!(len(&font, "fix"), Some(2));
+        assert_eq!(len(&font, "ffx"), Some(1));
+        assert_eq!(len(&font, "if"), None);
+        assert_eq!(len(&font, ""), None);
+
+        font.get_str_glyph_mut("fi").unwrap().set_right_edge(7);
+        assert_eq!(font.get_str_glyph("fi").unwrap().right_edge(), 7);
+        font.remove_str_glyph("fi");
+        assert_eq!(len(&font, "fix"), Some(1));
+    }
+
+    #[test]
+    fn multi_char_glyph_keys_round_trip() {
+        let input: &[u8] = b"ahf0 h1 b1 n3\n\
+            \n\
+            def w0 l0 r0\n\
+            \n\
+            \n\
+            'e' w1 l0 r2\n\
+            1\n\
+            \n\
+            \"e\\u{301}\" w1 l0 r2\n\
+            3\n\
+            \n\
+            \"fi\" w2 l0 r3\n\
+            11\n";
+        let font = Font::read(input).expect("failed to read font");
+        assert_eq!(font.chars().collect::<String>(), "e");
+        assert_eq!(
+            font.sequences().collect::<Vec<_>>(),
+            vec!["e\u{301}", "fi"]
+        );
+        let glyph = font.get_str_glyph("e\u{301}").unwrap();
+        assert_eq!(glyph.image()[(0, 0)], Color::C3);
+        let mut output = Vec::<u8>::new();
+        font.write(&mut output).expect("failed to write font");
+        assert_eq!(&output as &[u8], input);
+
+        let collection = font.to_collection().unwrap();
+        assert_eq!(collection.images[3].tag(), "fi");
+        let font = Font::from_collection(&collection).unwrap();
+        assert_eq!(font.get_str_glyph("fi").unwrap().right_edge(), 3);
+    }
+
+    #[test]
+    fn read_font_with_empty_glyph_key() {
+        let input: &[u8] = b"ahf0 h1 b1 n1\n\
+            \n\
+            def w0 l0 r0\n\
+            \n\
+            \n\
+            \"\" w1 l0 r2\n\
+            1\n";
+        let error = Font::read(input).err().unwrap();
+        let error = ParseError::from_io_error(&error).unwrap();
+        assert_eq!((error.line(), error.column()), (6, 2));
+        assert_eq!(error.kind(), &ParseErrorKind::EmptyGlyphKey);
+    }
+
+    #[test]
+    fn glyph_offsets_round_trip() {
+        let mut font = Font::with_glyph_height(4);
+        font.set_baseline(3);
+        let mut image = Image::new(1, 2);
+        image.fill_rect(0, 0, 1, 2, Color::C1);
+        let mut comma = Glyph::new(image, 0, 2);
+        comma.set_top(2);
+        font.set_char_glyph(',', comma);
+        let mut image = Image::new(1, 5);
+        image.fill_rect(0, 0, 1, 5, Color::C1);
+        let mut tall = Glyph::new(image, 0, 2);
+        tall.set_top(-1);
+        font.set_char_glyph('\u{c9}', tall);
+        assert_eq!((font.ascent(), font.descent()), (4, 1));
+        let mut output = Vec::<u8>::new();
+        font.write(&mut output).expect("failed to write font");
+        let expected: &[u8] = b"ahf1 f10 p0 h4 b3 s4 n2\n\
+              \n\
+              def w0 h4 t0 l0 r0\n\
+              \n\
+              \n\
+              \n\
+              \n\
+              \n\
+              ',' w1 h2 t2 l0 r2\n\
+              1\n\
+              1\n\
+              \n\
+              '\\u{c9}' w1 h5 t-1 l0 r2\n\
+              1\n\
+              1\n\
+              1\n\
+              1\n\
+              1\n";
+        assert_eq!(&output as &[u8], expected);
+        let font = Font::read(expected).expect("failed to read font");
+        assert_eq!(font.glyph_height(), 4);
+        assert_eq!(font[','].top(), 2);
+        assert_eq!(font[','].image().height(), 2);
+        assert_eq!(font['\u{c9}'].top(), -1);
+        assert_eq!(font['\u{c9}'].image().height(), 5);
+        assert_eq!(font[','].ink_bounds(), Some((0, 2, 1, 2)));
+    }
+
+    #[test]
+    fn font_with_glyph_offsets_to_collection() {
+        let mut font = Font::with_glyph_height(3);
+        font.set_baseline(2);
+        let mut image = Image::new(1, 1);
+        image[(0, 0)] = Color::C1;
+        let mut dot = Glyph::new(image, 0, 2);
+        dot.set_top(1);
+        font.set_char_glyph('.', dot);
+        let mut image = Image::new(1, 4);
+        image[(0, 0)] = Color::C2;
+        let mut accent = Glyph::new(image, 0, 2);
+        accent.set_top(-1);
+        font.set_char_glyph('^', accent);
+        let collection = font.to_collection().unwrap();
+        assert_eq!(collection.images[0].metadata(), &[0, 0, 2, 3, 3, 0]);
+        assert_eq!(collection.images[1].metadata(), &[0, 2, 1]);
+        assert_eq!(collection.images[1].height(), 1);
+        assert_eq!(collection.images[2].metadata(), &[0, 2, -1]);
+        assert_eq!(collection.images[2].height(), 4);
+
+        let mut output = Vec::<u8>::new();
+        collection.write(&mut output).unwrap();
+        let collection = Collection::read(&output as &[u8]).unwrap();
+        let font = Font::from_collection(&collection).unwrap();
+        assert_eq!(font.glyph_height(), 3);
+        assert_eq!(font.baseline(), 2);
+        assert_eq!(font['.'].top(), 1);
+        assert_eq!(font['.'].image().height(), 1);
+        assert_eq!(font['.'].ink_bounds(), Some((0, 1, 1, 1)));
+        assert_eq!(font['^'].top(), -1);
+        assert_eq!(font['^'].image().height(), 4);
+        assert_eq!(font['^'].image()[(0, 0)], Color::C2);
+    }
+}
+
+// ========================================================================= //
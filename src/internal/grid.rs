@@ -0,0 +1,271 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use crate::internal::color::Color;
+use crate::internal::font::{Font, Glyph};
+use crate::internal::image::Image;
+
+// ========================================================================= //
+
+impl Font {
+    /// Builds a font from a sprite sheet image that is divided into a grid of
+    /// `cell_width` by `cell_height` pixel cells.  The cells are assigned
+    /// characters from `chars` in row-major order (left to right, then top to
+    /// bottom); pass e.g. `' '..` to assign consecutive characters starting
+    /// from a space, or `"0123456789".chars()` to assign a specific set of
+    /// characters.  Cells past the end of `chars`, and partial cells at the
+    /// right or bottom edge of the sheet, are ignored.
+    ///
+    /// Each glyph's image is cropped horizontally to the columns of its cell
+    /// that contain non-transparent pixels, with a left edge of zero and a
+    /// right edge one pixel past the image, leaving a one-pixel gap between
+    /// glyphs (see `from_grid_with_spacing` to choose a different gap).
+    /// Cells with no non-transparent pixels are left unmapped, except that
+    /// a blank cell assigned to a whitespace character (such as `' '`)
+    /// becomes an empty glyph that advances by the full cell width.  The
+    /// font's glyph height is `cell_height`, and its default glyph is a
+    /// zero-width space.
+    ///
+    /// Panics if `cell_width` or `cell_height` is zero.
+    pub fn from_grid<I: IntoIterator<Item = char>>(
+        sheet: &Image,
+        cell_width: u32,
+        cell_height: u32,
+        chars: I,
+        baseline: i32,
+    ) -> Font {
+        Font::from_grid_with_spacing(
+            sheet,
+            cell_width,
+            cell_height,
+            chars,
+            baseline,
+            1,
+        )
+    }
+
+    /// Builds a font from a grid sprite sheet, as with `from_grid`, except
+    /// that each glyph's right edge is `spacing` pixels past its cropped
+    /// image, rather than one pixel.
+    ///
+    /// Panics if `cell_width` or `cell_height` is zero.
+    pub fn from_grid_with_spacing<I: IntoIterator<Item = char>>(
+        sheet: &Image,
+        cell_width: u32,
+        cell_height: u32,
+        chars: I,
+        baseline: i32,
+        spacing: u32,
+    ) -> Font {
+        Font::from_grid_cells(
+            sheet,
+            cell_width,
+            cell_height,
+            chars,
+            baseline,
+            |cell| {
+                let (start, end) = ink_columns(cell)?;
+                let image = sub_image(cell, start, end - start);
+                let right = (image.width() + spacing) as i32;
+                Some(Glyph::new(image, 0, right))
+            },
+        )
+    }
+
+    /// Builds a font from a grid sprite sheet, as with `from_grid`, except
+    /// that each glyph's width is marked by the `separator` color instead of
+    /// being detected from its ink.  The first column of a cell that
+    /// contains a `separator` pixel gives the glyph's width (and right
+    /// edge); the glyph's image consists of the columns to its left.  A cell
+    /// with no separator uses the full cell width.  Cells with no
+    /// non-transparent pixels at all (including separator pixels) are left
+    /// unmapped, except for whitespace characters, as with `from_grid`.
+    ///
+    /// Panics if `cell_width` or `cell_height` is zero.
+    pub fn from_grid_with_separator<I: IntoIterator<Item = char>>(
+        sheet: &Image,
+        cell_width: u32,
+        cell_height: u32,
+        chars: I,
+        baseline: i32,
+        separator: Color,
+    ) -> Font {
+        Font::from_grid_cells(
+            sheet,
+            cell_width,
+            cell_height,
+            chars,
+            baseline,
+            |cell| {
+                ink_columns(cell)?;
+                let width = (0..cell.width())
+                    .find(|&col| {
+                        (0..cell.height())
+                            .any(|row| cell[(col, row)] == separator)
+                    })
+                    .unwrap_or(cell.width());
+                let image = sub_image(cell, 0, width);
+                Some(Glyph::new(image, 0, width as i32))
+            },
+        )
+    }
+
+    fn from_grid_cells<I, F>(
+        sheet: &Image,
+        cell_width: u32,
+        cell_height: u32,
+        chars: I,
+        baseline: i32,
+        make_glyph: F,
+    ) -> Font
+    where
+        I: IntoIterator<Item = char>,
+        F: Fn(&Image) -> Option<Glyph>,
+    {
+        assert!(cell_width > 0 && cell_height > 0);
+        let mut font = Font::with_glyph_height(cell_height);
+        font.set_baseline(baseline);
+        let columns = sheet.width() / cell_width;
+        let rows = sheet.height() / cell_height;
+        let cells =
+            (0..rows).flat_map(|row| (0..columns).map(move |col| (col, row)));
+        for (chr, (col, row)) in chars.into_iter().zip(cells) {
+            let mut cell = Image::new(cell_width, cell_height);
+            cell.draw(
+                sheet,
+                -((col * cell_width) as i32),
+                -((row * cell_height) as i32),
+            );
+            if let Some(glyph) = make_glyph(&cell) {
+                font.set_char_glyph(chr, glyph);
+            } else if chr.is_whitespace() {
+                let image = Image::new(0, cell_height);
+                font.set_char_glyph(
+                    chr,
+                    Glyph::new(image, 0, cell_width as i32),
+                );
+            }
+        }
+        font
+    }
+}
+
+/// Returns the range of columns of the image that contain non-transparent
+/// pixels, as `(start, end)` with `end` exclusive, or `None` if the image is
+/// entirely transparent.
+fn ink_columns(image: &Image) -> Option<(u32, u32)> {
+    let has_ink = |col: u32| {
+        (0..image.height()).any(|row| image[(col, row)] != Color::C0)
+    };
+    let start = (0..image.width()).find(|&col| has_ink(col))?;
+    let end = (0..image.width()).rev().find(|&col| has_ink(col))? + 1;
+    Some((start, end))
+}
+
+/// Returns the full-height slice of the image starting at column `start` with
+/// the given width.
+fn sub_image(image: &Image, start: u32, width: u32) -> Image {
+    let mut sub = Image::new(width, image.height());
+    sub.draw(image, -(start as i32), 0);
+    sub
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::testutil::image_from_rows;
+
+    fn sheet() -> Image {
+        image_from_rows(&[
+            "0000111113",
+            "0000011013",
+            "0000001113",
+            "0103000300",
+            "0103000000",
+            "0103000000",
+        ])
+    }
+
+    #[test]
+    fn font_from_grid_by_ink() {
+        let font = Font::from_grid(&sheet(), 3, 3, 'a'.., 2);
+        assert_eq!(font.glyph_height(), 3);
+        assert_eq!(font.baseline(), 2);
+        assert_eq!(font.chars().collect::<String>(), "bcdef");
+        assert_eq!(font['b'].image().width(), 2);
+        assert_eq!(font['b'].image()[(0, 0)], Color::C1);
+        assert_eq!(font['b'].image()[(0, 1)], Color::C0);
+        assert_eq!(font['b'].right_edge(), 3);
+        assert_eq!(font['c'].image().width(), 3);
+        assert_eq!(font['c'].left_edge(), 0);
+        assert_eq!(font['c'].right_edge(), 4);
+        assert_eq!(font['d'].image().width(), 1);
+        assert_eq!(font['e'].image().width(), 1);
+        assert_eq!(font['f'].image().width(), 1);
+    }
+
+    #[test]
+    fn font_from_grid_with_spacing() {
+        let font = Font::from_grid_with_spacing(&sheet(), 3, 3, 'a'.., 2, 0);
+        assert_eq!(font['b'].right_edge(), 2);
+        assert_eq!(font['c'].right_edge(), 3);
+        let font = Font::from_grid_with_spacing(&sheet(), 3, 3, 'a'.., 2, 2);
+        assert_eq!(font['b'].right_edge(), 4);
+    }
+
+    #[test]
+    fn font_from_grid_with_blank_space_cell() {
+        let font = Font::from_grid(&sheet(), 3, 3, " !".chars(), 2);
+        assert_eq!(font.chars().collect::<String>(), " !");
+        assert_eq!(font[' '].image().width(), 0);
+        assert_eq!(font[' '].image().height(), 3);
+        assert_eq!(font[' '].right_edge(), 3);
+        assert_eq!(font.measure("  ").width(), 6);
+    }
+
+    #[test]
+    fn font_from_grid_with_charset() {
+        let font = Font::from_grid(&sheet(), 3, 3, "xyz!".chars(), 3);
+        assert_eq!(font.chars().collect::<String>(), "!yz");
+        assert_eq!(font['!'].image().width(), 1);
+    }
+
+    #[test]
+    fn font_from_grid_with_separator() {
+        let font = Font::from_grid_with_separator(
+            &sheet(),
+            3,
+            3,
+            "abcdef".chars(),
+            2,
+            Color::C3,
+        );
+        assert_eq!(font.chars().collect::<String>(), "bcdef");
+        assert_eq!(font['b'].image().width(), 3);
+        assert_eq!(font['b'].right_edge(), 3);
+        assert_eq!(font['d'].image().width(), 3);
+        assert_eq!(font['d'].right_edge(), 3);
+        assert_eq!(font['e'].image().width(), 0);
+        assert_eq!(font['f'].image().width(), 1);
+    }
+}
+
+// ========================================================================= //
@@ -19,7 +19,7 @@
 
 use crate::internal::color::Color;
 use crate::internal::palette::Palette;
-use crate::internal::util;
+use crate::internal::util::TextReader;
 use std::cmp::{max, min};
 use std::io::{self, Read, Write};
 use std::ops::{Index, IndexMut};
@@ -29,6 +29,7 @@ use std::ops::{Index, IndexMut};
 /// Represents a single ASCII Hex Image.
 #[derive(Clone)]
 pub struct Image {
+    pub(crate) comment: String,
     pub(crate) tag: String,
     pub(crate) metadata: Vec<i16>,
     pub(crate) width: u32,
@@ -40,13 +41,26 @@ impl Image {
     /// Constructs a new image with all pixels transparent.
     pub fn new(width: u32, height: u32) -> Image {
         let num_pixels = (width * height) as usize;
-        return Image {
+        Image {
+            comment: String::new(),
             tag: String::new(),
             metadata: Vec::new(),
             width,
             height,
             pixels: vec![Color::C0; num_pixels].into_boxed_slice(),
-        };
+        }
+    }
+
+    /// Returns the comment attached to this image (or empty string if it
+    /// doesn't have one).  Multi-line comments have their lines separated by
+    /// `\n`.
+    pub fn comment(&self) -> &str {
+        &self.comment
+    }
+
+    /// Sets the comment attached to this image.
+    pub fn set_comment<S: Into<String>>(&mut self, comment: S) {
+        self.comment = comment.into();
     }
 
     /// Returns the string tag for this image (or empty string if it doesn't
@@ -144,6 +158,7 @@ impl Image {
             }
         }
         Image {
+            comment: self.comment.clone(),
             tag: self.tag.clone(),
             metadata: self.metadata.clone(),
             width: self.width,
@@ -163,6 +178,7 @@ impl Image {
             }
         }
         Image {
+            comment: self.comment.clone(),
             tag: self.tag.clone(),
             metadata: self.metadata.clone(),
             width: self.width,
@@ -181,6 +197,7 @@ impl Image {
             }
         }
         Image {
+            comment: self.comment.clone(),
             tag: self.tag.clone(),
             metadata: self.metadata.clone(),
             width: self.height,
@@ -200,6 +217,7 @@ impl Image {
             }
         }
         Image {
+            comment: self.comment.clone(),
             tag: self.tag.clone(),
             metadata: self.metadata.clone(),
             width: self.height,
@@ -219,22 +237,18 @@ impl Image {
     }
 
     pub(crate) fn read<R: Read>(
-        mut reader: R,
+        reader: &mut TextReader<R>,
         width: u32,
         height: u32,
     ) -> io::Result<Image> {
         let mut pixels = Vec::with_capacity((width * height) as usize);
         if width > 0 && height > 0 {
-            let mut row_buffer = vec![0u8; width as usize];
             for _ in 0..height {
-                reader.read_exact(&mut row_buffer)?;
-                for &byte in &row_buffer {
-                    pixels.push(Color::from_byte(byte)?);
-                }
-                util::read_exactly(reader.by_ref(), b"\n")?;
+                reader.read_pixel_row(width, &mut pixels)?;
             }
         }
         Ok(Image {
+            comment: String::new(),
             tag: String::new(),
             metadata: Vec::new(),
             width,
@@ -243,6 +257,7 @@ impl Image {
         })
     }
 
+    #[allow(clippy::write_with_newline)]
     pub(crate) fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
         if self.width > 0 && self.height > 0 {
             for row in 0..self.height {
@@ -386,11 +401,12 @@ mod tests {
         let input1: &[u8] = b"EEEEE\n\
                               EEEEE\n\
                               EEEEE\n";
-        let mut image1 = Image::read(input1, 5, 3).unwrap();
+        let mut image1 =
+            Image::read(&mut TextReader::new(input1), 5, 3).unwrap();
         let input2: &[u8] = b"01110\n\
                               11011\n\
                               01110\n";
-        let image2 = Image::read(input2, 5, 3).unwrap();
+        let image2 = Image::read(&mut TextReader::new(input2), 5, 3).unwrap();
         image1.draw(&image2, -1, 1);
         let mut output = Vec::<u8>::new();
         image1.write(&mut output).unwrap();
@@ -0,0 +1,586 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use crate::internal::font::{Font, Glyph};
+use crate::internal::image::Image;
+use crate::internal::text::PlacedGlyphs;
+use std::cmp::max;
+
+// ========================================================================= //
+
+/// How each line of text is positioned horizontally within a `TextLayout`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Alignment {
+    /// Lines start at the left edge of the layout.
+    Left,
+    /// Lines are centered within the layout (rounding to the left).
+    Center,
+    /// Lines end at the right edge of the layout.
+    Right,
+    /// Space is added between words so that each line spans the full width
+    /// of the layout.  The last line of each paragraph (i.e. a line that ends
+    /// with an explicit `\n` or at the end of the text) is aligned left.
+    Justify,
+}
+
+// ========================================================================= //
+
+/// Options controlling how `Font::layout` lays out multiple lines of text.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LayoutOptions {
+    max_width: Option<u32>,
+    alignment: Alignment,
+    line_spacing: Option<u32>,
+}
+
+impl LayoutOptions {
+    /// Returns the default options: no wrapping, left alignment, and the
+    /// font's own line spacing.
+    pub fn new() -> LayoutOptions {
+        LayoutOptions::default()
+    }
+
+    /// Sets the maximum width of the layout, in pixels.  Lines longer than
+    /// this are wrapped at whitespace, or within a word if the word doesn't
+    /// fit on a line by itself.  Whitespace at a wrapping point is dropped.
+    pub fn max_width(mut self, width: u32) -> LayoutOptions {
+        self.max_width = Some(width);
+        self
+    }
+
+    /// Sets how lines are aligned within the layout.
+    pub fn alignment(mut self, alignment: Alignment) -> LayoutOptions {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Sets the vertical distance between the baselines of successive lines,
+    /// in pixels, overriding the font's `line_spacing()`.
+    pub fn line_spacing(mut self, spacing: u32) -> LayoutOptions {
+        self.line_spacing = Some(spacing);
+        self
+    }
+}
+
+impl Default for LayoutOptions {
+    fn default() -> LayoutOptions {
+        LayoutOptions {
+            max_width: None,
+            alignment: Alignment::Left,
+            line_spacing: None,
+        }
+    }
+}
+
+// ========================================================================= //
+
+/// A single glyph placed within a `TextLayout`.
+#[derive(Clone)]
+pub struct PlacedGlyph<'a> {
+    text: String,
+    x: i32,
+    y: i32,
+    glyph: &'a Glyph,
+}
+
+impl<'a> PlacedGlyph<'a> {
+    /// Returns the text that this glyph represents.  This is usually a
+    /// single character, but may be longer if the font has a glyph for a
+    /// multi-character sequence (see `Font::set_str_glyph`).
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns the position of the top-left corner of the glyph's image,
+    /// relative to the top-left corner of the layout.
+    pub fn position(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    /// Returns the glyph itself.
+    pub fn glyph(&self) -> &'a Glyph {
+        self.glyph
+    }
+}
+
+/// A single line of text within a `TextLayout`.
+#[derive(Clone)]
+pub struct TextLine<'a> {
+    text: String,
+    x: i32,
+    baseline: i32,
+    width: i32,
+    glyphs: Vec<PlacedGlyph<'a>>,
+}
+
+impl<'a> TextLine<'a> {
+    /// Returns the text of this line, without any trailing `\n` or any
+    /// whitespace dropped at a wrapping point.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns the x-position of the start of this line, relative to the left
+    /// edge of the layout.
+    pub fn x(&self) -> i32 {
+        self.x
+    }
+
+    /// Returns the y-position of this line's baseline, relative to the top of
+    /// the layout.
+    pub fn baseline(&self) -> i32 {
+        self.baseline
+    }
+
+    /// Returns the advance width of this line, in pixels (including any space
+    /// added for justification).
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    /// Returns the glyphs in this line, in order.
+    pub fn glyphs(&self) -> &[PlacedGlyph<'a>] {
+        &self.glyphs
+    }
+}
+
+/// The result of laying out text over multiple lines with `Font::layout`.
+#[derive(Clone)]
+pub struct TextLayout<'a> {
+    width: u32,
+    height: u32,
+    lines: Vec<TextLine<'a>>,
+}
+
+impl<'a> TextLayout<'a> {
+    /// Returns the width of the layout, in pixels.  This is the maximum width
+    /// if one was given, or else the width of the widest line.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the height of the layout, in pixels, from the top of the first
+    /// line's glyph cells to the bottom of the last line's glyph cells, or to
+    /// the lowest ink of any glyph in the font (see `Font::descent`) if that
+    /// is lower.  Glyphs whose top offset is negative may extend above the
+    /// top of the layout.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the lines of the layout, from top to bottom.
+    pub fn lines(&self) -> &[TextLine<'a>] {
+        &self.lines
+    }
+}
+
+// ========================================================================= //
+
+impl Font {
+    /// Lays out text over multiple lines.  Lines are broken at each `\n`, and
+    /// additionally wrapped to fit within the maximum width, if one is set.
+    /// A line that is still wider than the maximum width (because a single
+    /// glyph doesn't fit) starts at x = 0 regardless of alignment.
+    pub fn layout(
+        &self,
+        text: &str,
+        options: &LayoutOptions,
+    ) -> TextLayout<'_> {
+        let max_width = options.max_width.map(|width| width as i32);
+        let spacing = options.line_spacing.unwrap_or(self.line_spacing());
+        let mut wrapped = Vec::new();
+        for paragraph in text.split('\n') {
+            wrap_paragraph(self, paragraph, max_width, &mut wrapped);
+        }
+        let widths: Vec<i32> = wrapped
+            .iter()
+            .map(|(line, _)| self.measure(line).width())
+            .collect();
+        let layout_width = match max_width {
+            Some(width) => width,
+            None => widths.iter().copied().fold(0, max),
+        };
+        let mut lines = Vec::with_capacity(wrapped.len());
+        for (index, ((text, last), line_width)) in
+            wrapped.into_iter().zip(widths).enumerate()
+        {
+            let baseline = self.baseline() + (index as i32) * spacing as i32;
+            let top = baseline - self.baseline();
+            let alignment = match options.alignment {
+                Alignment::Justify if last => Alignment::Left,
+                alignment => alignment,
+            };
+            let x = match alignment {
+                Alignment::Left | Alignment::Justify => 0,
+                Alignment::Center => max(0, (layout_width - line_width) / 2),
+                Alignment::Right => max(0, layout_width - line_width),
+            };
+            let mut gaps = if alignment == Alignment::Justify {
+                Gaps::new(&text, layout_width - line_width)
+            } else {
+                Gaps::new("", 0)
+            };
+            let mut shift = 0;
+            let mut glyphs = Vec::with_capacity(text.len());
+            let width = {
+                let mut placed = PlacedGlyphs::new(self, &text);
+                for (key, glyph_x, glyph) in placed.by_ref() {
+                    shift += gaps.next_shift(key);
+                    glyphs.push(PlacedGlyph {
+                        text: key.to_string(),
+                        x: x + shift + glyph_x,
+                        y: top + glyph.top(),
+                        glyph,
+                    });
+                }
+                placed.pen() + shift
+            };
+            lines.push(TextLine { text, x, baseline, width, glyphs });
+        }
+        let bottom =
+            max(self.glyph_height() as i32, self.baseline() + self.descent());
+        let height = (lines.len() as u32 - 1) * spacing + bottom as u32;
+        TextLayout { width: max(0, layout_width) as u32, height, lines }
+    }
+}
+
+impl Image {
+    /// Draws laid-out text onto this image, placing the top-left corner of
+    /// the layout at `(x, y)`.  Glyph images that fall outside this image are
+    /// clipped.
+    pub fn draw_layout(&mut self, layout: &TextLayout, x: i32, y: i32) {
+        for line in layout.lines.iter() {
+            for placed in line.glyphs.iter() {
+                self.draw(placed.glyph.image(), x + placed.x, y + placed.y);
+            }
+        }
+    }
+}
+
+// ========================================================================= //
+
+/// Breaks a single paragraph (containing no `\n`) into lines no wider than
+/// `max_width`, pushing each line onto `lines` along with whether it is the
+/// last line of the paragraph.
+fn wrap_paragraph(
+    font: &Font,
+    paragraph: &str,
+    max_width: Option<i32>,
+    lines: &mut Vec<(String, bool)>,
+) {
+    let max_width = match max_width {
+        Some(width) => width,
+        None => {
+            lines.push((paragraph.to_string(), true));
+            return;
+        }
+    };
+    let units = glyph_units(font, paragraph);
+    let offset =
+        |index: usize| units.get(index).map_or(paragraph.len(), |u| u.start);
+    let mut push_line = |start: usize, end: usize, last: bool| {
+        let mut text = &paragraph[offset(start)..offset(end)];
+        if !last {
+            text = text.trim_end();
+        }
+        lines.push((text.to_string(), last));
+    };
+    // The current line spans units[start..=index], with advance width
+    // `width`.
+    let mut start = 0;
+    let mut width = 0;
+    for index in 0..units.len() {
+        let unit = &units[index];
+        if index > start {
+            width += unit.kerning;
+        }
+        width += unit.advance;
+        if unit.space || width <= max_width {
+            continue;
+        }
+        // Prefer to break just before the current word, if anything other
+        // than whitespace precedes it on this line.
+        let word_start = (start..index)
+            .rev()
+            .find(|&other| units[other].space)
+            .map_or(start, |other| other + 1);
+        let split = if (start..word_start).any(|other| !units[other].space) {
+            word_start
+        } else {
+            index
+        };
+        if split == start {
+            // A single glyph that is wider than the line; let it overflow.
+            continue;
+        }
+        push_line(start, split, false);
+        start = split;
+        width = units_width(&units[start..=index]);
+        // The remainder of the word might still be too wide to fit.
+        while index > start && width > max_width {
+            let mut end = start + 1;
+            let mut end_width = units[start].advance;
+            while end < index {
+                let next = end_width + units[end].kerning + units[end].advance;
+                if next > max_width {
+                    break;
+                }
+                end_width = next;
+                end += 1;
+            }
+            push_line(start, end, false);
+            start = end;
+            width = units_width(&units[start..=index]);
+        }
+    }
+    push_line(start, units.len(), true);
+}
+
+/// A glyph within a paragraph being wrapped.
+struct GlyphUnit {
+    /// The byte offset within the paragraph of the glyph's key.
+    start: usize,
+    /// The kerning adjustment between the previous glyph and this one.
+    kerning: i32,
+    /// The advance width of the glyph itself.
+    advance: i32,
+    /// True if the glyph's key is entirely whitespace.
+    space: bool,
+}
+
+/// Splits a paragraph into the glyphs that would be used to draw it, so that
+/// the width of any run of glyphs can be computed without re-measuring it.
+fn glyph_units(font: &Font, paragraph: &str) -> Vec<GlyphUnit> {
+    let mut units = Vec::new();
+    let mut placed = PlacedGlyphs::new(font, paragraph);
+    let mut start = 0;
+    loop {
+        let pen = placed.pen();
+        let (key, x, glyph) = match placed.next() {
+            Some(item) => item,
+            None => break,
+        };
+        let origin = x + glyph.left_edge();
+        units.push(GlyphUnit {
+            start,
+            kerning: origin - pen,
+            advance: placed.pen() - origin,
+            space: key.chars().all(char::is_whitespace),
+        });
+        start += key.len();
+    }
+    units
+}
+
+/// Returns the advance width of a run of glyphs drawn on their own.
+fn units_width(units: &[GlyphUnit]) -> i32 {
+    let kerning: i32 = units.iter().skip(1).map(|unit| unit.kerning).sum();
+    kerning + units.iter().map(|unit| unit.advance).sum::<i32>()
+}
+
+/// Distributes extra space among the gaps between words in a justified line.
+struct Gaps {
+    extra: i32,
+    num_gaps: i32,
+    gap_index: i32,
+    seen_word: bool,
+    in_gap: bool,
+}
+
+impl Gaps {
+    fn new(text: &str, extra: i32) -> Gaps {
+        let words = text.split_whitespace().count() as i32;
+        Gaps {
+            extra: max(0, extra),
+            num_gaps: max(0, words - 1),
+            gap_index: 0,
+            seen_word: false,
+            in_gap: false,
+        }
+    }
+
+    /// Returns the additional shift to apply to the glyph for `key` and all
+    /// following glyphs.
+    fn next_shift(&mut self, key: &str) -> i32 {
+        if key.chars().all(char::is_whitespace) {
+            self.in_gap = self.seen_word;
+            return 0;
+        }
+        self.seen_word = true;
+        if !self.in_gap || self.gap_index >= self.num_gaps {
+            return 0;
+        }
+        self.in_gap = false;
+        let mut shift = self.extra / self.num_gaps;
+        if self.gap_index < self.extra % self.num_gaps {
+            shift += 1;
+        }
+        self.gap_index += 1;
+        shift
+    }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::color::Color;
+    use crate::internal::testutil::{font_from_rows, image_rows, GlyphRows};
+
+    /// Glyphs for a font where every glyph is a 1-pixel-wide bar followed by
+    /// a 1-pixel gap, and spaces are 2 pixels wide.
+    const GLYPHS: &[GlyphRows] =
+        &[("def", 0, 2, &["1", "1"]), (" ", 0, 2, &["", ""])];
+
+    fn line_texts(layout: &TextLayout) -> Vec<String> {
+        layout.lines().iter().map(|line| line.text().to_string()).collect()
+    }
+
+    #[test]
+    fn layout_without_wrapping() {
+        let font = font_from_rows(2, 2, GLYPHS);
+        let layout = font.layout("ab\n\nabcd", &LayoutOptions::new());
+        assert_eq!(line_texts(&layout), vec!["ab", "", "abcd"]);
+        assert_eq!(layout.width(), 8);
+        assert_eq!(layout.height(), 6);
+        let baselines: Vec<i32> =
+            layout.lines().iter().map(TextLine::baseline).collect();
+        assert_eq!(baselines, vec![2, 4, 6]);
+    }
+
+    #[test]
+    fn layout_with_wrapping() {
+        let font = font_from_rows(2, 2, GLYPHS);
+        let options = LayoutOptions::new().max_width(10);
+        let layout = font.layout("ab cd  ef gh\nabcdefghijkl", &options);
+        assert_eq!(
+            line_texts(&layout),
+            vec!["ab cd", "ef gh", "abcde", "fghij", "kl"]
+        );
+        let widths: Vec<i32> =
+            layout.lines().iter().map(TextLine::width).collect();
+        assert_eq!(widths, vec![10, 10, 10, 10, 4]);
+        assert_eq!(layout.width(), 10);
+    }
+
+    #[test]
+    fn layout_long_word_after_break() {
+        let font = font_from_rows(2, 2, GLYPHS);
+        let options = LayoutOptions::new().max_width(6);
+        let layout = font.layout("a bcdefg h", &options);
+        assert_eq!(line_texts(&layout), vec!["a", "bcd", "efg", "h"]);
+    }
+
+    #[test]
+    fn layout_alignment() {
+        let font = font_from_rows(2, 2, GLYPHS);
+        let text = "ab cd e";
+        let xs = |alignment| {
+            let options =
+                LayoutOptions::new().max_width(11).alignment(alignment);
+            let layout = font.layout(text, &options);
+            layout.lines().iter().map(TextLine::x).collect::<Vec<i32>>()
+        };
+        assert_eq!(xs(Alignment::Left), vec![0, 0]);
+        assert_eq!(xs(Alignment::Center), vec![0, 4]);
+        assert_eq!(xs(Alignment::Right), vec![1, 9]);
+        assert_eq!(xs(Alignment::Justify), vec![0, 0]);
+    }
+
+    #[test]
+    fn layout_justified() {
+        let font = font_from_rows(2, 2, GLYPHS);
+        let options =
+            LayoutOptions::new().max_width(15).alignment(Alignment::Justify);
+        let layout = font.layout("a b c d e f", &options);
+        assert_eq!(line_texts(&layout), vec!["a b c d", "e f"]);
+        let line = &layout.lines()[0];
+        assert_eq!(line.width(), 15);
+        let xs: Vec<i32> =
+            line.glyphs().iter().map(|placed| placed.position().0).collect();
+        assert_eq!(xs, vec![0, 2, 5, 7, 9, 11, 13]);
+        let line = &layout.lines()[1];
+        assert_eq!(line.width(), 6);
+    }
+
+    #[test]
+    fn layout_overflowing_line_alignment() {
+        let mut font = font_from_rows(2, 2, GLYPHS);
+        let image = Image::new(6, 2);
+        font.set_char_glyph('W', Glyph::new(image, 0, 6));
+        for alignment in [Alignment::Center, Alignment::Right] {
+            let options =
+                LayoutOptions::new().max_width(4).alignment(alignment);
+            let layout = font.layout("W a", &options);
+            assert_eq!(line_texts(&layout), vec!["W", "a"]);
+            assert_eq!(layout.lines()[0].x(), 0);
+            assert_eq!(layout.lines()[0].width(), 6);
+        }
+    }
+
+    #[test]
+    fn layout_with_kerning() {
+        let mut font = font_from_rows(2, 2, GLYPHS);
+        font.set_kerning('a', 'b', -1);
+        let options = LayoutOptions::new().max_width(5);
+        let layout = font.layout("ab abc", &options);
+        assert_eq!(line_texts(&layout), vec!["ab", "abc"]);
+        assert_eq!(layout.lines()[1].width(), 5);
+    }
+
+    #[test]
+    fn layout_height_includes_descent() {
+        let mut font = font_from_rows(2, 2, GLYPHS);
+        let mut image = Image::new(1, 2);
+        image.fill_rect(0, 0, 1, 2, Color::C1);
+        let mut glyph = Glyph::new(image, 0, 2);
+        glyph.set_top(1);
+        font.set_char_glyph(',', glyph);
+        let layout = font.layout("a\nb", &LayoutOptions::new());
+        assert_eq!(layout.height(), 5);
+    }
+
+    #[test]
+    fn layout_line_spacing() {
+        let font = font_from_rows(2, 2, GLYPHS);
+        let options = LayoutOptions::new().line_spacing(5);
+        let layout = font.layout("a\nb", &options);
+        assert_eq!(layout.height(), 7);
+        let line = &layout.lines()[1];
+        assert_eq!(line.baseline(), 7);
+        assert_eq!(line.glyphs()[0].position(), (0, 5));
+    }
+
+    #[test]
+    fn draw_layout_onto_image() {
+        let font = font_from_rows(2, 2, GLYPHS);
+        let options =
+            LayoutOptions::new().max_width(4).alignment(Alignment::Right);
+        let layout = font.layout("ab c", &options);
+        let mut image = Image::new(5, 4);
+        image.draw_layout(&layout, 1, 0);
+        assert_eq!(
+            image_rows(&image),
+            vec!["01010", "01010", "00010", "00010"]
+        );
+    }
+}
+
+// ========================================================================= //
@@ -17,10 +17,28 @@
 // | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
 // +--------------------------------------------------------------------------+
 
+pub mod bdf;
+pub mod bmfont;
 pub mod collect;
 pub mod color;
+pub mod coverage;
+pub mod error;
+pub mod family;
+pub mod font;
+pub mod grid;
 pub mod image;
+pub mod layout;
+pub mod options;
 pub mod palette;
+pub mod psf;
+pub mod rich;
+pub mod stack;
+pub mod subset;
+#[cfg(test)]
+pub mod testutil;
+pub mod text;
+pub mod ttf;
 pub mod util;
+pub mod variant;
 
 // ========================================================================= //
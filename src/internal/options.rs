@@ -0,0 +1,172 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use crate::internal::collect::FormatInfo;
+
+// ========================================================================= //
+
+/// Options controlling how strictly AHI and AHF files are parsed.
+///
+/// By default, parsing is strict: every line must end with a single `\n`,
+/// and pixel rows must use uppercase hex digits.  The `lenient` constructor
+/// relaxes all of these rules, which is useful for files that have been
+/// edited by hand or checked out with Windows line endings.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ReadOptions {
+    pub(crate) allow_crlf: bool,
+    pub(crate) allow_trailing_whitespace: bool,
+    pub(crate) allow_missing_final_newline: bool,
+    pub(crate) allow_lowercase_hex: bool,
+}
+
+impl ReadOptions {
+    /// Returns options for strict parsing (the default).
+    pub fn new() -> ReadOptions {
+        ReadOptions::default()
+    }
+
+    /// Returns options that enable all of the available leniencies.
+    pub fn lenient() -> ReadOptions {
+        ReadOptions {
+            allow_crlf: true,
+            allow_trailing_whitespace: true,
+            allow_missing_final_newline: true,
+            allow_lowercase_hex: true,
+        }
+    }
+
+    /// Sets whether lines may end with `\r\n` instead of `\n`.
+    pub fn allow_crlf(mut self, allow: bool) -> ReadOptions {
+        self.allow_crlf = allow;
+        self
+    }
+
+    /// Sets whether lines may have trailing spaces or tabs.
+    pub fn allow_trailing_whitespace(mut self, allow: bool) -> ReadOptions {
+        self.allow_trailing_whitespace = allow;
+        self
+    }
+
+    /// Sets whether the last line of the file may omit its newline.
+    pub fn allow_missing_final_newline(mut self, allow: bool) -> ReadOptions {
+        self.allow_missing_final_newline = allow;
+        self
+    }
+
+    /// Sets whether pixel rows may use lowercase hex digits (`a` through
+    /// `f`).
+    pub fn allow_lowercase_hex(mut self, allow: bool) -> ReadOptions {
+        self.allow_lowercase_hex = allow;
+        self
+    }
+}
+
+// ========================================================================= //
+
+/// Options controlling which encoding, format version and flags are used when
+/// writing an AHI (or BHI) file.
+///
+/// By default, the lowest format version (and the fewest flags) able to
+/// represent the data are chosen.  A minimum version and/or explicit flags
+/// can be requested, in which case the writer will still upgrade past them as
+/// needed, unless `error_if_unrepresentable` is set, in which case the write
+/// will instead fail.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WriteOptions {
+    pub(crate) min_version: u32,
+    pub(crate) max_version: u32,
+    pub(crate) flags: Option<u32>,
+    pub(crate) error_if_unrepresentable: bool,
+    pub(crate) binary: bool,
+}
+
+impl WriteOptions {
+    /// Returns the default options, which choose the lowest possible format
+    /// version.
+    pub fn new() -> WriteOptions {
+        WriteOptions::default()
+    }
+
+    /// Returns options that write in the same encoding, format version and
+    /// flags that a file was read with (see `Collection::read_with_format`).
+    pub fn from_format(format: &FormatInfo) -> WriteOptions {
+        if format.is_binary() {
+            // The BHI version is unrelated to the AHI version, and BHI only
+            // has one version, so only the flags carry over.
+            WriteOptions::new().binary(true).flags(format.flags())
+        } else {
+            WriteOptions::new()
+                .min_version(format.version())
+                .max_version(format.version())
+                .flags(format.flags())
+        }
+    }
+
+    /// Sets whether to write the binary BHI encoding rather than AHI text.
+    /// The version options apply only to AHI, since BHI has a single
+    /// version.
+    pub fn binary(mut self, binary: bool) -> WriteOptions {
+        self.binary = binary;
+        self
+    }
+
+    /// Sets the minimum format version to write.
+    pub fn min_version(mut self, version: u32) -> WriteOptions {
+        self.min_version = version;
+        self
+    }
+
+    /// Sets the maximum format version to write.  If the data can't be
+    /// represented in this version, the writer will either use a higher
+    /// version anyway or fail, depending on `error_if_unrepresentable`.
+    pub fn max_version(mut self, version: u32) -> WriteOptions {
+        self.max_version = version;
+        self
+    }
+
+    /// Sets the format flags to write (a combination of the
+    /// `Collection::FLAG_*` constants), rather than choosing them
+    /// automatically.  Setting any flags requires version 1 or higher.
+    pub fn flags(mut self, flags: u32) -> WriteOptions {
+        self.flags = Some(flags);
+        self
+    }
+
+    /// Sets whether writing should fail with an error, rather than silently
+    /// using a higher version or additional flags, if the data can't be
+    /// represented within the requested maximum version and flags.
+    pub fn error_if_unrepresentable(mut self, error: bool) -> WriteOptions {
+        self.error_if_unrepresentable = error;
+        self
+    }
+}
+
+impl Default for WriteOptions {
+    fn default() -> WriteOptions {
+        WriteOptions {
+            min_version: 0,
+            max_version: u32::MAX,
+            flags: None,
+            error_if_unrepresentable: false,
+            binary: false,
+        }
+    }
+}
+
+// ========================================================================= //
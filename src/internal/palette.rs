@@ -18,8 +18,9 @@
 // +--------------------------------------------------------------------------+
 
 use crate::internal::color::Color;
-use crate::internal::util;
-use std::io::{self, Error, ErrorKind, Read, Write};
+use crate::internal::error::ParseErrorKind;
+use crate::internal::util::TextReader;
+use std::io::{self, Read, Write};
 use std::ops::{Index, IndexMut};
 
 // ========================================================================= //
@@ -37,15 +38,18 @@ impl Palette {
     }
 
     /// Returns a reference to the default palette.
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> &'static Palette {
         &DEFAULT_PALETTE
     }
 
-    pub(crate) fn read<R: Read>(mut reader: R) -> io::Result<Palette> {
+    pub(crate) fn read<R: Read>(
+        reader: &mut TextReader<R>,
+    ) -> io::Result<Palette> {
         let mut palette = Palette::new([(0u8, 0u8, 0u8, 0u8); 16]);
         for index in 0..16 {
             let terminator = if index == 15 { b'\n' } else { b';' };
-            let digits = util::read_hex_digits(reader.by_ref(), terminator)?;
+            let digits = reader.read_hex_digits(terminator)?;
             palette.rgba[index] = match digits.len() {
                 0 => (0, 0, 0, 0),
                 1 => {
@@ -90,14 +94,15 @@ impl Palette {
                     digits[6] * 0x10 + digits[7],
                 ),
                 _ => {
-                    let msg = "too many digits in palette color";
-                    return Err(Error::new(ErrorKind::InvalidData, msg));
+                    let kind = ParseErrorKind::TooManyPaletteDigits;
+                    return Err(reader.error(kind));
                 }
             };
         }
         Ok(palette)
     }
 
+    #[allow(clippy::write_with_newline)]
     pub(crate) fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
         for (index, &(r, g, b, a)) in self.rgba.iter().enumerate() {
             if a == 0 {
@@ -210,7 +215,7 @@ mod tests {
     fn read_palette() {
         let input: &[u8] =
             b"C;;7F;F00;1EBA;C2C7F;FF7F00;0;F;3F7FBF9;01234567;1;2;3;4;5\n";
-        let palette = Palette::read(input).unwrap();
+        let palette = Palette::read(&mut TextReader::new(input)).unwrap();
         assert_eq!(palette[Color::C0], (0xcc, 0xcc, 0xcc, 0xff));
         assert_eq!(palette[Color::C1], (0, 0, 0, 0));
         assert_eq!(palette[Color::C2], (0x7f, 0x7f, 0x7f, 0xff));
@@ -228,7 +233,7 @@ mod tests {
     fn read_and_write_palette() {
         let input: &[u8] =
             b"C;;7F;F00;1EBA;C2C7F;FF7F00;0;F;3F7FBF9;01234567;1;2;3;4;5\n";
-        let palette = Palette::read(input).unwrap();
+        let palette = Palette::read(&mut TextReader::new(input)).unwrap();
         let mut output = Vec::<u8>::new();
         palette.write(&mut output).unwrap();
         assert_eq!(&output as &[u8], input);
@@ -0,0 +1,671 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use crate::internal::color::Color;
+use crate::internal::font::{Font, Glyph};
+use crate::internal::image::Image;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Error, ErrorKind, Read, Write};
+
+// ========================================================================= //
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE_512: u8 = 0x01;
+const PSF1_MODE_HAS_TAB: u8 = 0x02;
+const PSF1_MODE_SEQ: u8 = 0x04;
+const PSF1_SEPARATOR: u16 = 0xFFFF;
+const PSF1_START_SEQ: u16 = 0xFFFE;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+const PSF2_HEADER_SIZE: u32 = 32;
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+const PSF2_SEPARATOR: u8 = 0xFF;
+const PSF2_START_SEQ: u8 = 0xFE;
+
+/// The largest glyph width or height accepted in a PSF2 header.
+const PSF2_MAX_SIZE: u32 = 0xFFFF;
+
+/// The largest number of glyphs accepted in a PSF2 header (one for each
+/// Unicode code point).
+const PSF2_MAX_GLYPHS: u32 = 0x110000;
+
+/// The character that the Linux console draws for unmapped characters, and
+/// which is therefore used for the font's default glyph.
+const REPLACEMENT_CHAR: char = '\u{FFFD}';
+
+/// The key used to report the default glyph from the PSF writers, matching
+/// the tag used for it by `Font::to_collection`.
+const DEFAULT_GLYPH_KEY: &str = "def";
+
+/// Distinct glyph bitmaps, each with the keys that map to it.
+type PsfBitmaps = Vec<(Vec<u8>, Vec<String>)>;
+
+// ========================================================================= //
+
+impl Font {
+    /// Reads a font from a Linux console PSF file, in either the PSF1 or
+    /// PSF2 format.  Set pixels in the glyph bitmaps become pixels of the
+    /// given color, and unset pixels become transparent.
+    ///
+    /// Each glyph is as wide as the font, with a left edge of zero and a
+    /// right edge equal to the width.  If the file has a Unicode table, each
+    /// glyph is assigned to the characters and sequences listed for it, and
+    /// the glyph for U+FFFD (if any) becomes the font's default glyph;
+    /// otherwise, glyph number _n_ is assigned to the character with code
+    /// point _n_.  Since PSF files have no baseline, the font's baseline is
+    /// set to its glyph height.
+    pub fn read_psf<R: Read>(mut reader: R, color: Color) -> io::Result<Font> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let mut data = PsfData { data: &data, pos: 0 };
+        if data.peek(2) == Some(&PSF1_MAGIC[..]) {
+            data.bytes(2)?;
+            let mode = data.bytes(1)?[0];
+            let height = data.bytes(1)?[0] as u32;
+            let num_glyphs = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+            let glyphs = data.glyphs(num_glyphs, 8, height, color)?;
+            let table = if mode & (PSF1_MODE_HAS_TAB | PSF1_MODE_SEQ) != 0 {
+                Some(data.psf1_table(num_glyphs)?)
+            } else {
+                None
+            };
+            Ok(psf_font(8, height, glyphs, table))
+        } else if data.peek(4) == Some(&PSF2_MAGIC[..]) {
+            data.bytes(4)?;
+            let _version = data.u32()?;
+            let header_size = data.u32()?;
+            let flags = data.u32()?;
+            let num_glyphs = data.u32()?;
+            let glyph_size = data.u32()?;
+            let height = data.u32()?;
+            let width = data.u32()?;
+            if header_size < PSF2_HEADER_SIZE {
+                return Err(invalid_data("PSF2 header is too small"));
+            }
+            if width > PSF2_MAX_SIZE || height > PSF2_MAX_SIZE {
+                return Err(invalid_data("PSF2 glyphs are too large"));
+            }
+            if num_glyphs > PSF2_MAX_GLYPHS {
+                return Err(invalid_data("PSF2 file has too many glyphs"));
+            }
+            if height.checked_mul(width.div_ceil(8)) != Some(glyph_size) {
+                return Err(invalid_data("PSF2 glyph size doesn't match"));
+            }
+            data.bytes((header_size - PSF2_HEADER_SIZE) as usize)?;
+            let num_glyphs = num_glyphs as usize;
+            let glyphs_size = (glyph_size as u64) * (num_glyphs as u64);
+            if glyphs_size > data.remaining() as u64 {
+                let msg = "unexpected end of PSF file";
+                return Err(Error::new(ErrorKind::UnexpectedEof, msg));
+            }
+            let glyphs = data.glyphs(num_glyphs, width, height, color)?;
+            let table = if flags & PSF2_HAS_UNICODE_TABLE != 0 {
+                Some(data.psf2_table(num_glyphs)?)
+            } else {
+                None
+            };
+            Ok(psf_font(width, height, glyphs, table))
+        } else {
+            Err(invalid_data("not a PSF file"))
+        }
+    }
+
+    /// Writes the font to a PSF1 file, along with a Unicode table mapping
+    /// each glyph to its character or sequence keys.  PSF1 glyphs are always
+    /// eight pixels wide, and a PSF1 file holds either 256 or 512 glyphs;
+    /// unused glyph slots are left blank.  See `write_psf2` for how glyphs
+    /// are converted and when `pad` is needed.
+    ///
+    /// Returns an error if the glyphs aren't all eight pixels wide (or, if
+    /// `pad` is true, if any glyph is wider than eight pixels), or if the
+    /// font has more than 512 distinct glyphs.
+    pub fn write_psf1<W: Write>(
+        &self,
+        mut writer: W,
+        pad: bool,
+    ) -> io::Result<BTreeSet<String>> {
+        let (width, glyphs, multicolor) = self.psf_glyphs(pad, 8)?;
+        if width != 8 {
+            let msg =
+                format!("PSF1 glyphs must be 8 pixels wide, not {}", width);
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        if self.glyph_height() > u8::MAX as u32 {
+            let msg = "PSF1 glyphs can't be more than 255 pixels tall";
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        let all_keys: Vec<String> =
+            glyphs.iter().flat_map(|(_, keys)| keys.iter().cloned()).collect();
+        let num_slots = match glyphs.len() {
+            0..=256 => 256,
+            257..=512 => 512,
+            num => {
+                let msg = format!("PSF1 can't hold {} distinct glyphs", num);
+                return Err(Error::new(ErrorKind::InvalidInput, msg));
+            }
+        };
+        let mut mode = PSF1_MODE_HAS_TAB;
+        if num_slots == 512 {
+            mode |= PSF1_MODE_512;
+        }
+        let (_, sequences) = split_keys(&all_keys);
+        if !sequences.is_empty() {
+            mode |= PSF1_MODE_SEQ;
+        }
+        writer.write_all(&PSF1_MAGIC)?;
+        writer.write_all(&[mode, self.glyph_height() as u8])?;
+        let blank = vec![0u8; self.glyph_height() as usize];
+        for index in 0..num_slots {
+            match glyphs.get(index) {
+                Some((bitmap, _)) => writer.write_all(bitmap)?,
+                None => writer.write_all(&blank)?,
+            }
+        }
+        for index in 0..num_slots {
+            let keys = glyphs.get(index).map(|(_, keys)| &keys[..]);
+            let (chars, sequences) = split_keys(keys.unwrap_or(&[]));
+            for chr in chars {
+                write_psf1_char(writer.by_ref(), chr)?;
+            }
+            for key in sequences {
+                writer.write_all(&PSF1_START_SEQ.to_le_bytes())?;
+                for chr in key.chars() {
+                    write_psf1_char(writer.by_ref(), chr)?;
+                }
+            }
+            writer.write_all(&PSF1_SEPARATOR.to_le_bytes())?;
+        }
+        Ok(multicolor)
+    }
+
+    /// Writes the font to a PSF2 file, along with a Unicode table mapping
+    /// each glyph to its character or sequence keys.  Returns the keys of any
+    /// glyphs that use more than one non-transparent color (and so can't be
+    /// represented exactly, since PSF glyphs are monochrome), with the
+    /// default glyph reported as `"def"`.
+    ///
+    /// PSF fonts are fixed-width, so every glyph must have the same advance
+    /// (the distance from its left edge to its right edge), which becomes
+    /// the font's width.  If `pad` is false, a font whose glyphs have
+    /// different advances is rejected with an error; if `pad` is true, the
+    /// width is instead the largest advance, and narrower glyphs are padded
+    /// with blank columns on the right.  Each glyph image is drawn into its
+    /// cell at its left edge and top offset, clipping anything outside the
+    /// cell.  Glyphs with identical bitmaps are written only once.
+    ///
+    /// The default glyph is mapped to U+FFFD (which the Linux console uses
+    /// for characters missing from the font), unless the font has its own
+    /// glyph for U+FFFD.  The kerning table, line spacing, and baseline have
+    /// no PSF equivalent, and are omitted.
+    pub fn write_psf2<W: Write>(
+        &self,
+        mut writer: W,
+        pad: bool,
+    ) -> io::Result<BTreeSet<String>> {
+        let (width, glyphs, multicolor) = self.psf_glyphs(pad, 0)?;
+        let height = self.glyph_height();
+        let glyph_size = match height.checked_mul(width.div_ceil(8)) {
+            Some(size) => size,
+            None => {
+                let msg = "PSF2 glyphs can't be more than 4 GiB each";
+                return Err(Error::new(ErrorKind::InvalidInput, msg));
+            }
+        };
+        let header = [
+            0,
+            PSF2_HEADER_SIZE,
+            PSF2_HAS_UNICODE_TABLE,
+            glyphs.len() as u32,
+            glyph_size,
+            height,
+            width,
+        ];
+        writer.write_all(&PSF2_MAGIC)?;
+        for value in header {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        for (bitmap, _) in glyphs.iter() {
+            writer.write_all(bitmap)?;
+        }
+        for (_, keys) in glyphs.iter() {
+            let (chars, sequences) = split_keys(keys);
+            for chr in chars {
+                write!(writer, "{}", chr)?;
+            }
+            for key in sequences {
+                writer.write_all(&[PSF2_START_SEQ])?;
+                write!(writer, "{}", key)?;
+            }
+            writer.write_all(&[PSF2_SEPARATOR])?;
+        }
+        Ok(multicolor)
+    }
+
+    /// Converts this font's glyphs into PSF bitmaps, returning the font's
+    /// width along with each distinct bitmap and the keys that map to it,
+    /// and the keys of any multi-color glyphs.  If `pad` is true, the width
+    /// is at least `min_width`.
+    fn psf_glyphs(
+        &self,
+        pad: bool,
+        min_width: i32,
+    ) -> io::Result<(u32, PsfBitmaps, BTreeSet<String>)> {
+        let advance = |glyph: &Glyph| glyph.right_edge() - glyph.left_edge();
+        let default_key = if self.get_char_glyph(REPLACEMENT_CHAR).is_some() {
+            None
+        } else {
+            Some(REPLACEMENT_CHAR.to_string())
+        };
+        let mut entries: Vec<(String, Option<String>, &Glyph)> = vec![(
+            DEFAULT_GLYPH_KEY.to_string(),
+            default_key,
+            self.default_glyph(),
+        )];
+        for chr in self.chars() {
+            let key = chr.to_string();
+            entries.push((key.clone(), Some(key), &self[chr]));
+        }
+        for key in self.sequences() {
+            let glyph = self.get_str_glyph(key).unwrap();
+            entries.push((key.to_string(), Some(key.to_string()), glyph));
+        }
+        let width = advance(self.default_glyph());
+        let width = if pad {
+            entries
+                .iter()
+                .map(|&(_, _, glyph)| advance(glyph))
+                .fold(min_width, i32::max)
+        } else {
+            match entries
+                .iter()
+                .find(|&&(_, _, glyph)| advance(glyph) != width)
+            {
+                Some((name, _, glyph)) => {
+                    let msg = format!(
+                        "PSF glyphs must all have the same width, but the \
+                         glyph for {:?} is {} pixels wide instead of {}",
+                        name,
+                        advance(glyph),
+                        width
+                    );
+                    return Err(Error::new(ErrorKind::InvalidInput, msg));
+                }
+                None => width,
+            }
+        };
+        let width = width.max(0) as u32;
+        let height = self.glyph_height();
+        let row_bytes = width.div_ceil(8) as usize;
+        let mut glyphs: PsfBitmaps = Vec::new();
+        let mut indices: BTreeMap<Vec<u8>, usize> = BTreeMap::new();
+        let mut multicolor = BTreeSet::new();
+        for (name, key, glyph) in entries {
+            let mut cell = Image::new(width, height);
+            cell.draw(glyph.image(), -glyph.left_edge(), glyph.top());
+            let mut bitmap = vec![0u8; row_bytes * height as usize];
+            let mut colors = BTreeSet::new();
+            for row in 0..height {
+                for col in 0..width {
+                    let color = cell[(col, row)];
+                    if color != Color::C0 {
+                        let index =
+                            row as usize * row_bytes + col as usize / 8;
+                        bitmap[index] |= 0x80 >> (col % 8);
+                        colors.insert(color.to_byte());
+                    }
+                }
+            }
+            if colors.len() > 1 {
+                multicolor.insert(name);
+            }
+            let index = *indices.entry(bitmap.clone()).or_insert_with(|| {
+                glyphs.push((bitmap, Vec::new()));
+                glyphs.len() - 1
+            });
+            glyphs[index].1.extend(key);
+        }
+        Ok((width, glyphs, multicolor))
+    }
+}
+
+/// Builds a font from decoded PSF glyph images and (optional) Unicode table.
+fn psf_font(
+    width: u32,
+    height: u32,
+    images: Vec<Image>,
+    table: Option<Vec<Vec<String>>>,
+) -> Font {
+    let mut font = Font::with_glyph_height(height);
+    font.set_default_glyph(Glyph::new(Image::new(0, height), 0, width as i32));
+    for (index, image) in images.into_iter().enumerate() {
+        let glyph = Glyph::new(image, 0, width as i32);
+        let keys = match table {
+            Some(ref table) => table[index].clone(),
+            None => match char::from_u32(index as u32) {
+                Some(chr) => vec![chr.to_string()],
+                None => Vec::new(),
+            },
+        };
+        for key in keys {
+            if key == REPLACEMENT_CHAR.to_string() {
+                font.set_default_glyph(glyph.clone());
+            } else {
+                font.set_str_glyph(&key, glyph.clone());
+            }
+        }
+    }
+    font
+}
+
+/// Splits glyph keys into single characters and multi-character sequences.
+fn split_keys(keys: &[String]) -> (Vec<char>, Vec<&str>) {
+    let mut chars = Vec::new();
+    let mut sequences = Vec::new();
+    for key in keys {
+        let mut iter = key.chars();
+        match (iter.next(), iter.next()) {
+            (Some(chr), None) => chars.push(chr),
+            _ => sequences.push(key.as_str()),
+        }
+    }
+    (chars, sequences)
+}
+
+fn write_psf1_char<W: Write>(mut writer: W, chr: char) -> io::Result<()> {
+    if chr as u32 >= PSF1_START_SEQ as u32 {
+        let msg = format!("PSF1 can't represent {:?}", chr);
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
+    }
+    writer.write_all(&(chr as u16).to_le_bytes())
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+// ========================================================================= //
+
+/// A cursor over the contents of a PSF file.
+struct PsfData<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PsfData<'a> {
+    fn peek(&self, len: usize) -> Option<&'a [u8]> {
+        self.data.get(self.pos..(self.pos + len))
+    }
+
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        match self.peek(len) {
+            Some(bytes) => {
+                self.pos += len;
+                Ok(bytes)
+            }
+            None => {
+                let msg = "unexpected end of PSF file";
+                Err(Error::new(ErrorKind::UnexpectedEof, msg))
+            }
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn glyphs(
+        &mut self,
+        num_glyphs: usize,
+        width: u32,
+        height: u32,
+        color: Color,
+    ) -> io::Result<Vec<Image>> {
+        let row_bytes = width.div_ceil(8) as usize;
+        let mut glyphs = Vec::new();
+        for _ in 0..num_glyphs {
+            let bitmap = self.bytes(row_bytes * height as usize)?;
+            let mut image = Image::new(width, height);
+            for row in 0..height {
+                for col in 0..width {
+                    let byte =
+                        bitmap[row as usize * row_bytes + col as usize / 8];
+                    if byte & (0x80 >> (col % 8)) != 0 {
+                        image[(col, row)] = color;
+                    }
+                }
+            }
+            glyphs.push(image);
+        }
+        Ok(glyphs)
+    }
+
+    fn psf1_table(
+        &mut self,
+        num_glyphs: usize,
+    ) -> io::Result<Vec<Vec<String>>> {
+        let mut table = Vec::new();
+        for _ in 0..num_glyphs {
+            let mut keys: Vec<String> = Vec::new();
+            let mut in_sequence = false;
+            loop {
+                match self.u16()? {
+                    PSF1_SEPARATOR => break,
+                    PSF1_START_SEQ => {
+                        keys.push(String::new());
+                        in_sequence = true;
+                    }
+                    value => {
+                        let chr =
+                            char::from_u32(value as u32).ok_or_else(|| {
+                                invalid_data("invalid PSF1 Unicode value")
+                            })?;
+                        match keys.last_mut() {
+                            Some(key) if in_sequence => key.push(chr),
+                            _ => keys.push(chr.to_string()),
+                        }
+                    }
+                }
+            }
+            keys.retain(|key| !key.is_empty());
+            table.push(keys);
+        }
+        Ok(table)
+    }
+
+    fn psf2_table(
+        &mut self,
+        num_glyphs: usize,
+    ) -> io::Result<Vec<Vec<String>>> {
+        let mut table = Vec::new();
+        for _ in 0..num_glyphs {
+            let start = self.pos;
+            while self.bytes(1)?[0] != PSF2_SEPARATOR {}
+            let entry = &self.data[start..(self.pos - 1)];
+            let mut parts = entry.split(|&byte| byte == PSF2_START_SEQ);
+            let chars = std::str::from_utf8(parts.next().unwrap_or(&[]))
+                .map_err(|_| invalid_data("invalid UTF-8 in PSF2 table"))?;
+            let mut keys: Vec<String> =
+                chars.chars().map(|chr| chr.to_string()).collect();
+            for part in parts {
+                let key = std::str::from_utf8(part).map_err(|_| {
+                    invalid_data("invalid UTF-8 in PSF2 table")
+                })?;
+                if !key.is_empty() {
+                    keys.push(key.to_string());
+                }
+            }
+            table.push(keys);
+        }
+        Ok(table)
+    }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::testutil::{font_from_rows, GlyphRows};
+
+    /// Glyphs that all have an advance of four pixels.  The tests move the
+    /// `"ll"` ligature down a row, to cover glyphs with a top offset.
+    const GLYPHS: &[GlyphRows] = &[
+        ("def", 0, 4, &["111", "111"]),
+        ("l", -1, 3, &["1", "1"]),
+        ("|", -1, 3, &["1", "1"]),
+        ("ll", 0, 4, &["23"]),
+    ];
+
+    #[test]
+    fn write_psf2() {
+        let mut font = font_from_rows(2, 2, GLYPHS);
+        font.get_str_glyph_mut("ll").unwrap().set_top(1);
+        let mut output = Vec::<u8>::new();
+        let multicolor = font.write_psf2(&mut output, false).unwrap();
+        assert_eq!(multicolor.into_iter().collect::<Vec<_>>(), vec!["ll"]);
+        let mut expected = PSF2_MAGIC.to_vec();
+        for value in [0u32, 32, 1, 3, 2, 2, 4] {
+            expected.extend_from_slice(&value.to_le_bytes());
+        }
+        expected.extend_from_slice(&[0xE0, 0xE0, 0x40, 0x40, 0x00, 0xC0]);
+        expected.extend_from_slice(b"\xEF\xBF\xBD\xFFl|\xFF\xFEll\xFF");
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn psf2_round_trip() {
+        let mut font = font_from_rows(2, 2, GLYPHS);
+        font.get_str_glyph_mut("ll").unwrap().set_top(1);
+        let mut output = Vec::<u8>::new();
+        font.write_psf2(&mut output, false).unwrap();
+        let copy = Font::read_psf(&output as &[u8], Color::C5).unwrap();
+        assert_eq!(copy.glyph_height(), 2);
+        assert_eq!(copy.chars().collect::<String>(), "l|");
+        assert_eq!(copy.sequences().collect::<Vec<_>>(), vec!["ll"]);
+        assert_eq!(copy.default_glyph().image().width(), 4);
+        assert_eq!(copy['l'].right_edge(), 4);
+        assert_eq!(copy['|'].image()[(1, 1)], Color::C5);
+        assert_eq!(copy['|'].image()[(0, 1)], Color::C0);
+        let ligature = copy.get_str_glyph("ll").unwrap();
+        assert_eq!(ligature.image()[(1, 1)], Color::C5);
+        assert_eq!(ligature.image()[(1, 0)], Color::C0);
+    }
+
+    #[test]
+    fn psf_rejects_or_pads_mixed_widths() {
+        let mut font = font_from_rows(2, 2, GLYPHS);
+        font.get_str_glyph_mut("ll").unwrap().set_top(1);
+        font.set_char_glyph('m', Glyph::new(Image::new(6, 2), 0, 6));
+        let error = font.write_psf2(Vec::<u8>::new(), false).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("\"m\" is 6 pixels wide"));
+        let mut output = Vec::<u8>::new();
+        font.write_psf2(&mut output, true).unwrap();
+        let copy = Font::read_psf(&output as &[u8], Color::C1).unwrap();
+        assert_eq!(copy['l'].right_edge(), 6);
+        assert_eq!(copy['m'].right_edge(), 6);
+    }
+
+    #[test]
+    fn psf1_round_trip() {
+        let mut font = font_from_rows(2, 2, GLYPHS);
+        font.get_str_glyph_mut("ll").unwrap().set_top(1);
+        let error = font.write_psf1(Vec::<u8>::new(), false).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let mut output = Vec::<u8>::new();
+        font.write_psf1(&mut output, true).unwrap();
+        assert_eq!(&output[..4], &[0x36, 0x04, 0x06, 2]);
+        assert_eq!(&output[4..10], &[0xE0, 0xE0, 0x40, 0x40, 0x00, 0xC0]);
+        assert_eq!(output.len(), 4 + 256 * 2 + (4 + 6 + 8) + 253 * 2);
+        let copy = Font::read_psf(&output as &[u8], Color::C1).unwrap();
+        assert_eq!(copy.chars().collect::<String>(), "l|");
+        assert_eq!(copy.sequences().collect::<Vec<_>>(), vec!["ll"]);
+        assert_eq!(copy['l'].right_edge(), 8);
+        assert_eq!(copy['|'].image()[(1, 0)], Color::C1);
+    }
+
+    #[test]
+    fn read_psf1_without_unicode_table() {
+        let mut input = vec![0x36, 0x04, 0x00, 1];
+        input.extend(0..=255u8);
+        let font = Font::read_psf(&input as &[u8], Color::C1).unwrap();
+        assert_eq!(font.chars().count(), 256);
+        assert_eq!(font['A'].image()[(1, 0)], Color::C1);
+        assert_eq!(font['A'].image()[(0, 0)], Color::C0);
+        assert_eq!(font['A'].image()[(7, 0)], Color::C1);
+    }
+
+    #[test]
+    fn read_invalid_psf() {
+        let error = Font::read_psf(&b"ahf0"[..], Color::C1).err().unwrap();
+        assert_eq!(error.to_string(), "not a PSF file");
+        let input: &[u8] = &[0x36, 0x04, 0x00, 1, 0xFF];
+        let error = Font::read_psf(input, Color::C1).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    fn psf2_header(num_glyphs: u32, height: u32, width: u32) -> Vec<u8> {
+        let glyph_size = height.wrapping_mul(width.div_ceil(8));
+        let mut header = PSF2_MAGIC.to_vec();
+        for value in [0, 32, 1, num_glyphs, glyph_size, height, width] {
+            header.extend_from_slice(&u32::to_le_bytes(value));
+        }
+        header
+    }
+
+    #[test]
+    fn read_truncated_psf2() {
+        let header = psf2_header(0xFFFFFFFF, 8, 8);
+        let error = Font::read_psf(&header[..], Color::C1).err().unwrap();
+        assert_eq!(error.to_string(), "PSF2 file has too many glyphs");
+        let mut input = psf2_header(0x10000, 8, 8);
+        input.extend_from_slice(&[0; 8]);
+        let error = Font::read_psf(&input[..], Color::C1).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        let mut input = psf2_header(2, 1, 8);
+        input.extend_from_slice(&[0xFF, 0xFF, b'a', PSF2_SEPARATOR]);
+        let error = Font::read_psf(&input[..], Color::C1).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        let error = Font::read_psf(&input[..20], Color::C1).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn read_oversized_psf2() {
+        let header = psf2_header(1, 0x10000, 0x100000);
+        let error = Font::read_psf(&header[..], Color::C1).err().unwrap();
+        assert_eq!(error.to_string(), "PSF2 glyphs are too large");
+        let mut header = psf2_header(1, 0xFFFF, 0xFFFF);
+        header[20..24].copy_from_slice(&u32::to_le_bytes(0xFFFF));
+        let error = Font::read_psf(&header[..], Color::C1).err().unwrap();
+        assert_eq!(error.to_string(), "PSF2 glyph size doesn't match");
+    }
+}
+
+// ========================================================================= //
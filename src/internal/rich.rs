@@ -0,0 +1,278 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use crate::internal::color::Color;
+use crate::internal::font::{Font, Glyph};
+use crate::internal::image::Image;
+use crate::internal::text::{PlacedGlyphs, TextMetrics};
+use std::cmp::min;
+
+// ========================================================================= //
+
+/// A run of text with its own styling, for drawing rich text with
+/// `Image::draw_spans`.
+///
+/// By default, a span is drawn with the font passed to the drawing function,
+/// using the glyphs' own colors, with no underline.
+#[derive(Clone)]
+pub struct Span<'a> {
+    text: String,
+    colors: [Color; 16],
+    font: Option<&'a Font>,
+    underline: Option<Color>,
+}
+
+impl<'a> Span<'a> {
+    /// Creates a new span with the given text and default styling.
+    pub fn new<S: Into<String>>(text: S) -> Span<'a> {
+        let mut colors = [Color::C0; 16];
+        for (index, color) in colors.iter_mut().enumerate() {
+            *color = Color::from_nibble(index as u8);
+        }
+        Span { text: text.into(), colors, font: None, underline: None }
+    }
+
+    /// Returns the text of this span.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Remaps glyph pixels of color `from` to color `to` when drawing this
+    /// span.  Transparent (`C0`) pixels are never drawn, so remapping `from`
+    /// `C0` has no effect; remapping a color `to` `C0` makes those pixels
+    /// transparent.
+    pub fn recolor(mut self, from: Color, to: Color) -> Span<'a> {
+        self.colors[from as usize] = to;
+        self
+    }
+
+    /// Draws this span with the given font, instead of the default one.
+    pub fn font(mut self, font: &'a Font) -> Span<'a> {
+        self.font = Some(font);
+        self
+    }
+
+    /// Underlines this span with the given color.  The underline is one
+    /// pixel thick, spans the span's advance width, and is drawn on the row
+    /// just below the baseline, behind the glyphs.
+    pub fn underline(mut self, color: Color) -> Span<'a> {
+        self.underline = Some(color);
+        self
+    }
+}
+
+// ========================================================================= //
+
+enum SpanItem<'a> {
+    Underline { x: i32, width: u32, color: Color },
+    Glyph { x: i32, y: i32, glyph: &'a Glyph, colors: &'a [Color; 16] },
+}
+
+/// Places the underlines and glyphs of a line of spans, relative to the
+/// origin, and returns the total advance width.  Kerning is applied within
+/// each span, but not between spans.  Every underline is visited before any
+/// glyph, so that drawing in visiting order puts all underlines behind all
+/// glyphs (even glyphs that overhang a neighboring span).
+fn place_spans<'a, F>(
+    font: &'a Font,
+    spans: &'a [Span<'a>],
+    mut visit: F,
+) -> i32
+where
+    F: FnMut(SpanItem<'a>),
+{
+    let mut pen = 0;
+    let mut glyph_items = Vec::new();
+    for span in spans.iter() {
+        let font = span.font.unwrap_or(font);
+        let mut glyphs = PlacedGlyphs::new(font, &span.text);
+        for (_, x, glyph) in glyphs.by_ref() {
+            glyph_items.push(SpanItem::Glyph {
+                x: pen + x,
+                y: glyph.top() - font.baseline(),
+                glyph,
+                colors: &span.colors,
+            });
+        }
+        let width = glyphs.pen();
+        if let Some(color) = span.underline {
+            let (x, width) = (pen + min(0, width), width.unsigned_abs());
+            visit(SpanItem::Underline { x, width, color });
+        }
+        pen += width;
+    }
+    for item in glyph_items {
+        visit(item);
+    }
+    pen
+}
+
+impl Font {
+    /// Measures a single line of rich text, as it would be drawn by
+    /// `Image::draw_spans` with this as the default font.
+    pub fn measure_spans(&self, spans: &[Span]) -> TextMetrics {
+        let mut metrics = TextMetrics::empty();
+        metrics.width = place_spans(self, spans, |item| match item {
+            SpanItem::Underline { x, width, .. } => {
+                metrics.include_rect(x, 0, width, 1);
+            }
+            SpanItem::Glyph { x, y, glyph, .. } => {
+                metrics.include_image(x, y, glyph.image());
+            }
+        });
+        metrics
+    }
+
+    /// Renders a single line of rich text into a new image, as with
+    /// `render`.  The image is tall enough to hold any glyph of this font
+    /// and of every span's font (including glyphs offset outside their
+    /// font's glyph cell), aligned on a common baseline.
+    pub fn render_spans(&self, spans: &[Span]) -> Image {
+        let fonts = || {
+            Some(self).into_iter().chain(spans.iter().filter_map(|s| s.font))
+        };
+        let top =
+            fonts().map(|font| -font.image_extent().0).min().unwrap_or(0);
+        let bottom =
+            fonts().map(|font| font.image_extent().1).max().unwrap_or(0);
+        let (x, y, width, height) =
+            self.measure_spans(spans).canvas(top, bottom);
+        let mut image = Image::new(width, height);
+        image.draw_spans(self, spans, -x, -y);
+        image
+    }
+}
+
+impl Image {
+    /// Draws a single line of rich text onto this image.  Each span is drawn
+    /// with its own font (or `font`, if the span doesn't set one), with its
+    /// colors remapped and transparent pixels skipped, as with `draw`.  All
+    /// spans share a common baseline, and the origin of the text is placed
+    /// at `(x, y)`.  Returns the x-position at which any following text would
+    /// start.
+    pub fn draw_spans(
+        &mut self,
+        font: &Font,
+        spans: &[Span],
+        x: i32,
+        y: i32,
+    ) -> i32 {
+        let width = place_spans(font, spans, |item| match item {
+            SpanItem::Underline { x: line_x, width, color } => {
+                self.fill_rect(x + line_x, y, width, 1, color);
+            }
+            SpanItem::Glyph { x: glyph_x, y: glyph_y, glyph, colors } => {
+                let image = glyph.image();
+                let (left, top) = (x + glyph_x, y + glyph_y);
+                for row in 0..image.height() {
+                    let dest_y = top + row as i32;
+                    if dest_y < 0 || dest_y >= self.height() as i32 {
+                        continue;
+                    }
+                    for col in 0..image.width() {
+                        let dest_x = left + col as i32;
+                        if dest_x < 0 || dest_x >= self.width() as i32 {
+                            continue;
+                        }
+                        let color = image[(col, row)];
+                        if color == Color::C0 {
+                            continue;
+                        }
+                        let color = colors[color as usize];
+                        if color != Color::C0 {
+                            self[(dest_x as u32, dest_y as u32)] = color;
+                        }
+                    }
+                }
+            }
+        });
+        x + width
+    }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::testutil::{font_from_rows, image_rows};
+
+    #[test]
+    fn draw_spans_with_colors() {
+        let font = font_from_rows(2, 2, &[("def", 0, 2, &["F", "1"])]);
+        let spans = [
+            Span::new("ab"),
+            Span::new("c").recolor(Color::C1, Color::C3),
+            Span::new("d").recolor(Color::Cf, Color::C0),
+        ];
+        let mut image = Image::new(8, 3);
+        image.fill_rect(0, 0, 8, 3, Color::Ce);
+        assert_eq!(image.draw_spans(&font, &spans, 0, 2), 8);
+        assert_eq!(
+            image_rows(&image),
+            vec!["FEFEFEEE", "1E1E3E1E", "EEEEEEEE"]
+        );
+    }
+
+    #[test]
+    fn render_spans_with_glyphs_outside_cell() {
+        let mut font = font_from_rows(
+            1,
+            1,
+            &[("a", 0, 1, &["1", "2"]), ("b", 0, 1, &["3"])],
+        );
+        font['a'].set_top(-1);
+        let image = font.render_spans(&[Span::new("b")]);
+        assert_eq!(image_rows(&image), vec!["0", "3"]);
+    }
+
+    #[test]
+    fn draw_underlines_behind_overhanging_glyphs() {
+        let font = font_from_rows(
+            2,
+            1,
+            &[("a", 0, 1, &["00", "11"]), ("b", 0, 1, &["0", "0"])],
+        );
+        let spans = [Span::new("a"), Span::new("b").underline(Color::C5)];
+        let mut image = Image::new(3, 2);
+        assert_eq!(image.draw_spans(&font, &spans, 0, 1), 2);
+        assert_eq!(image_rows(&image), vec!["000", "110"]);
+    }
+
+    #[test]
+    fn render_spans_with_fonts_and_underline() {
+        let small = font_from_rows(2, 2, &[("def", 0, 2, &["F", "1"])]);
+        let tall = font_from_rows(3, 2, &[("def", 0, 2, &["F", "2", "0"])]);
+        let spans = [
+            Span::new("a"),
+            Span::new("bc").font(&tall).underline(Color::C5),
+            Span::new("d"),
+        ];
+        let metrics = small.measure_spans(&spans);
+        assert_eq!(metrics.width(), 8);
+        assert_eq!(metrics.bounds(), (0, -2, 7, 3));
+        let image = small.render_spans(&spans);
+        assert_eq!(
+            image_rows(&image),
+            vec!["F0F0F0F0", "10202010", "00555500"]
+        );
+    }
+}
+
+// ========================================================================= //
@@ -0,0 +1,315 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use crate::internal::font::{Font, Glyph};
+use crate::internal::image::Image;
+use crate::internal::text::TextMetrics;
+use std::cmp::max;
+
+// ========================================================================= //
+
+/// A chain of fonts, used to render text that no single font fully covers.
+///
+/// Each character is looked up in the first font, then in each fallback font
+/// in turn; if no font has a glyph for the character, the first font's
+/// default glyph is used.  Glyphs from different fonts are aligned on a
+/// common baseline, so fonts with differing glyph heights and baselines can
+/// be mixed freely.
+#[derive(Clone)]
+pub struct FontStack {
+    fonts: Vec<Font>,
+}
+
+impl FontStack {
+    /// Creates a new stack containing just the given primary font.
+    pub fn new(font: Font) -> FontStack {
+        FontStack { fonts: vec![font] }
+    }
+
+    /// Adds a fallback font to the end of the stack.
+    pub fn push(&mut self, font: Font) {
+        self.fonts.push(font);
+    }
+
+    /// Returns the fonts in the stack, in lookup order.  The first font is
+    /// the primary font.
+    pub fn fonts(&self) -> &[Font] {
+        &self.fonts
+    }
+
+    /// Returns the glyph to use for the given character, along with the index
+    /// (into `fonts()`) of the font that supplied it.  If no font has a glyph
+    /// for the character, returns the primary font's default glyph.
+    pub fn resolve(&self, chr: char) -> (usize, &Glyph) {
+        for (index, font) in self.fonts.iter().enumerate() {
+            if let Some(glyph) = font.get_char_glyph(chr) {
+                return (index, glyph);
+            }
+        }
+        (0, self.fonts[0].default_glyph())
+    }
+
+    /// Returns the common baseline for the stack, measured in pixels down from
+    /// the top of a line.  This is the largest baseline of any font in the
+    /// stack (counting any glyphs offset above their font's glyph cell), so
+    /// that every font's glyphs fit above it.
+    pub fn baseline(&self) -> i32 {
+        self.fonts.iter().map(|font| font.image_extent().0).max().unwrap_or(0)
+    }
+
+    /// Returns the height of a line of text rendered with the stack, in
+    /// pixels.  This is enough to hold the glyphs of every font in the stack
+    /// (including any glyphs that extend below their font's glyph cell) when
+    /// they are aligned on the common baseline.
+    pub fn glyph_height(&self) -> u32 {
+        let descent = self
+            .fonts
+            .iter()
+            .map(|font| font.image_extent().1)
+            .max()
+            .unwrap_or(0);
+        max(0, self.baseline() + descent) as u32
+    }
+
+    /// Places each glyph for a single line of text, reporting which font
+    /// supplied it.  At each position, the first font with a glyph for the
+    /// next character uses its longest matching key (see
+    /// `Font::get_str_glyph`).  Kerning is applied between two successive
+    /// glyphs only if both were supplied by the same font.
+    pub fn glyphs(&self, text: &str) -> Vec<StackGlyph<'_>> {
+        self.place(text).0
+    }
+
+    fn place(&self, text: &str) -> (Vec<StackGlyph<'_>>, i32) {
+        let mut glyphs = Vec::with_capacity(text.len());
+        let mut pen = 0;
+        let mut prev: Option<(char, usize)> = None;
+        let mut rest = text;
+        while let Some(first) = rest.chars().next() {
+            let (font_index, len, glyph) = self
+                .fonts
+                .iter()
+                .enumerate()
+                .find_map(|(index, font)| {
+                    font.match_glyph(rest)
+                        .map(|(len, glyph)| (index, len, glyph))
+                })
+                .unwrap_or_else(|| {
+                    (0, first.len_utf8(), self.fonts[0].default_glyph())
+                });
+            let (key, remainder) = rest.split_at(len);
+            rest = remainder;
+            let font = &self.fonts[font_index];
+            if let Some((prev_chr, prev_index)) = prev {
+                if prev_index == font_index {
+                    pen += font.get_kerning(prev_chr, first);
+                }
+            }
+            prev = key.chars().next_back().map(|last| (last, font_index));
+            glyphs.push(StackGlyph {
+                text: key.to_string(),
+                font_index,
+                x: pen - glyph.left_edge(),
+                y: glyph.top() - font.baseline(),
+                glyph,
+            });
+            pen += glyph.right_edge() - glyph.left_edge();
+        }
+        (glyphs, pen)
+    }
+
+    /// Measures a single line of text as it would be drawn with the stack.
+    pub fn measure(&self, text: &str) -> TextMetrics {
+        let (glyphs, width) = self.place(text);
+        let mut metrics = TextMetrics::empty();
+        for placed in glyphs.iter() {
+            metrics.include_image(placed.x, placed.y, placed.glyph.image());
+        }
+        metrics.width = width;
+        metrics
+    }
+
+    /// Renders a single line of text into a new image, as with
+    /// `Font::render`.  The common baseline is `baseline()` pixels down from
+    /// the top of the image.
+    pub fn render(&self, text: &str) -> Image {
+        let top = -self.baseline();
+        let bottom = top + self.glyph_height() as i32;
+        let (x, y, width, height) = self.measure(text).canvas(top, bottom);
+        let mut image = Image::new(width, height);
+        image.draw_stack_text(self, text, -x, -y);
+        image
+    }
+}
+
+// ========================================================================= //
+
+/// A single glyph placed by a `FontStack`.
+#[derive(Clone)]
+pub struct StackGlyph<'a> {
+    text: String,
+    font_index: usize,
+    x: i32,
+    y: i32,
+    glyph: &'a Glyph,
+}
+
+impl<'a> StackGlyph<'a> {
+    /// Returns the text that this glyph represents (usually a single
+    /// character).
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns the index (into `FontStack::fonts()`) of the font that
+    /// supplied this glyph.
+    pub fn font_index(&self) -> usize {
+        self.font_index
+    }
+
+    /// Returns the position of the top-left corner of the glyph's image,
+    /// relative to the origin of the text (the point on the common baseline
+    /// at which the first glyph starts).
+    pub fn position(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    /// Returns the glyph itself.
+    pub fn glyph(&self) -> &'a Glyph {
+        self.glyph
+    }
+}
+
+// ========================================================================= //
+
+impl Image {
+    /// Draws a single line of text onto this image using a font stack, as
+    /// with `draw_text`.  The origin of the text is placed at `(x, y)`, with
+    /// `y` giving the position of the common baseline.  Returns the
+    /// x-position at which any following text would start.
+    pub fn draw_stack_text(
+        &mut self,
+        stack: &FontStack,
+        text: &str,
+        x: i32,
+        y: i32,
+    ) -> i32 {
+        let (glyphs, width) = stack.place(text);
+        for placed in glyphs.iter() {
+            self.draw(placed.glyph.image(), x + placed.x, y + placed.y);
+        }
+        x + width
+    }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::color::Color;
+    use crate::internal::testutil::{font_from_rows, image_rows};
+
+    fn test_stack() -> FontStack {
+        let mut latin = font_from_rows(
+            3,
+            2,
+            &[("def", 0, 1, &["1", "1", "1"]), ("a", 0, 1, &["2", "2", "0"])],
+        );
+        latin.set_kerning('a', 'a', 1);
+        latin.set_kerning('a', '*', 5);
+        let symbols = font_from_rows(
+            5,
+            3,
+            &[
+                ("*", 0, 2, &["33", "33", "33", "33", "33"]),
+                ("a", 0, 2, &["44", "44", "44", "44", "44"]),
+            ],
+        );
+        let mut stack = FontStack::new(latin);
+        stack.push(symbols);
+        stack
+    }
+
+    #[test]
+    fn resolve_chars_across_fonts() {
+        let stack = test_stack();
+        assert_eq!(stack.fonts().len(), 2);
+        assert_eq!(stack.resolve('a').0, 0);
+        assert_eq!(stack.resolve('*').0, 1);
+        let (index, glyph) = stack.resolve('?');
+        assert_eq!(index, 0);
+        assert_eq!(glyph.image()[(0, 0)], Color::C1);
+    }
+
+    #[test]
+    fn reconcile_baselines_and_heights() {
+        let stack = test_stack();
+        assert_eq!(stack.baseline(), 3);
+        assert_eq!(stack.glyph_height(), 5);
+        let glyphs = stack.glyphs("aa*?");
+        let summary: Vec<(&str, usize, (i32, i32))> = glyphs
+            .iter()
+            .map(|placed| {
+                (placed.text(), placed.font_index(), placed.position())
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("a", 0, (0, -2)),
+                ("a", 0, (2, -2)),
+                ("*", 1, (3, -3)),
+                ("?", 0, (5, -2)),
+            ]
+        );
+        let metrics = stack.measure("aa*?");
+        assert_eq!(metrics.width(), 6);
+        assert_eq!(metrics.bounds(), (0, -3, 6, 5));
+    }
+
+    #[test]
+    fn stack_with_glyphs_outside_cell() {
+        let mut symbols = font_from_rows(
+            2,
+            1,
+            &[("_", 0, 1, &["5", "5", "5"]), ("^", 0, 1, &["6"])],
+        );
+        symbols['^'].set_top(-2);
+        let mut stack = FontStack::new(symbols);
+        stack.push(test_stack().fonts()[0].clone());
+        assert_eq!(stack.baseline(), 3);
+        assert_eq!(stack.glyph_height(), 5);
+        assert_eq!(
+            image_rows(&stack.render("^_a")),
+            vec!["600", "002", "052", "050", "050"]
+        );
+    }
+
+    #[test]
+    fn render_stack_text() {
+        let stack = test_stack();
+        assert_eq!(
+            image_rows(&stack.render("a*?")),
+            vec!["0330", "2331", "2331", "0331", "0330"]
+        );
+    }
+}
+
+// ========================================================================= //
@@ -0,0 +1,123 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use crate::internal::font::Font;
+use std::collections::BTreeSet;
+
+// ========================================================================= //
+
+impl Font {
+    /// Returns the set of characters used in the given texts, suitable for
+    /// passing to `subset`.  Newlines are excluded, since they are treated as
+    /// line breaks by `layout` rather than drawn as glyphs.
+    pub fn used_chars<I, S>(texts: I) -> BTreeSet<char>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut chars = BTreeSet::new();
+        for text in texts {
+            chars.extend(text.as_ref().chars().filter(|&chr| chr != '\n'));
+        }
+        chars
+    }
+
+    /// Returns a copy of this font containing only the glyphs for the given
+    /// characters, along with the set of requested characters that have no
+    /// glyph in this font (and so will be drawn with the default glyph).  The
+    /// new font keeps this font's default glyph, other properties, any
+    /// multi-character sequences made up entirely of requested characters,
+    /// and any kerning pairs whose characters are both kept.
+    pub fn subset<I>(&self, chars: I) -> (Font, BTreeSet<char>)
+    where
+        I: IntoIterator<Item = char>,
+    {
+        let requested: BTreeSet<char> = chars.into_iter().collect();
+        let mut font = self.clone();
+        for chr in self.chars() {
+            if !requested.contains(&chr) {
+                font.remove_char_glyph(chr);
+            }
+        }
+        for key in self.sequences() {
+            if !key.chars().all(|chr| requested.contains(&chr)) {
+                font.remove_str_glyph(key);
+            }
+        }
+        for ((left, right), _) in self.kerning_pairs() {
+            if font.get_char_glyph(left).is_none()
+                || font.get_char_glyph(right).is_none()
+            {
+                font.set_kerning(left, right, 0);
+            }
+        }
+        let missing = requested
+            .into_iter()
+            .filter(|&chr| self.get_char_glyph(chr).is_none())
+            .collect();
+        (font, missing)
+    }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::font::Glyph;
+    use crate::internal::image::Image;
+
+    #[test]
+    fn used_chars_in_texts() {
+        let texts = vec!["Hello,\nworld", "Hola"];
+        let chars = Font::used_chars(&texts);
+        assert_eq!(chars.into_iter().collect::<String>(), ",Hadelorw");
+        let owned = vec![String::from("a b")];
+        assert_eq!(Font::used_chars(owned).len(), 3);
+    }
+
+    #[test]
+    fn subset_font() {
+        let mut font = Font::with_glyph_height(1);
+        font.set_name("Full");
+        font.set_default_glyph(Glyph::new(Image::new(1, 1), 0, 2));
+        for chr in "ATVo".chars() {
+            font.set_char_glyph(chr, Glyph::new(Image::new(1, 1), 0, 2));
+        }
+        font.set_str_glyph("AV", Glyph::new(Image::new(2, 1), 0, 3));
+        font.set_str_glyph("To", Glyph::new(Image::new(2, 1), 0, 3));
+        font.set_kerning('A', 'V', -1);
+        font.set_kerning('T', 'o', -2);
+        font.set_kerning('V', 'A', -1);
+
+        let (subset, missing) = font.subset("VAx!".chars());
+        assert_eq!(subset.name(), "Full");
+        assert_eq!(subset.default_glyph().right_edge(), 2);
+        assert_eq!(subset.chars().collect::<String>(), "AV");
+        assert_eq!(subset.sequences().collect::<Vec<_>>(), vec!["AV"]);
+        assert_eq!(
+            subset.kerning_pairs().collect::<Vec<_>>(),
+            vec![(('A', 'V'), -1), (('V', 'A'), -1)]
+        );
+        assert_eq!(missing.into_iter().collect::<String>(), "!x");
+        assert_eq!(font.chars().len(), 4);
+    }
+}
+
+// ========================================================================= //
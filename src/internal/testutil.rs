@@ -0,0 +1,72 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use crate::internal::color::Color;
+use crate::internal::font::{Font, Glyph};
+use crate::internal::image::Image;
+
+// ========================================================================= //
+
+/// A glyph for `font_from_rows`: its key (or `"def"` for the default glyph),
+/// its left and right edges, and its image's rows of hex digits.
+pub type GlyphRows<'a> = (&'a str, i32, i32, &'a [&'a str]);
+
+/// Returns the rows of the image as strings of hex digits, as they would
+/// appear in an AHI file.
+pub fn image_rows(image: &Image) -> Vec<String> {
+    let mut output = Vec::<u8>::new();
+    image.write(&mut output).unwrap();
+    String::from_utf8(output).unwrap().lines().map(str::to_string).collect()
+}
+
+/// Returns an image whose pixels are given by rows of hex digits, as they
+/// would appear in an AHI file.
+pub fn image_from_rows(rows: &[&str]) -> Image {
+    let width = rows.first().map_or(0, |row| row.len() as u32);
+    let mut image = Image::new(width, rows.len() as u32);
+    for (row, digits) in rows.iter().enumerate() {
+        assert_eq!(digits.len() as u32, width);
+        for (col, digit) in digits.bytes().enumerate() {
+            image[(col as u32, row as u32)] = Color::from_byte(digit).unwrap();
+        }
+    }
+    image
+}
+
+/// Returns a font with the given glyph height and baseline, and the given
+/// glyphs.
+pub fn font_from_rows(
+    height: u32,
+    baseline: i32,
+    glyphs: &[GlyphRows],
+) -> Font {
+    let mut font = Font::with_glyph_height(height);
+    font.set_baseline(baseline);
+    for &(key, left, right, rows) in glyphs.iter() {
+        let glyph = Glyph::new(image_from_rows(rows), left, right);
+        if key == "def" {
+            font.set_default_glyph(glyph);
+        } else {
+            font.set_str_glyph(key, glyph);
+        }
+    }
+    font
+}
+
+// ========================================================================= //
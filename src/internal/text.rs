@@ -0,0 +1,326 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use crate::internal::font::{Font, Glyph};
+use crate::internal::image::Image;
+use std::cmp::{max, min};
+
+// ========================================================================= //
+
+/// The size of a single line of text, as measured by `Font::measure`.
+///
+/// All positions are relative to the _origin_ of the text, which is the point
+/// on the baseline at which the first glyph starts.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TextMetrics {
+    pub(crate) width: i32,
+    left: i32,
+    top: i32,
+    right: i32,
+    bottom: i32,
+}
+
+impl TextMetrics {
+    /// Returns metrics with zero width and empty bounds.
+    pub(crate) fn empty() -> TextMetrics {
+        TextMetrics { width: 0, left: 0, top: 0, right: 0, bottom: 0 }
+    }
+
+    /// Expands the bounds to include an image whose top-left corner is at
+    /// `(x, y)` relative to the origin.  Images with zero area are ignored.
+    pub(crate) fn include_image(&mut self, x: i32, y: i32, image: &Image) {
+        self.include_rect(x, y, image.width(), image.height());
+    }
+
+    /// Expands the bounds to include the given rectangle, relative to the
+    /// origin.  Rectangles with zero area are ignored.
+    pub(crate) fn include_rect(&mut self, x: i32, y: i32, w: u32, h: u32) {
+        let (width, height) = (w as i32, h as i32);
+        if width == 0 || height == 0 {
+            return;
+        }
+        if self.left == self.right {
+            self.left = x;
+            self.top = y;
+            self.right = x + width;
+            self.bottom = y + height;
+        } else {
+            self.left = min(self.left, x);
+            self.top = min(self.top, y);
+            self.right = max(self.right, x + width);
+            self.bottom = max(self.bottom, y + height);
+        }
+    }
+
+    /// Returns the rectangle, relative to the origin, needed to render the
+    /// text: the union of the glyph bounds, the advance width, and the line
+    /// box spanning from `top` to `bottom`.
+    pub(crate) fn canvas(
+        &self,
+        top: i32,
+        bottom: i32,
+    ) -> (i32, i32, u32, u32) {
+        let (mut left, mut right) = (min(0, self.width), max(0, self.width));
+        let (mut top, mut bottom) = (top, bottom);
+        if self.left != self.right {
+            left = min(left, self.left);
+            right = max(right, self.right);
+            top = min(top, self.top);
+            bottom = max(bottom, self.bottom);
+        }
+        (left, top, max(0, right - left) as u32, max(0, bottom - top) as u32)
+    }
+
+    /// Returns the advance width of the text, in pixels.  This is the
+    /// horizontal distance from the origin to the point at which any
+    /// following text would start.
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    /// Returns the bounding rectangle of all the glyph images in the text, as
+    /// `(x, y, width, height)`, relative to the origin.  Because glyph images
+    /// can extend past their left and right edges, this may start to the left
+    /// of the origin or end to the right of the advance width.  The rectangle
+    /// is empty if the text has no glyph images with nonzero size.
+    pub fn bounds(&self) -> (i32, i32, u32, u32) {
+        (
+            self.left,
+            self.top,
+            (self.right - self.left) as u32,
+            (self.bottom - self.top) as u32,
+        )
+    }
+}
+
+// ========================================================================= //
+
+/// An iterator over the glyphs for a single line of text, yielding the key
+/// (a single character, or a multi-character sequence) that each glyph
+/// represents, along with the x-position, relative to the origin, of the left
+/// edge of the glyph's image.  At each position, the longest key with a glyph
+/// in the font is used.  Kerning adjustments are applied between the last
+/// character of each key and the first character of the next.
+pub(crate) struct PlacedGlyphs<'f, 't> {
+    font: &'f Font,
+    rest: &'t str,
+    prev: Option<char>,
+    pen: i32,
+}
+
+impl<'f, 't> PlacedGlyphs<'f, 't> {
+    pub(crate) fn new(font: &'f Font, text: &'t str) -> PlacedGlyphs<'f, 't> {
+        PlacedGlyphs { font, rest: text, prev: None, pen: 0 }
+    }
+
+    /// Returns the current pen position, relative to the origin.  Once the
+    /// iterator is exhausted, this is the advance width of the text.
+    pub(crate) fn pen(&self) -> i32 {
+        self.pen
+    }
+}
+
+impl<'f, 't> Iterator for PlacedGlyphs<'f, 't> {
+    type Item = (&'t str, i32, &'f Glyph);
+
+    fn next(&mut self) -> Option<(&'t str, i32, &'f Glyph)> {
+        let first = self.rest.chars().next()?;
+        let (len, glyph) = self
+            .font
+            .match_glyph(self.rest)
+            .unwrap_or((first.len_utf8(), self.font.default_glyph()));
+        let (key, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        if let Some(prev) = self.prev {
+            self.pen += self.font.get_kerning(prev, first);
+        }
+        self.prev = key.chars().next_back();
+        let x = self.pen - glyph.left_edge();
+        self.pen += glyph.right_edge() - glyph.left_edge();
+        Some((key, x, glyph))
+    }
+}
+
+// ========================================================================= //
+
+impl Font {
+    /// Measures a single line of text as it would be drawn with this font.
+    /// Characters with no glyph in the font are measured using the default
+    /// glyph, and kerning adjustments are applied between successive
+    /// characters.  No line breaking is performed.
+    pub fn measure(&self, text: &str) -> TextMetrics {
+        let top = -self.baseline();
+        let mut metrics = TextMetrics::empty();
+        let mut glyphs = PlacedGlyphs::new(self, text);
+        for (_, x, glyph) in glyphs.by_ref() {
+            metrics.include_image(x, top + glyph.top(), glyph.image());
+        }
+        metrics.width = glyphs.pen();
+        metrics
+    }
+
+    /// Renders a single line of text into a new image.  The image is tall
+    /// enough to hold the font's glyphs, with the baseline `baseline()` pixels
+    /// down from the top, and wide enough to hold both the text's advance
+    /// width and any glyph images that overhang it.  The origin of the text
+    /// is at the left edge of the image, unless some glyph extends to the left
+    /// of the origin, in which case the image is widened to include it.
+    pub fn render(&self, text: &str) -> Image {
+        let top = -self.baseline();
+        let bottom = top + self.glyph_height() as i32;
+        let (x, y, width, height) = self.measure(text).canvas(top, bottom);
+        let mut image = Image::new(width, height);
+        image.draw_text(self, text, -x, -y);
+        image
+    }
+}
+
+impl Image {
+    /// Draws a single line of text onto this image using the given font.  The
+    /// origin of the text (the point on the baseline at which the first glyph
+    /// starts) is placed at `(x, y)`.  Glyph images that fall outside this
+    /// image are clipped.  Returns the x-position at which any following text
+    /// would start.
+    pub fn draw_text(
+        &mut self,
+        font: &Font,
+        text: &str,
+        x: i32,
+        y: i32,
+    ) -> i32 {
+        let top = y - font.baseline();
+        let mut glyphs = PlacedGlyphs::new(font, text);
+        for (_, glyph_x, glyph) in glyphs.by_ref() {
+            self.draw(glyph.image(), x + glyph_x, top + glyph.top());
+        }
+        x + glyphs.pen()
+    }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use crate::internal::color::Color;
+    use crate::internal::font::Glyph;
+    use crate::internal::image::Image;
+    use crate::internal::testutil::{font_from_rows, image_rows, GlyphRows};
+
+    const GLYPHS: &[GlyphRows] = &[
+        ("def", 0, 3, &["11", "11", "00"]),
+        ("i", 0, 2, &["2", "2", "0"]),
+        ("j", 2, 4, &["003", "003", "330"]),
+        (" ", 0, 2, &["", "", ""]),
+    ];
+
+    #[test]
+    fn measure_empty_text() {
+        let metrics = font_from_rows(3, 2, GLYPHS).measure("");
+        assert_eq!(metrics.width(), 0);
+        assert_eq!(metrics.bounds(), (0, 0, 0, 0));
+        let metrics = font_from_rows(3, 2, GLYPHS).measure("  ");
+        assert_eq!(metrics.width(), 4);
+        assert_eq!(metrics.bounds(), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn measure_text() {
+        let font = font_from_rows(3, 2, GLYPHS);
+        let metrics = font.measure("ii?");
+        assert_eq!(metrics.width(), 7);
+        assert_eq!(metrics.bounds(), (0, -2, 6, 3));
+        let metrics = font.measure("j");
+        assert_eq!(metrics.width(), 2);
+        assert_eq!(metrics.bounds(), (-2, -2, 3, 3));
+    }
+
+    #[test]
+    fn measure_text_with_kerning() {
+        let mut font = font_from_rows(3, 2, GLYPHS);
+        font.set_kerning('i', 'i', -1);
+        font.set_kerning('i', 'j', 2);
+        assert_eq!(font.measure("iii").width(), 4);
+        assert_eq!(font.measure("ij").width(), 6);
+        assert_eq!(font.measure("ji").width(), 4);
+    }
+
+    #[test]
+    fn render_text() {
+        let font = font_from_rows(3, 2, GLYPHS);
+        let image = font.render("i?i");
+        assert_eq!(image_rows(&image), vec!["2011020", "2011020", "0000000"]);
+    }
+
+    #[test]
+    fn render_text_with_left_overhang() {
+        let font = font_from_rows(3, 2, GLYPHS);
+        let image = font.render("ij");
+        assert_eq!(image_rows(&image), vec!["2030", "2030", "3300"]);
+        let image = font.render("ji");
+        assert_eq!(image_rows(&image), vec!["003020", "003020", "330000"]);
+    }
+
+    #[test]
+    fn draw_text_relative_to_baseline() {
+        let font = font_from_rows(3, 2, GLYPHS);
+        let mut image = Image::new(5, 4);
+        let end = image.draw_text(&font, "ij", 1, 3);
+        assert_eq!(end, 5);
+        assert_eq!(
+            image_rows(&image),
+            vec!["00000", "02030", "02030", "03300"]
+        );
+        let mut image = Image::new(3, 2);
+        assert_eq!(image.draw_text(&font, "?", -1, 1), 2);
+        assert_eq!(image_rows(&image), vec!["100", "000"]);
+    }
+
+    #[test]
+    fn render_text_with_ligature() {
+        let mut font = font_from_rows(3, 2, GLYPHS);
+        let mut image = Image::new(2, 3);
+        image.fill_rect(0, 1, 2, 1, Color::C5);
+        font.set_str_glyph("ij", Glyph::new(image, 0, 3));
+        font.set_kerning('j', 'i', 1);
+        font.set_kerning('i', 'i', -1);
+        assert_eq!(font.measure("iji").width(), 6);
+        let image = font.render("iji");
+        assert_eq!(image_rows(&image), vec!["000020", "550020", "000000"]);
+    }
+
+    #[test]
+    fn render_text_with_offset_glyphs() {
+        let mut font = font_from_rows(3, 2, GLYPHS);
+        let mut image = Image::new(1, 1);
+        image[(0, 0)] = Color::C4;
+        let mut dot = Glyph::new(image.clone(), 0, 2);
+        dot.set_top(1);
+        font.set_char_glyph('.', dot);
+        let mut accent = Glyph::new(image, 0, 2);
+        accent.set_top(-1);
+        font.set_char_glyph('^', accent);
+        let image = font.render("i.");
+        assert_eq!(image_rows(&image), vec!["2000", "2040", "0000"]);
+        assert_eq!(font.measure("^").bounds(), (0, -3, 1, 1));
+        let image = font.render("^");
+        assert_eq!(image_rows(&image), vec!["40", "00", "00", "00"]);
+    }
+}
+
+// ========================================================================= //
@@ -0,0 +1,829 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use crate::internal::color::Color;
+use crate::internal::font::{Font, Glyph};
+use std::cmp::{max, min};
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fmt::Display;
+use std::io::{self, Error, ErrorKind, Write};
+
+// ========================================================================= //
+
+/// The number of font units per pixel.  The em square is as tall as the
+/// font's glyph height, so that the font renders pixel-exactly at a size
+/// equal to its glyph height.
+const UNITS_PER_PIXEL: i32 = 64;
+
+/// The value that the checksum of the whole font file must add up to.
+const CHECKSUM_MAGIC: u32 = 0xB1B0_AFBA;
+
+const HEAD_MAGIC: u32 = 0x5F0F_3CF5;
+
+/// The TrueType glyph flag for points that lie on the curve.
+const ON_CURVE: u8 = 0x01;
+
+// ========================================================================= //
+
+impl Font {
+    /// Writes the font as a TrueType font file, with each glyph's image
+    /// converted into outlines made of pixel squares.  Adjacent pixels are
+    /// merged into a single outline (with holes where needed), and every
+    /// non-transparent pixel is filled, so multi-color glyphs are flattened
+    /// to a single color.
+    ///
+    /// The font's em square is as tall as its glyph height, with 64 font
+    /// units per pixel, so the font renders pixel-exactly at a size equal to
+    /// its glyph height.  The ascent is the font's baseline, and the line gap
+    /// is the amount by which the line spacing exceeds the glyph height.
+    /// Each glyph's advance width is the distance from its left edge to its
+    /// right edge, and its outline is positioned relative to its left edge
+    /// (so that the left side bearing is the distance from the left edge to
+    /// the glyph's leftmost pixel).  The default glyph becomes glyph 0
+    /// (`.notdef`), which is drawn for missing characters.
+    ///
+    /// Along with the `cmap`, `glyf`, `head`, `hhea`, `hmtx`, and `name`
+    /// tables, the `loca`, `maxp`, `post`, and `OS/2` tables required by most
+    /// font software are written.  Multi-character glyph keys and the kerning
+    /// table are omitted.
+    ///
+    /// Returns an error if the glyph height is zero or greater than 256, if
+    /// the font has too many glyphs (or a name too long) for the font's
+    /// tables, or if any glyph is too large to be represented.
+    pub fn write_ttf<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let height = self.glyph_height() as i32;
+        if !(1..=256).contains(&height) {
+            let msg = format!(
+                "TrueType font height must be from 1 to 256 pixels, not {}",
+                height
+            );
+            return Err(Error::new(ErrorKind::InvalidInput, msg));
+        }
+        let mut glyphs = vec![TtfGlyph::new(self.default_glyph(), self)?];
+        let chars: Vec<char> = self.chars().collect();
+        for &chr in chars.iter() {
+            glyphs.push(TtfGlyph::new(&self[chr], self)?);
+        }
+        let metrics = TtfMetrics::new(self, &glyphs)?;
+        let (glyf, loca) = glyf_and_loca_tables(&glyphs)?;
+        let tables: Vec<([u8; 4], Vec<u8>)> = vec![
+            (*b"OS/2", os2_table(&metrics, &chars)),
+            (*b"cmap", cmap_table(&chars)?),
+            (*b"glyf", glyf),
+            (*b"head", head_table(&metrics)),
+            (*b"hhea", hhea_table(&metrics, &glyphs)?),
+            (*b"hmtx", hmtx_table(&glyphs)),
+            (*b"loca", loca),
+            (*b"maxp", maxp_table(&glyphs)?),
+            (*b"name", name_table(self.name())?),
+            (*b"post", post_table(&metrics, &glyphs)),
+        ];
+        writer.write_all(&sfnt_file(tables))
+    }
+}
+
+// ========================================================================= //
+
+/// A glyph converted into TrueType outlines, in font units.
+struct TtfGlyph {
+    contours: Vec<Vec<(i16, i16)>>,
+    advance: u16,
+    bounds: Option<(i16, i16, i16, i16)>,
+}
+
+impl TtfGlyph {
+    fn new(glyph: &Glyph, font: &Font) -> io::Result<TtfGlyph> {
+        let advance = max(0, glyph.right_edge() - glyph.left_edge());
+        let advance = to_u16(advance * UNITS_PER_PIXEL)?;
+        let x_origin = glyph.left_edge();
+        let y_origin = font.baseline() - glyph.top();
+        let mut contours = Vec::new();
+        let mut bounds: Option<(i16, i16, i16, i16)> = None;
+        for contour in trace_outlines(glyph) {
+            let mut points = Vec::with_capacity(contour.len());
+            for (col, row) in contour {
+                let x = to_i16((col - x_origin) * UNITS_PER_PIXEL)?;
+                let y = to_i16((y_origin - row) * UNITS_PER_PIXEL)?;
+                bounds = Some(match bounds {
+                    None => (x, y, x, y),
+                    Some((x0, y0, x1, y1)) => {
+                        (min(x0, x), min(y0, y), max(x1, x), max(y1, y))
+                    }
+                });
+                points.push((x, y));
+            }
+            contours.push(points);
+        }
+        Ok(TtfGlyph { contours, advance, bounds })
+    }
+
+    fn left_side_bearing(&self) -> i16 {
+        self.bounds.map(|(x_min, _, _, _)| x_min).unwrap_or(0)
+    }
+
+    fn num_points(&self) -> usize {
+        self.contours.iter().map(Vec::len).sum()
+    }
+}
+
+/// Traces the outlines of the non-transparent pixels of a glyph's image,
+/// returning contours of pixel-corner coordinates `(col, row)`.  Outer
+/// contours run clockwise (as seen on screen) and holes counterclockwise, as
+/// TrueType requires, and points in the middle of straight runs are omitted.
+fn trace_outlines(glyph: &Glyph) -> Vec<Vec<(i32, i32)>> {
+    let image = glyph.image();
+    let (width, height) = (image.width() as i32, image.height() as i32);
+    let filled = |col: i32, row: i32| {
+        col >= 0
+            && row >= 0
+            && col < width
+            && row < height
+            && image[(col as u32, row as u32)] != Color::C0
+    };
+    // Collect the boundary edges of each filled pixel, directed so that the
+    // filled pixel lies to the right of each edge.
+    let mut edges: BTreeMap<(i32, i32), Vec<(i32, i32)>> = BTreeMap::new();
+    let mut add_edge = |from: (i32, i32), to: (i32, i32)| {
+        edges.entry(from).or_default().push(to);
+    };
+    for row in 0..height {
+        for col in 0..width {
+            if !filled(col, row) {
+                continue;
+            }
+            if !filled(col, row - 1) {
+                add_edge((col, row), (col + 1, row));
+            }
+            if !filled(col + 1, row) {
+                add_edge((col + 1, row), (col + 1, row + 1));
+            }
+            if !filled(col, row + 1) {
+                add_edge((col + 1, row + 1), (col, row + 1));
+            }
+            if !filled(col - 1, row) {
+                add_edge((col, row + 1), (col, row));
+            }
+        }
+    }
+    // Link the edges into closed loops.  Every corner has as many edges
+    // leaving it as arriving, so following edges from any starting corner
+    // always leads back to it.
+    let mut contours = Vec::new();
+    while let Some(&start) = edges.keys().next() {
+        let mut points = vec![start];
+        let mut current = start;
+        loop {
+            let ends = edges.get_mut(&current).unwrap();
+            let next = ends.pop().unwrap();
+            if ends.is_empty() {
+                edges.remove(&current);
+            }
+            if next == start {
+                break;
+            }
+            points.push(next);
+            current = next;
+        }
+        contours.push(remove_collinear_points(points));
+    }
+    contours
+}
+
+/// Removes the points of a closed contour that lie in the middle of a
+/// straight segment.
+fn remove_collinear_points(points: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
+    let num_points = points.len();
+    (0..num_points)
+        .filter(|&index| {
+            let (px, py) = points[(index + num_points - 1) % num_points];
+            let (x, y) = points[index];
+            let (nx, ny) = points[(index + 1) % num_points];
+            (x - px) * (ny - y) != (y - py) * (nx - x)
+        })
+        .map(|index| points[index])
+        .collect()
+}
+
+// ========================================================================= //
+
+/// Font-wide metrics, in font units.
+struct TtfMetrics {
+    units_per_em: u16,
+    ascender: i16,
+    descender: i16,
+    line_gap: i16,
+    bounds: (i16, i16, i16, i16),
+    pixel_height: u16,
+}
+
+impl TtfMetrics {
+    fn new(font: &Font, glyphs: &[TtfGlyph]) -> io::Result<TtfMetrics> {
+        let height = font.glyph_height() as i32;
+        let line_gap = max(0, font.line_spacing() as i32 - height);
+        let bounds = glyphs
+            .iter()
+            .filter_map(|glyph| glyph.bounds)
+            .reduce(|(a0, b0, a1, b1), (x0, y0, x1, y1)| {
+                (min(a0, x0), min(b0, y0), max(a1, x1), max(b1, y1))
+            })
+            .unwrap_or((0, 0, 0, 0));
+        Ok(TtfMetrics {
+            units_per_em: to_u16(height * UNITS_PER_PIXEL)?,
+            ascender: to_i16(font.baseline() * UNITS_PER_PIXEL)?,
+            descender: to_i16((font.baseline() - height) * UNITS_PER_PIXEL)?,
+            line_gap: to_i16(line_gap * UNITS_PER_PIXEL)?,
+            bounds,
+            pixel_height: height as u16,
+        })
+    }
+}
+
+fn glyf_and_loca_tables(
+    glyphs: &[TtfGlyph],
+) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let mut glyf = Vec::new();
+    let mut loca = Vec::new();
+    for glyph in glyphs.iter() {
+        push_u32(&mut loca, glyf.len() as u32);
+        let (x_min, y_min, x_max, y_max) = match glyph.bounds {
+            Some(bounds) => bounds,
+            None => continue,
+        };
+        push_i16(&mut glyf, to_i16(glyph.contours.len())?);
+        push_i16(&mut glyf, x_min);
+        push_i16(&mut glyf, y_min);
+        push_i16(&mut glyf, x_max);
+        push_i16(&mut glyf, y_max);
+        let mut end = 0;
+        for contour in glyph.contours.iter() {
+            end += contour.len();
+            push_u16(&mut glyf, to_u16(end - 1)?);
+        }
+        push_u16(&mut glyf, 0); // instructionLength
+        let points: Vec<(i16, i16)> =
+            glyph.contours.iter().flatten().copied().collect();
+        glyf.extend(points.iter().map(|_| ON_CURVE));
+        let mut previous = (0i16, 0i16);
+        for &(x, _) in points.iter() {
+            push_i16(&mut glyf, x.wrapping_sub(previous.0));
+            previous.0 = x;
+        }
+        for &(_, y) in points.iter() {
+            push_i16(&mut glyf, y.wrapping_sub(previous.1));
+            previous.1 = y;
+        }
+        pad_to_four_bytes(&mut glyf);
+    }
+    push_u32(&mut loca, glyf.len() as u32);
+    Ok((glyf, loca))
+}
+
+fn head_table(metrics: &TtfMetrics) -> Vec<u8> {
+    let mut table = Vec::with_capacity(54);
+    push_u32(&mut table, 0x0001_0000); // version
+    push_u32(&mut table, 0x0001_0000); // fontRevision
+    push_u32(&mut table, 0); // checkSumAdjustment (filled in later)
+    push_u32(&mut table, HEAD_MAGIC);
+    // Baseline at y=0, left sidebearing point at x=0, integer scaling.
+    push_u16(&mut table, 0x000B);
+    push_u16(&mut table, metrics.units_per_em);
+    table.extend_from_slice(&[0; 16]); // created and modified dates
+    let (x_min, y_min, x_max, y_max) = metrics.bounds;
+    push_i16(&mut table, x_min);
+    push_i16(&mut table, y_min);
+    push_i16(&mut table, x_max);
+    push_i16(&mut table, y_max);
+    push_u16(&mut table, 0); // macStyle
+    push_u16(&mut table, metrics.pixel_height); // lowestRecPPEM
+    push_i16(&mut table, 2); // fontDirectionHint
+    push_i16(&mut table, 1); // indexToLocFormat (32-bit offsets)
+    push_i16(&mut table, 0); // glyphDataFormat
+    table
+}
+
+fn hhea_table(
+    metrics: &TtfMetrics,
+    glyphs: &[TtfGlyph],
+) -> io::Result<Vec<u8>> {
+    let advance_max = glyphs.iter().map(|glyph| glyph.advance).max();
+    let inked = glyphs.iter().filter_map(|glyph| {
+        glyph.bounds.map(|(x_min, _, x_max, _)| (glyph.advance, x_min, x_max))
+    });
+    let mut min_lsb = 0;
+    let mut min_rsb = 0;
+    let mut max_extent = 0;
+    for (index, (advance, x_min, x_max)) in inked.enumerate() {
+        let rsb = advance as i32 - x_max as i32;
+        if index == 0 {
+            (min_lsb, min_rsb, max_extent) = (x_min, rsb, x_max);
+        }
+        min_lsb = min(min_lsb, x_min);
+        min_rsb = min(min_rsb, rsb);
+        max_extent = max(max_extent, x_max);
+    }
+    let mut table = Vec::with_capacity(36);
+    push_u32(&mut table, 0x0001_0000); // version
+    push_i16(&mut table, metrics.ascender);
+    push_i16(&mut table, metrics.descender);
+    push_i16(&mut table, metrics.line_gap);
+    push_u16(&mut table, advance_max.unwrap_or(0));
+    push_i16(&mut table, min_lsb);
+    push_i16(&mut table, min_rsb.clamp(i16::MIN as i32, 0) as i16);
+    push_i16(&mut table, max_extent);
+    push_i16(&mut table, 1); // caretSlopeRise
+    push_i16(&mut table, 0); // caretSlopeRun
+    push_i16(&mut table, 0); // caretOffset
+    table.extend_from_slice(&[0; 8]); // reserved
+    push_i16(&mut table, 0); // metricDataFormat
+    push_u16(&mut table, to_u16(glyphs.len())?); // numberOfHMetrics
+    Ok(table)
+}
+
+fn hmtx_table(glyphs: &[TtfGlyph]) -> Vec<u8> {
+    let mut table = Vec::with_capacity(4 * glyphs.len());
+    for glyph in glyphs.iter() {
+        push_u16(&mut table, glyph.advance);
+        push_i16(&mut table, glyph.left_side_bearing());
+    }
+    table
+}
+
+fn maxp_table(glyphs: &[TtfGlyph]) -> io::Result<Vec<u8>> {
+    let max_points = glyphs.iter().map(TtfGlyph::num_points).max();
+    let max_contours = glyphs.iter().map(|glyph| glyph.contours.len()).max();
+    let mut table = Vec::with_capacity(32);
+    push_u32(&mut table, 0x0001_0000); // version
+    push_u16(&mut table, to_u16(glyphs.len())?);
+    push_u16(&mut table, to_u16(max_points.unwrap_or(0))?);
+    push_u16(&mut table, to_u16(max_contours.unwrap_or(0))?);
+    push_u16(&mut table, 0); // maxCompositePoints
+    push_u16(&mut table, 0); // maxCompositeContours
+    push_u16(&mut table, 2); // maxZones
+    table.extend_from_slice(&[0; 16]); // unused hinting limits
+    Ok(table)
+}
+
+fn post_table(metrics: &TtfMetrics, glyphs: &[TtfGlyph]) -> Vec<u8> {
+    let advance = glyphs.first().map(|glyph| glyph.advance);
+    let fixed_pitch =
+        glyphs.iter().all(|glyph| Some(glyph.advance) == advance);
+    let mut table = Vec::with_capacity(32);
+    push_u32(&mut table, 0x0003_0000); // version (no glyph names)
+    push_u32(&mut table, 0); // italicAngle
+    push_i16(&mut table, metrics.descender / 2); // underlinePosition
+    push_i16(&mut table, UNITS_PER_PIXEL as i16); // underlineThickness
+    push_u32(&mut table, fixed_pitch as u32);
+    table.extend_from_slice(&[0; 16]); // memory usage hints
+    table
+}
+
+fn os2_table(metrics: &TtfMetrics, chars: &[char]) -> Vec<u8> {
+    let em = metrics.units_per_em as i16;
+    let first_char = chars.first().map_or(0, |&chr| min(chr as u32, 0xFFFF));
+    let last_char = chars.last().map_or(0, |&chr| min(chr as u32, 0xFFFF));
+    let (_, y_min, _, y_max) = metrics.bounds;
+    let mut table = Vec::with_capacity(96);
+    push_u16(&mut table, 4); // version
+    push_i16(&mut table, em / 2); // xAvgCharWidth
+    push_u16(&mut table, 400); // usWeightClass (normal)
+    push_u16(&mut table, 5); // usWidthClass (medium)
+    push_u16(&mut table, 0); // fsType (installable)
+    for _ in 0..2 {
+        // Subscript and superscript size and offset.
+        push_i16(&mut table, em / 2);
+        push_i16(&mut table, em / 2);
+        push_i16(&mut table, 0);
+        push_i16(&mut table, em / 4);
+    }
+    push_i16(&mut table, UNITS_PER_PIXEL as i16); // yStrikeoutSize
+    push_i16(&mut table, metrics.ascender / 3); // yStrikeoutPosition
+    push_i16(&mut table, 0); // sFamilyClass
+    table.extend_from_slice(&[0; 10]); // panose
+    table.extend_from_slice(&[0; 16]); // ulUnicodeRange1-4
+    table.extend_from_slice(b"NONE"); // achVendID
+    push_u16(&mut table, 0x0040); // fsSelection (regular)
+    push_u16(&mut table, first_char as u16);
+    push_u16(&mut table, last_char as u16);
+    push_i16(&mut table, metrics.ascender); // sTypoAscender
+    push_i16(&mut table, metrics.descender); // sTypoDescender
+    push_i16(&mut table, metrics.line_gap); // sTypoLineGap
+    push_u16(&mut table, max(metrics.ascender, y_max).max(0) as u16);
+    push_u16(&mut table, (-min(metrics.descender, y_min)).max(0) as u16);
+    push_u32(&mut table, 1); // ulCodePageRange1 (Latin 1)
+    push_u32(&mut table, 0); // ulCodePageRange2
+    push_i16(&mut table, 0); // sxHeight
+    push_i16(&mut table, 0); // sCapHeight
+    push_u16(&mut table, 0); // usDefaultChar
+    push_u16(&mut table, 0x20); // usBreakChar
+    push_u16(&mut table, 0); // usMaxContext
+    table
+}
+
+/// Builds a `cmap` table mapping each character to its glyph, where the
+/// glyph for `chars[n]` is glyph `n + 1`.  The table has a format 4
+/// subtable for characters in the Basic Multilingual Plane and a format 12
+/// subtable for all characters, each listed for both the Unicode and
+/// Windows platforms.
+fn cmap_table(chars: &[char]) -> io::Result<Vec<u8>> {
+    // Group the characters into runs of consecutive code points, which map
+    // to consecutive glyph IDs.
+    let mut runs: Vec<(u32, u32, u32)> = Vec::new();
+    for (index, &chr) in chars.iter().enumerate() {
+        let code = chr as u32;
+        match runs.last_mut() {
+            Some((_, end, _)) if *end + 1 == code => *end = code,
+            _ => runs.push((code, code, index as u32 + 1)),
+        }
+    }
+
+    let mut segments: Vec<(u16, u16, u16)> = runs
+        .iter()
+        .filter(|&&(start, _, _)| start < 0xFFFF)
+        .map(|&(start, end, glyph)| {
+            let end = min(end, 0xFFFE);
+            (start as u16, end as u16, (glyph.wrapping_sub(start)) as u16)
+        })
+        .collect();
+    segments.push((0xFFFF, 0xFFFF, 1));
+    let length = to_u16(16 + 8 * segments.len())?;
+    let seg_count = segments.len() as u16;
+    let entry_selector = 15 - seg_count.leading_zeros() as u16;
+    let search_range = 2 << entry_selector;
+    let mut format4 = Vec::new();
+    push_u16(&mut format4, 4); // format
+    push_u16(&mut format4, length);
+    push_u16(&mut format4, 0); // language
+    push_u16(&mut format4, 2 * seg_count);
+    push_u16(&mut format4, search_range);
+    push_u16(&mut format4, entry_selector);
+    push_u16(&mut format4, 2 * seg_count - search_range);
+    for &(_, end, _) in segments.iter() {
+        push_u16(&mut format4, end);
+    }
+    push_u16(&mut format4, 0); // reservedPad
+    for &(start, _, _) in segments.iter() {
+        push_u16(&mut format4, start);
+    }
+    for &(_, _, delta) in segments.iter() {
+        push_u16(&mut format4, delta);
+    }
+    for _ in segments.iter() {
+        push_u16(&mut format4, 0); // idRangeOffset
+    }
+
+    let mut format12 = Vec::new();
+    push_u16(&mut format12, 12); // format
+    push_u16(&mut format12, 0); // reserved
+    push_u32(&mut format12, 16 + 12 * runs.len() as u32); // length
+    push_u32(&mut format12, 0); // language
+    push_u32(&mut format12, runs.len() as u32);
+    for &(start, end, glyph) in runs.iter() {
+        push_u32(&mut format12, start);
+        push_u32(&mut format12, end);
+        push_u32(&mut format12, glyph);
+    }
+
+    let format4_offset = 4 + 8 * 4;
+    let format12_offset = format4_offset + format4.len() as u32;
+    let mut table = Vec::new();
+    push_u16(&mut table, 0); // version
+    push_u16(&mut table, 4); // numTables
+    for &(platform, encoding, offset) in [
+        (0, 3, format4_offset),
+        (0, 4, format12_offset),
+        (3, 1, format4_offset),
+        (3, 10, format12_offset),
+    ]
+    .iter()
+    {
+        push_u16(&mut table, platform);
+        push_u16(&mut table, encoding);
+        push_u32(&mut table, offset);
+    }
+    table.extend_from_slice(&format4);
+    table.extend_from_slice(&format12);
+    Ok(table)
+}
+
+/// Builds a `name` table for a regular-style font with the given family
+/// name (or `"Untitled"` if the name is empty).
+fn name_table(name: &str) -> io::Result<Vec<u8>> {
+    let family = if name.is_empty() { "Untitled" } else { name };
+    let postscript: String = family
+        .chars()
+        .filter(|&chr| chr > ' ' && chr <= '~' && !"[](){}<>/%".contains(chr))
+        .take(63)
+        .collect();
+    let postscript = if postscript.is_empty() {
+        "Untitled".to_string()
+    } else {
+        postscript
+    };
+    let full_name = format!("{} Regular", family);
+    let names: [(u16, &str); 6] = [
+        (1, family),
+        (2, "Regular"),
+        (3, &postscript),
+        (4, &full_name),
+        (5, "Version 1.0"),
+        (6, &postscript),
+    ];
+    let mut strings = Vec::new();
+    let mut records = Vec::new();
+    for &(name_id, value) in names.iter() {
+        let offset = to_u16(strings.len())?;
+        for unit in value.encode_utf16() {
+            push_u16(&mut strings, unit);
+        }
+        let length = to_u16(strings.len() - offset as usize)?;
+        push_u16(&mut records, 3); // platformID (Windows)
+        push_u16(&mut records, 1); // encodingID (Unicode BMP)
+        push_u16(&mut records, 0x0409); // languageID (English)
+        push_u16(&mut records, name_id);
+        push_u16(&mut records, length);
+        push_u16(&mut records, offset);
+    }
+    let mut table = Vec::new();
+    push_u16(&mut table, 0); // format
+    push_u16(&mut table, names.len() as u16);
+    push_u16(&mut table, to_u16(6 + records.len())?); // stringOffset
+    table.extend_from_slice(&records);
+    table.extend_from_slice(&strings);
+    Ok(table)
+}
+
+/// Assembles a complete font file from the given tables, which must be
+/// sorted by tag, and fills in the `head` table's checksum adjustment.
+fn sfnt_file(tables: Vec<([u8; 4], Vec<u8>)>) -> Vec<u8> {
+    let num_tables = tables.len() as u16;
+    let entry_selector = 15 - num_tables.leading_zeros() as u16;
+    let search_range = 16 << entry_selector;
+    let mut file = Vec::new();
+    push_u32(&mut file, 0x0001_0000); // sfntVersion
+    push_u16(&mut file, num_tables);
+    push_u16(&mut file, search_range);
+    push_u16(&mut file, entry_selector);
+    push_u16(&mut file, 16 * num_tables - search_range);
+    let mut offset = 12 + 16 * tables.len() as u32;
+    let mut head_offset = None;
+    for (tag, table) in tables.iter() {
+        if tag == b"head" {
+            head_offset = Some(offset as usize);
+        }
+        file.extend_from_slice(tag);
+        push_u32(&mut file, checksum(table));
+        push_u32(&mut file, offset);
+        push_u32(&mut file, table.len() as u32);
+        offset += (table.len() as u32).div_ceil(4) * 4;
+    }
+    for (_, mut table) in tables {
+        pad_to_four_bytes(&mut table);
+        file.extend_from_slice(&table);
+    }
+    if let Some(head_offset) = head_offset {
+        let adjustment = CHECKSUM_MAGIC.wrapping_sub(checksum(&file));
+        file[(head_offset + 8)..(head_offset + 12)]
+            .copy_from_slice(&adjustment.to_be_bytes());
+    }
+    file
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+fn pad_to_four_bytes(data: &mut Vec<u8>) {
+    let padded_len = data.len().div_ceil(4) * 4;
+    data.resize(padded_len, 0);
+}
+
+fn push_u16(data: &mut Vec<u8>, value: u16) {
+    data.extend_from_slice(&value.to_be_bytes());
+}
+
+fn push_i16(data: &mut Vec<u8>, value: i16) {
+    data.extend_from_slice(&value.to_be_bytes());
+}
+
+fn push_u32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&value.to_be_bytes());
+}
+
+fn to_i16<T: TryInto<i16> + Copy + Display>(value: T) -> io::Result<i16> {
+    value.try_into().map_err(|_| too_large(value))
+}
+
+fn to_u16<T: TryInto<u16> + Copy + Display>(value: T) -> io::Result<u16> {
+    value.try_into().map_err(|_| too_large(value))
+}
+
+fn too_large<T: Display>(value: T) -> Error {
+    let msg = format!("value is too large for a TrueType font: {}", value);
+    Error::new(ErrorKind::InvalidInput, msg)
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::image::Image;
+    use crate::internal::testutil::{font_from_rows, GlyphRows};
+
+    fn u16_at(data: &[u8], offset: usize) -> u16 {
+        u16::from_be_bytes([data[offset], data[offset + 1]])
+    }
+
+    fn i16_at(data: &[u8], offset: usize) -> i16 {
+        u16_at(data, offset) as i16
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        (u16_at(data, offset) as u32) << 16 | u16_at(data, offset + 2) as u32
+    }
+
+    /// Returns the contents of the table with the given tag.
+    fn table<'a>(file: &'a [u8], tag: &[u8]) -> &'a [u8] {
+        let num_tables = u16_at(file, 4) as usize;
+        for index in 0..num_tables {
+            let record = 12 + 16 * index;
+            if &file[record..(record + 4)] == tag {
+                let offset = u32_at(file, record + 8) as usize;
+                let length = u32_at(file, record + 12) as usize;
+                return &file[offset..(offset + length)];
+            }
+        }
+        panic!("no {:?} table", String::from_utf8_lossy(tag));
+    }
+
+    /// Looks up a character's glyph ID in the font's format 4 subtable.
+    fn lookup_glyph(file: &[u8], chr: char) -> u16 {
+        let cmap = table(file, b"cmap");
+        let subtable = &cmap[(u32_at(cmap, 8) as usize)..];
+        assert_eq!(u16_at(subtable, 0), 4);
+        let seg_count = u16_at(subtable, 6) as usize / 2;
+        for segment in 0..seg_count {
+            let end = u16_at(subtable, 14 + 2 * segment);
+            let start = u16_at(subtable, 16 + 2 * (seg_count + segment));
+            let delta = u16_at(subtable, 16 + 2 * (2 * seg_count + segment));
+            let code = chr as u16;
+            if start <= code && code <= end {
+                return code.wrapping_add(delta);
+            }
+        }
+        0
+    }
+
+    const GLYPHS: &[GlyphRows] = &[
+        ("def", 0, 4, &["111", "101", "111"]),
+        ("L", -1, 3, &["20", "20", "23"]),
+        (" ", 0, 2, &["", "", "", ""]),
+        ("\u{1F600}", 0, 2, &["0"]),
+    ];
+
+    #[test]
+    fn trace_merged_outlines() {
+        let font = font_from_rows(4, 3, GLYPHS);
+        let outlines = trace_outlines(&font['L']);
+        assert_eq!(outlines.len(), 1);
+        assert_eq!(outlines[0].len(), 6);
+        let outlines = trace_outlines(font.default_glyph());
+        assert_eq!(outlines.len(), 2);
+        let area = |contour: &Vec<(i32, i32)>| {
+            let num_points = contour.len();
+            (0..num_points)
+                .map(|index| {
+                    let (x0, y0) = contour[index];
+                    let (x1, y1) = contour[(index + 1) % num_points];
+                    x0 * y1 - x1 * y0
+                })
+                .sum::<i32>()
+        };
+        // In row-down coordinates, a clockwise (on screen) contour has
+        // positive area, so the outer contour is positive and the hole is
+        // negative.
+        let mut areas: Vec<i32> = outlines.iter().map(area).collect();
+        areas.sort();
+        assert_eq!(areas, vec![-2, 18]);
+    }
+
+    #[test]
+    fn write_ttf_tables() {
+        let mut font = font_from_rows(4, 3, GLYPHS);
+        font.set_line_spacing(5);
+        let mut file = Vec::<u8>::new();
+        font.write_ttf(&mut file).unwrap();
+        assert_eq!(u32_at(&file, 0), 0x0001_0000);
+        assert_eq!(u16_at(&file, 4), 10);
+        assert_eq!(checksum(&file), CHECKSUM_MAGIC);
+
+        let head = table(&file, b"head");
+        assert_eq!(u16_at(head, 18), 256); // unitsPerEm
+        let hhea = table(&file, b"hhea");
+        assert_eq!(i16_at(hhea, 4), 192); // ascender
+        assert_eq!(i16_at(hhea, 6), -64); // descender
+        assert_eq!(i16_at(hhea, 8), 64); // lineGap
+        assert_eq!(u16_at(hhea, 34), 4); // numberOfHMetrics
+        assert_eq!(u16_at(table(&file, b"maxp"), 4), 4); // numGlyphs
+
+        assert_eq!(lookup_glyph(&file, ' '), 1);
+        assert_eq!(lookup_glyph(&file, 'L'), 2);
+        assert_eq!(lookup_glyph(&file, 'M'), 0);
+
+        // 'L' advances by 4 pixels, and its leftmost pixel is one pixel
+        // to the right of its left edge.
+        let hmtx = table(&file, b"hmtx");
+        assert_eq!(u16_at(hmtx, 8), 256);
+        assert_eq!(i16_at(hmtx, 10), 64);
+
+        // The space is empty; 'L' is a single six-point contour.
+        let loca = table(&file, b"loca");
+        assert_eq!(u32_at(loca, 4), u32_at(loca, 8));
+        let glyf = table(&file, b"glyf");
+        let glyph = &glyf[(u32_at(loca, 8) as usize)..];
+        assert_eq!(i16_at(glyph, 0), 1);
+        assert_eq!(
+            (i16_at(glyph, 2), i16_at(glyph, 4)),
+            (64, 0) // xMin, yMin
+        );
+        assert_eq!(
+            (i16_at(glyph, 6), i16_at(glyph, 8)),
+            (192, 192) // xMax, yMax
+        );
+        assert_eq!(u16_at(glyph, 10), 5);
+    }
+
+    #[test]
+    fn write_ttf_with_supplementary_chars() {
+        let mut font = font_from_rows(4, 3, GLYPHS);
+        font.set_name("Pixel Test");
+        let mut file = Vec::<u8>::new();
+        font.write_ttf(&mut file).unwrap();
+        let cmap = table(&file, b"cmap");
+        let subtable = &cmap[(u32_at(cmap, 16) as usize)..];
+        assert_eq!(u16_at(subtable, 0), 12);
+        assert_eq!(u32_at(subtable, 12), 3); // numGroups
+        assert_eq!(u32_at(subtable, 40), 0x1F600); // startCharCode
+        assert_eq!(u32_at(subtable, 48), 3); // startGlyphID
+        let name = table(&file, b"name");
+        assert_eq!(u16_at(name, 2), 6);
+        let string_offset = u16_at(name, 4) as usize;
+        let length = u16_at(name, 6 + 8) as usize;
+        let family: Vec<u16> = (0..(length / 2))
+            .map(|index| u16_at(name, string_offset + 2 * index))
+            .collect();
+        assert_eq!(String::from_utf16(&family).unwrap(), "Pixel Test");
+    }
+
+    #[test]
+    fn write_ttf_with_invalid_height() {
+        let font = Font::with_glyph_height(0);
+        let error = font.write_ttf(Vec::<u8>::new()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn write_ttf_with_too_many_glyphs() {
+        let mut font = Font::with_glyph_height(1);
+        for code in 0..0x11000 {
+            if let Some(chr) = char::from_u32(code) {
+                font.set_char_glyph(chr, Glyph::new(Image::new(0, 1), 0, 1));
+            }
+        }
+        let error = font.write_ttf(Vec::<u8>::new()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn write_ttf_with_long_name() {
+        let mut font = Font::with_glyph_height(1);
+        font.set_name("x".repeat(0x8000));
+        let error = font.write_ttf(Vec::<u8>::new()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        font.set_name("x".repeat(100));
+        assert!(font.write_ttf(Vec::<u8>::new()).is_ok());
+    }
+}
+
+// ========================================================================= //
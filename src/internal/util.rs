@@ -17,261 +17,502 @@
 // | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
 // +--------------------------------------------------------------------------+
 
+use crate::internal::color::Color;
+use crate::internal::error::{ParseError, ParseErrorKind};
+use crate::internal::options::ReadOptions;
 use std::char;
-use std::i16;
-use std::io::{self, Error, ErrorKind, Read};
+use std::io::{self, Error, ErrorKind, Read, Write};
 
 // ========================================================================= //
 
-const MAX_HEADER_VALUE: i32 = 0xFFFF;
+pub(crate) const MAX_HEADER_VALUE: i32 = 0xFFFF;
 
 // ========================================================================= //
 
-fn read_char_escape<R: Read>(
-    mut reader: R,
-    quote: u8,
-) -> io::Result<Option<char>> {
-    let mut buffer = vec![0u8];
-    reader.read_exact(&mut buffer)?;
-    let byte = buffer[0];
-    if byte == quote {
-        Ok(None)
-    } else if byte == b'\\' {
-        reader.read_exact(&mut buffer)?;
-        let esc = buffer[0];
-        if esc == b'\\' {
-            Ok(Some('\\'))
-        } else if esc == b'\'' {
-            Ok(Some('\''))
-        } else if esc == b'"' {
-            Ok(Some('"'))
-        } else if esc == b'n' {
-            Ok(Some('\n'))
-        } else if esc == b'r' {
-            Ok(Some('\r'))
-        } else if esc == b't' {
-            Ok(Some('\t'))
-        } else if esc == b'u' {
-            read_exactly(reader.by_ref(), b"{")?;
-            let value = read_hex_u32(reader.by_ref(), b'}')?;
-            char::from_u32(value)
-                .ok_or_else(|| {
-                    let msg = format!("invalid unicode value: {}", value);
-                    Error::new(ErrorKind::InvalidData, msg)
-                })
-                .map(Some)
-        } else {
-            let msg = format!("invalid char escape: {}", esc);
-            Err(Error::new(ErrorKind::InvalidData, msg))
+/// Wraps a reader for a text (AHI or AHF) file, and keeps track of the
+/// current position within the file so that parse errors can report where
+/// they occurred.
+pub(crate) struct TextReader<R> {
+    reader: R,
+    options: ReadOptions,
+    peeked: Option<u8>,
+    line: usize,
+    column: usize,
+    last_line: usize,
+    last_column: usize,
+    item: Option<(&'static str, usize)>,
+}
+
+impl<R: Read> TextReader<R> {
+    #[cfg(test)]
+    pub(crate) fn new(reader: R) -> TextReader<R> {
+        TextReader::with_options(reader, &ReadOptions::new())
+    }
+
+    pub(crate) fn with_options(
+        reader: R,
+        options: &ReadOptions,
+    ) -> TextReader<R> {
+        TextReader {
+            reader,
+            options: *options,
+            peeked: None,
+            line: 1,
+            column: 1,
+            last_line: 1,
+            last_column: 1,
+            item: None,
         }
-    } else if byte < b' ' || byte > b'~' {
-        let msg = format!("invalid char literal byte: {}", byte);
-        Err(Error::new(ErrorKind::InvalidData, msg))
-    } else {
-        Ok(Some(char::from_u32(byte as u32).unwrap()))
     }
-}
 
-pub(crate) fn read_exactly<R: Read>(
-    mut reader: R,
-    expected: &[u8],
-) -> io::Result<()> {
-    let mut actual = vec![0u8; expected.len()];
-    reader.read_exact(&mut actual)?;
-    if &actual as &[u8] != expected {
-        let msg = format!(
-            "expected '{}', found '{}'",
-            String::from_utf8_lossy(expected),
-            String::from_utf8_lossy(&actual)
-        );
-        Err(Error::new(ErrorKind::InvalidData, msg))
-    } else {
-        Ok(())
+    /// Sets the image or glyph that is currently being parsed, for error
+    /// reporting purposes.
+    pub(crate) fn set_item(&mut self, noun: &'static str, index: usize) {
+        self.item = Some((noun, index));
     }
-}
 
-pub(crate) fn read_header_int<R: Read>(
-    reader: R,
-    terminator: u8,
-) -> io::Result<i32> {
-    let mut negative = false;
-    let mut any_digits = false;
-    let mut value: i32 = 0;
-    for next in reader.bytes() {
-        let byte = next?;
-        if byte == terminator {
-            if !any_digits {
-                let msg = "missing integer field in header";
-                return Err(Error::new(ErrorKind::InvalidData, msg));
+    /// Returns an error for a problem with the most recently read byte.
+    pub(crate) fn error(&self, kind: ParseErrorKind) -> Error {
+        ParseError::new(self.last_line, self.last_column, self.item, kind)
+            .into()
+    }
+
+    /// Returns the next byte without consuming it, or `None` at end of file.
+    pub(crate) fn peek(&mut self) -> io::Result<Option<u8>> {
+        if self.peeked.is_none() {
+            let mut buffer = [0u8];
+            loop {
+                match self.reader.read(&mut buffer) {
+                    Ok(0) => return Ok(None),
+                    Ok(_) => break,
+                    Err(ref err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err),
+                }
             }
-            break;
-        } else if byte == b'-' {
-            if negative || any_digits {
-                let msg = "misplaced minus sign in header field";
-                return Err(Error::new(ErrorKind::InvalidData, msg));
+            self.peeked = Some(buffer[0]);
+        }
+        Ok(self.peeked)
+    }
+
+    /// Consumes and returns the next byte, or returns an error at end of
+    /// file.
+    pub(crate) fn next_byte(&mut self) -> io::Result<u8> {
+        match self.peek()? {
+            Some(byte) => {
+                self.peeked = None;
+                self.last_line = self.line;
+                self.last_column = self.column;
+                if byte == b'\n' {
+                    self.line += 1;
+                    self.column = 1;
+                } else {
+                    self.column += 1;
+                }
+                Ok(byte)
             }
-            negative = true;
-        } else if byte < b'0' || byte > b'9' {
-            let msg = format!(
-                "invalid byte in header field: '{}'",
-                String::from_utf8_lossy(&[byte])
-            );
-            return Err(Error::new(ErrorKind::InvalidData, msg));
-        } else {
-            value = value * 10 + (byte - b'0') as i32;
-            if value > MAX_HEADER_VALUE {
-                let msg = "header value is too large";
-                return Err(Error::new(ErrorKind::InvalidData, msg));
+            None => {
+                let kind = ParseErrorKind::UnexpectedEof;
+                Err(ParseError::new(self.line, self.column, self.item, kind)
+                    .into())
             }
-            any_digits = true;
         }
     }
-    if negative {
-        value = -value;
-    }
-    Ok(value)
-}
 
-pub(crate) fn read_header_uint<R: Read>(
-    reader: R,
-    terminator: u8,
-) -> io::Result<u32> {
-    let value = read_header_int(reader, terminator)?;
-    if value < 0 {
-        let msg = format!("value must be nonnegative (was {})", value);
-        return Err(Error::new(ErrorKind::InvalidData, msg));
+    /// Returns true if the next byte ends the current line (allowing for any
+    /// leniencies permitted by the read options), without consuming it.
+    fn at_line_end(&mut self) -> io::Result<bool> {
+        Ok(match self.peek()? {
+            Some(b'\n') => true,
+            Some(b'\r') => self.options.allow_crlf,
+            Some(b' ') | Some(b'\t') => self.options.allow_trailing_whitespace,
+            Some(_) => false,
+            None => self.options.allow_missing_final_newline,
+        })
     }
-    Ok(value as u32)
-}
 
-pub(crate) fn read_hex_digits<R: Read>(
-    reader: R,
-    terminator: u8,
-) -> io::Result<Vec<u8>> {
-    let mut digits = Vec::<u8>::new();
-    for next in reader.bytes() {
-        let byte = next?;
-        if byte == terminator {
-            break;
+    /// Consumes the end of the current line.  Depending on the read options,
+    /// this may also consume trailing whitespace and/or a carriage return,
+    /// and may accept the end of the file in place of a newline.
+    pub(crate) fn read_newline(&mut self) -> io::Result<()> {
+        if self.options.allow_trailing_whitespace {
+            while let Some(b' ') | Some(b'\t') = self.peek()? {
+                self.next_byte()?;
+            }
         }
-        let digit = if byte >= b'0' && byte <= b'9' {
-            byte - b'0'
-        } else if byte >= b'a' && byte <= b'f' {
-            byte - b'a' + 0xa
-        } else if byte >= b'A' && byte <= b'F' {
-            byte - b'A' + 0xA
-        } else {
-            let msg = format!(
-                "invalid hex digit: '{}'",
-                String::from_utf8_lossy(&[byte])
-            );
-            return Err(Error::new(ErrorKind::InvalidData, msg));
-        };
-        digits.push(digit as u8);
+        if self.options.allow_crlf && self.peek()? == Some(b'\r') {
+            self.next_byte()?;
+        }
+        if self.options.allow_missing_final_newline && self.peek()?.is_none() {
+            return Ok(());
+        }
+        self.read_exactly(b"\n")
     }
-    Ok(digits)
-}
 
-pub(crate) fn read_hex_u32<R: Read>(
-    reader: R,
-    terminator: u8,
-) -> io::Result<u32> {
-    let digits = read_hex_digits(reader, terminator)?;
-    if digits.is_empty() {
-        let msg = "missing hex literal";
-        return Err(Error::new(ErrorKind::InvalidData, msg));
-    }
-    if digits.len() > 8 {
-        let msg = "hex literal is too large";
-        return Err(Error::new(ErrorKind::InvalidData, msg));
+    fn read_char_escape(&mut self, quote: u8) -> io::Result<Option<char>> {
+        let byte = self.next_byte()?;
+        if byte == quote {
+            Ok(None)
+        } else if byte == b'\\' {
+            let esc = self.next_byte()?;
+            if esc == b'\\' {
+                Ok(Some('\\'))
+            } else if esc == b'\'' {
+                Ok(Some('\''))
+            } else if esc == b'"' {
+                Ok(Some('"'))
+            } else if esc == b'n' {
+                Ok(Some('\n'))
+            } else if esc == b'r' {
+                Ok(Some('\r'))
+            } else if esc == b't' {
+                Ok(Some('\t'))
+            } else if esc == b'u' {
+                self.read_exactly(b"{")?;
+                let value = self.read_hex_u32(b'}')?;
+                char::from_u32(value)
+                    .ok_or_else(|| {
+                        self.error(ParseErrorKind::InvalidUnicodeValue(value))
+                    })
+                    .map(Some)
+            } else {
+                Err(self.error(ParseErrorKind::InvalidCharEscape(esc)))
+            }
+        } else if !(b' '..=b'~').contains(&byte) {
+            Err(self.error(ParseErrorKind::InvalidCharLiteralByte(byte)))
+        } else {
+            Ok(Some(char::from_u32(byte as u32).unwrap()))
+        }
     }
-    let mut value: u32 = 0;
-    for digit in digits.into_iter() {
-        value = value * 0x10 + digit as u32;
+
+    pub(crate) fn read_exactly(&mut self, expected: &[u8]) -> io::Result<()> {
+        for &byte in expected {
+            let found = self.next_byte()?;
+            if found != byte {
+                let expected = String::from_utf8_lossy(expected).into_owned();
+                let kind = ParseErrorKind::UnexpectedByte { expected, found };
+                return Err(self.error(kind));
+            }
+        }
+        Ok(())
     }
-    Ok(value)
-}
 
-pub(crate) fn read_list_of_i16s<R: Read>(
-    mut reader: R,
-) -> io::Result<Vec<i16>> {
-    read_exactly(reader.by_ref(), b"[")?;
-    let mut values = Vec::<i16>::new();
-    let mut done = false;
-    while !done {
+    pub(crate) fn read_header_int(
+        &mut self,
+        terminator: u8,
+    ) -> io::Result<i32> {
         let mut negative = false;
         let mut any_digits = false;
         let mut value: i32 = 0;
-        for byte in reader.by_ref().bytes() {
-            let byte = byte?;
-            if byte == b']' || byte == b',' {
-                if !any_digits && (byte == b',' || !values.is_empty()) {
-                    let msg = "missing integer in list";
-                    return Err(Error::new(ErrorKind::InvalidData, msg));
+        loop {
+            if terminator == b'\n' && self.at_line_end()? {
+                if !any_digits {
+                    return Err(self.error(ParseErrorKind::MissingInteger));
                 }
-                if byte == b']' {
-                    done = true;
+                self.read_newline()?;
+                break;
+            }
+            let byte = self.next_byte()?;
+            if byte == terminator {
+                if !any_digits {
+                    return Err(self.error(ParseErrorKind::MissingInteger));
                 }
                 break;
             } else if byte == b'-' {
                 if negative || any_digits {
-                    let msg = "misplaced minus sign in integer list";
-                    return Err(Error::new(ErrorKind::InvalidData, msg));
+                    let kind = ParseErrorKind::MisplacedMinusSign;
+                    return Err(self.error(kind));
                 }
                 negative = true;
-            } else if byte < b'0' || byte > b'9' {
-                let msg = format!(
-                    "invalid byte in list integer: '{}'",
-                    String::from_utf8_lossy(&[byte])
-                );
-                return Err(Error::new(ErrorKind::InvalidData, msg));
+            } else if !byte.is_ascii_digit() {
+                let kind = ParseErrorKind::InvalidIntegerByte(byte);
+                return Err(self.error(kind));
             } else {
                 value = value * 10 + (byte - b'0') as i32;
+                if value > MAX_HEADER_VALUE {
+                    return Err(self.error(ParseErrorKind::ValueOutOfRange));
+                }
                 any_digits = true;
-                if value > 0x8000 {
+            }
+        }
+        if negative {
+            value = -value;
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn read_header_uint(
+        &mut self,
+        terminator: u8,
+    ) -> io::Result<u32> {
+        let value = self.read_header_int(terminator)?;
+        if value < 0 {
+            return Err(self.error(ParseErrorKind::NegativeValue(value)));
+        }
+        Ok(value as u32)
+    }
+
+    pub(crate) fn read_hex_digits(
+        &mut self,
+        terminator: u8,
+    ) -> io::Result<Vec<u8>> {
+        let mut digits = Vec::<u8>::new();
+        loop {
+            if terminator == b'\n' && self.at_line_end()? {
+                self.read_newline()?;
+                break;
+            }
+            let byte = self.next_byte()?;
+            if byte == terminator {
+                break;
+            }
+            let digit = if byte.is_ascii_digit() {
+                byte - b'0'
+            } else if (b'a'..=b'f').contains(&byte) {
+                byte - b'a' + 0xa
+            } else if (b'A'..=b'F').contains(&byte) {
+                byte - b'A' + 0xA
+            } else {
+                return Err(self.error(ParseErrorKind::InvalidHexDigit(byte)));
+            };
+            digits.push(digit);
+        }
+        Ok(digits)
+    }
+
+    pub(crate) fn read_hex_u32(&mut self, terminator: u8) -> io::Result<u32> {
+        let digits = self.read_hex_digits(terminator)?;
+        if digits.is_empty() {
+            return Err(self.error(ParseErrorKind::MissingHexLiteral));
+        }
+        if digits.len() > 8 {
+            return Err(self.error(ParseErrorKind::HexLiteralTooLarge));
+        }
+        let mut value: u32 = 0;
+        for digit in digits.into_iter() {
+            value = value * 0x10 + digit as u32;
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn read_list_of_i16s(&mut self) -> io::Result<Vec<i16>> {
+        self.read_exactly(b"[")?;
+        let mut values = Vec::<i16>::new();
+        let mut done = false;
+        while !done {
+            let mut negative = false;
+            let mut any_digits = false;
+            let mut value: i32 = 0;
+            loop {
+                let byte = self.next_byte()?;
+                if byte == b']' || byte == b',' {
+                    if !any_digits && (byte == b',' || !values.is_empty()) {
+                        let kind = ParseErrorKind::MissingInteger;
+                        return Err(self.error(kind));
+                    }
+                    if byte == b']' {
+                        done = true;
+                    }
                     break;
+                } else if byte == b'-' {
+                    if negative || any_digits {
+                        let kind = ParseErrorKind::MisplacedMinusSign;
+                        return Err(self.error(kind));
+                    }
+                    negative = true;
+                } else if !byte.is_ascii_digit() {
+                    let kind = ParseErrorKind::InvalidIntegerByte(byte);
+                    return Err(self.error(kind));
+                } else {
+                    value = value * 10 + (byte - b'0') as i32;
+                    any_digits = true;
+                    if value > 0x8000 {
+                        break;
+                    }
                 }
             }
+            if any_digits {
+                if negative {
+                    value = -value;
+                }
+                if value > (i16::MAX as i32) || value < (i16::MIN as i32) {
+                    return Err(self.error(ParseErrorKind::ValueOutOfRange));
+                }
+                values.push(value as i16);
+                if !done {
+                    self.read_exactly(b" ")?;
+                }
+            } else {
+                debug_assert!(values.is_empty());
+                debug_assert!(done);
+            }
         }
-        if any_digits {
-            if negative {
-                value = -value;
+        Ok(values)
+    }
+
+    pub(crate) fn read_quoted_char(&mut self) -> io::Result<char> {
+        self.read_exactly(b"\'")?;
+        if let Some(chr) = self.read_char_escape(b'\'')? {
+            self.read_exactly(b"\'")?;
+            Ok(chr)
+        } else {
+            Err(self.error(ParseErrorKind::EmptyCharLiteral))
+        }
+    }
+
+    pub(crate) fn read_quoted_string(&mut self) -> io::Result<String> {
+        self.read_exactly(b"\"")?;
+        let mut string = String::new();
+        while let Some(chr) = self.read_char_escape(b'"')? {
+            string.push(chr);
+        }
+        Ok(string)
+    }
+
+    /// Reads zero or more comment lines (each starting with `#`), and
+    /// returns their contents joined by newlines.
+    pub(crate) fn read_comments(&mut self) -> io::Result<String> {
+        let mut comment = String::new();
+        let mut first = true;
+        while self.peek()? == Some(b'#') {
+            self.next_byte()?;
+            if self.peek()? == Some(b' ') {
+                self.next_byte()?;
             }
-            if value > (i16::MAX as i32) || value < (i16::MIN as i32) {
-                let msg = "list integer value is out of range";
-                return Err(Error::new(ErrorKind::InvalidData, msg));
+            let mut bytes = Vec::new();
+            while let Some(byte) = self.peek()? {
+                if byte == b'\n' {
+                    break;
+                }
+                bytes.push(self.next_byte()?);
             }
-            values.push(value as i16);
-            if !done {
-                read_exactly(reader.by_ref(), b" ")?;
+            if self.options.allow_crlf && bytes.last() == Some(&b'\r') {
+                bytes.pop();
+            }
+            let line = String::from_utf8(bytes)
+                .map_err(|_| self.error(ParseErrorKind::InvalidUtf8))?;
+            self.read_newline()?;
+            if !first {
+                comment.push('\n');
+            }
+            first = false;
+            comment.push_str(&line);
+        }
+        Ok(comment)
+    }
+
+    /// Reads one row of `width` pixels, followed by a newline.
+    pub(crate) fn read_pixel_row(
+        &mut self,
+        width: u32,
+        pixels: &mut Vec<Color>,
+    ) -> io::Result<()> {
+        for _ in 0..width {
+            let byte = self.next_byte()?;
+            let color = if self.options.allow_lowercase_hex {
+                Color::from_byte(byte.to_ascii_uppercase())
+            } else {
+                Color::from_byte(byte)
+            };
+            match color {
+                Some(color) => pixels.push(color),
+                None => {
+                    return Err(self.error(ParseErrorKind::InvalidPixel(byte)))
+                }
             }
-        } else {
-            debug_assert!(values.is_empty());
-            debug_assert!(done);
         }
+        self.read_newline()
     }
-    Ok(values)
 }
 
-pub(crate) fn read_quoted_char<R: Read>(mut reader: R) -> io::Result<char> {
-    read_exactly(reader.by_ref(), b"\'")?;
-    if let Some(chr) = read_char_escape(reader.by_ref(), b'\'')? {
-        read_exactly(reader.by_ref(), b"\'")?;
-        Ok(chr)
+// ========================================================================= //
+
+pub(crate) fn read_exactly<R: Read>(
+    mut reader: R,
+    expected: &[u8],
+) -> io::Result<()> {
+    let mut actual = vec![0u8; expected.len()];
+    reader.read_exact(&mut actual)?;
+    if &actual as &[u8] != expected {
+        let msg = format!(
+            "expected '{}', found '{}'",
+            String::from_utf8_lossy(expected),
+            String::from_utf8_lossy(&actual)
+        );
+        Err(Error::new(ErrorKind::InvalidData, msg))
     } else {
-        Err(Error::new(ErrorKind::InvalidData, "empty char literal"))
+        Ok(())
     }
 }
 
-pub(crate) fn read_quoted_string<R: Read>(
-    mut reader: R,
-) -> io::Result<String> {
-    read_exactly(reader.by_ref(), b"\"")?;
-    let mut string = String::new();
-    while let Some(chr) = read_char_escape(reader.by_ref(), b'"')? {
-        string.push(chr);
+#[allow(clippy::write_with_newline)]
+pub(crate) fn write_comment<W: Write>(
+    mut writer: W,
+    comment: &str,
+) -> io::Result<()> {
+    if comment.is_empty() {
+        return Ok(());
+    }
+    for line in comment.split('\n') {
+        if line.is_empty() {
+            write!(writer, "#\n")?;
+        } else {
+            write!(writer, "# {}\n", line)?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn write_quoted_char<W: Write>(
+    mut writer: W,
+    chr: char,
+) -> io::Result<()> {
+    let escaped: String = chr.escape_default().collect();
+    write!(writer, "'{}'", escaped)
+}
+
+pub(crate) fn write_quoted_string<W: Write>(
+    mut writer: W,
+    string: &str,
+) -> io::Result<()> {
+    let mut escaped = String::new();
+    for chr in string.chars() {
+        escaped.push_str(&chr.escape_default().collect::<String>());
+    }
+    write!(writer, "\"{}\"", escaped)
+}
+
+pub(crate) fn write_list_of_i16s<W: Write>(
+    mut writer: W,
+    values: &[i16],
+) -> io::Result<()> {
+    write!(writer, "[")?;
+    let mut first = true;
+    for &value in values.iter() {
+        if first {
+            first = false;
+        } else {
+            write!(writer, ", ")?;
+        }
+        write!(writer, "{}", value)?;
+    }
+    write!(writer, "]")
+}
+
+pub(crate) fn read_u16<R: Read>(mut reader: R) -> io::Result<u16> {
+    let mut buffer = [0u8; 2];
+    reader.read_exact(&mut buffer)?;
+    Ok(u16::from_be_bytes(buffer))
+}
+
+pub(crate) fn write_u16<W: Write>(
+    mut writer: W,
+    value: u32,
+) -> io::Result<()> {
+    if value > MAX_HEADER_VALUE as u32 {
+        let msg = format!("value is too large for u16 field: {}", value);
+        return Err(Error::new(ErrorKind::InvalidInput, msg));
     }
-    Ok(string)
+    writer.write_all(&(value as u16).to_be_bytes())
 }
 
 // ========================================================================= //
@@ -0,0 +1,251 @@
+// +--------------------------------------------------------------------------+
+// | Copyright 2016 Matthew D. Steele <mdsteele@alum.mit.edu>                 |
+// |                                                                          |
+// | This file is part of AHI.                                                |
+// |                                                                          |
+// | AHI is free software: you can redistribute it and/or modify it under     |
+// | the terms of the GNU General Public License as published by the Free     |
+// | Software Foundation, either version 3 of the License, or (at your        |
+// | option) any later version.                                               |
+// |                                                                          |
+// | AHI is distributed in the hope that it will be useful, but WITHOUT ANY   |
+// | WARRANTY; without even the implied warranty of MERCHANTABILITY or        |
+// | FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License    |
+// | for details.                                                             |
+// |                                                                          |
+// | You should have received a copy of the GNU General Public License along  |
+// | with AHI.  If not, see <http://www.gnu.org/licenses/>.                   |
+// +--------------------------------------------------------------------------+
+
+use crate::internal::color::Color;
+use crate::internal::font::{Font, Glyph};
+use crate::internal::image::Image;
+use std::convert::TryFrom;
+
+// ========================================================================= //
+
+impl Font {
+    /// Returns a synthesized bold version of this font.  Each glyph's image
+    /// is dilated one pixel to the right (so each pixel is drawn twice, side
+    /// by side), making it one pixel wider, and its right edge is moved right
+    /// by one pixel to match.
+    pub fn emboldened(&self) -> Font {
+        self.map_glyphs(|glyph| {
+            let src = glyph.image();
+            let mut image = Image::new(src.width() + 1, src.height());
+            image.draw(src, 1, 0);
+            image.draw(src, 0, 0);
+            derived_glyph(
+                glyph,
+                image,
+                glyph.left_edge(),
+                glyph.right_edge() + 1,
+            )
+        })
+    }
+
+    /// Returns a synthesized italic version of this font.  Each row of each
+    /// glyph's image is shifted right by one pixel for every `slant` rows it
+    /// lies above the baseline (and left for rows below the baseline), so
+    /// that glyphs lean to the right while still sitting on the baseline.
+    /// Images are widened to fit, and the left and right edges are adjusted so
+    /// that glyphs keep their original advance.
+    ///
+    /// Panics if `slant` is zero.
+    pub fn italicized(&self, slant: u32) -> Font {
+        assert!(slant > 0);
+        let slant = slant as i32;
+        let baseline = self.baseline();
+        self.map_glyphs(|glyph| {
+            let src = glyph.image();
+            let height = src.height();
+            let shift = |row: u32| {
+                (baseline - 1 - glyph.top() - row as i32).div_euclid(slant)
+            };
+            let min_shift = (0..height).map(shift).min().unwrap_or(0);
+            let max_shift = (0..height).map(shift).max().unwrap_or(0);
+            let extra = (max_shift - min_shift) as u32;
+            let mut image = Image::new(src.width() + extra, height);
+            for row in 0..height {
+                let offset = (shift(row) - min_shift) as u32;
+                for col in 0..src.width() {
+                    image[(col + offset, row)] = src[(col, row)];
+                }
+            }
+            derived_glyph(
+                glyph,
+                image,
+                glyph.left_edge() - min_shift,
+                glyph.right_edge() - min_shift,
+            )
+        })
+    }
+
+    /// Returns a version of this font with a one-pixel outline of the given
+    /// color drawn around each glyph.  The outline covers every transparent
+    /// pixel that is adjacent (including diagonally) to a non-transparent
+    /// one.  Glyph images grow by two pixels in each dimension, so the glyph
+    /// height and line spacing increase by two, the baseline moves down by
+    /// one, and each glyph's advance increases by two.
+    pub fn outlined(&self, color: Color) -> Font {
+        let mut font = self.map_glyphs(|glyph| {
+            let src = glyph.image();
+            let mut image = Image::new(src.width() + 2, src.height() + 2);
+            for row in 0..src.height() {
+                for col in 0..src.width() {
+                    if src[(col, row)] != Color::C0 {
+                        image.fill_rect(col as i32, row as i32, 3, 3, color);
+                    }
+                }
+            }
+            image.draw(src, 1, 1);
+            derived_glyph(
+                glyph,
+                image,
+                glyph.left_edge(),
+                glyph.right_edge() + 2,
+            )
+        });
+        font.set_glyph_height(self.glyph_height() + 2);
+        font.set_baseline(self.baseline() + 1);
+        font.set_line_spacing(self.line_spacing() + 2);
+        font
+    }
+
+    /// Returns a version of this font scaled up by an integer factor.  Each
+    /// pixel of each glyph image becomes an `n` by `n` block, and the glyph
+    /// edges, baseline, line spacing, and kerning adjustments are all
+    /// multiplied by `n`.
+    ///
+    /// Panics if `n` is zero, or if any scaled image dimension, edge,
+    /// metric, or kerning adjustment would overflow.
+    pub fn scaled(&self, n: u32) -> Font {
+        assert!(n > 0);
+        let ni = i32::try_from(n).expect(SCALE_OVERFLOW);
+        let mul = |value: u32| value.checked_mul(n).expect(SCALE_OVERFLOW);
+        let muli = |value: i32| value.checked_mul(ni).expect(SCALE_OVERFLOW);
+        let mut font = self.map_glyphs(|glyph| {
+            let src = glyph.image();
+            let mut image = Image::new(mul(src.width()), mul(src.height()));
+            for row in 0..image.height() {
+                for col in 0..image.width() {
+                    image[(col, row)] = src[(col / n, row / n)];
+                }
+            }
+            let mut scaled = derived_glyph(
+                glyph,
+                image,
+                muli(glyph.left_edge()),
+                muli(glyph.right_edge()),
+            );
+            scaled.set_top(muli(glyph.top()));
+            scaled
+        });
+        font.set_glyph_height(mul(self.glyph_height()));
+        font.set_baseline(muli(self.baseline()));
+        font.set_line_spacing(mul(self.line_spacing()));
+        for ((left, right), adjustment) in self.kerning_pairs() {
+            font.set_kerning(left, right, muli(adjustment));
+        }
+        font
+    }
+}
+
+const SCALE_OVERFLOW: &str = "scaled font dimensions overflow";
+
+/// Returns a new glyph with the given image and edges, keeping the top
+/// offset, comment, tag, and metadata of the original glyph.
+fn derived_glyph(
+    glyph: &Glyph,
+    mut image: Image,
+    left: i32,
+    right: i32,
+) -> Glyph {
+    image.set_comment(glyph.comment());
+    image.set_tag(glyph.tag());
+    image.set_metadata(glyph.metadata().to_vec());
+    let mut derived = Glyph::new(image, left, right);
+    derived.set_top(glyph.top());
+    derived
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::testutil::{font_from_rows, image_rows, GlyphRows};
+
+    const GLYPHS: &[GlyphRows] = &[("L", 0, 3, &["10", "10", "12", "00"])];
+
+    #[test]
+    fn emboldened_font() {
+        let mut font = font_from_rows(4, 3, GLYPHS);
+        font['L'].set_tag("L");
+        let font = font.emboldened();
+        assert_eq!(
+            image_rows(font['L'].image()),
+            vec!["110", "110", "122", "000"]
+        );
+        assert_eq!(font['L'].right_edge(), 4);
+        assert_eq!(font['L'].tag(), "L");
+        assert_eq!(font.default_glyph().image().width(), 1);
+    }
+
+    #[test]
+    fn italicized_font() {
+        let font = font_from_rows(4, 3, GLYPHS).italicized(2);
+        assert_eq!(
+            image_rows(font['L'].image()),
+            vec!["0010", "0100", "0120", "0000"]
+        );
+        let font = font_from_rows(4, 3, GLYPHS).italicized(1);
+        assert_eq!(
+            image_rows(font['L'].image()),
+            vec!["00010", "00100", "01200", "00000"]
+        );
+        assert_eq!(font['L'].left_edge(), 1);
+        assert_eq!(font['L'].right_edge(), 4);
+        assert_eq!(font.glyph_height(), 4);
+    }
+
+    #[test]
+    fn outlined_font() {
+        let font = font_from_rows(4, 3, GLYPHS).outlined(Color::Cf);
+        assert_eq!(font.glyph_height(), 6);
+        assert_eq!(font.baseline(), 4);
+        assert_eq!(font.line_spacing(), 6);
+        assert_eq!(
+            image_rows(font['L'].image()),
+            vec!["FFF0", "F1F0", "F1FF", "F12F", "FFFF", "0000"]
+        );
+        assert_eq!(font['L'].right_edge(), 5);
+    }
+
+    #[test]
+    fn scaled_font() {
+        let mut font = font_from_rows(4, 3, GLYPHS);
+        font.set_kerning('L', 'L', -1);
+        let font = font.scaled(2);
+        assert_eq!(font.glyph_height(), 8);
+        assert_eq!(font.baseline(), 6);
+        assert_eq!(font.line_spacing(), 8);
+        assert_eq!(font['L'].right_edge(), 6);
+        assert_eq!(font.get_kerning('L', 'L'), -2);
+        assert_eq!(font['L'].image()[(3, 5)], Color::C2);
+        assert_eq!(font['L'].image()[(1, 6)], Color::C0);
+
+        let mut output = Vec::<u8>::new();
+        font.write(&mut output).unwrap();
+        let font = Font::read(&output as &[u8]).unwrap();
+        assert_eq!(font['L'].image().width(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "scaled font dimensions overflow")]
+    fn scaled_font_overflow() {
+        font_from_rows(4, 3, GLYPHS).scaled(1 << 30);
+    }
+}
+
+// ========================================================================= //
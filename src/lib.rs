@@ -47,9 +47,9 @@
 //!
 //! The start of the .ahi file is the _header line_, which has the form
 //! `ahi<version> w<width> h<height> n<num_images>`, where each of the four
-//! fields is a decimal number.  So, the above file is AHI version 0 (currently
-//! the only valid version), and contains two 20x5-pixel images (all the
-//! images in a single file must have the same dimensions).
+//! fields is a decimal number.  So, the above file is AHI version 0 (version 1
+//! is described below), and contains two 20x5-pixel images (all the images in
+//! a version 0 file must have the same dimensions).
 //!
 //! After the header line comes the images, which are separated from the header
 //! line and from each other by double-newlines.  Each image has one text line
@@ -66,6 +66,11 @@
 //! color, instead color `0` is special-cased to be transparent (and color `1`
 //! is black).
 //!
+//! In version 1 of the format, each image may be preceded by one or more
+//! _comment lines_, each starting with `#`.  Comments are attached to the
+//! image that follows them (see [`Image::comment`]), and are preserved when
+//! the collection is written back out.
+//!
 //! # The AHF format
 //!
 //! ASCII Hex Font (AHF) is a variation on the AHI file format, meant for
@@ -103,9 +108,9 @@
 //!
 //! The start of the .ahf file is the _header line_, which has the form
 //! `ahf<version> h<height> b<baseline> n<num_glyphs>`, where each of the four
-//! fields is a decimal number.  So, the above file is AHF version 0 (currently
-//! the only valid version), and contains two 6-pixel high glyphs in addition
-//! to the default glyph, with a baseline height of 5 pixels from the top.
+//! fields is a decimal number.  So, the above file is AHF version 0 (version 1
+//! is described below), and contains two 6-pixel high glyphs in addition to
+//! the default glyph, with a baseline height of 5 pixels from the top.
 //!
 //! After the header line comes the glyphs, which are separated from the header
 //! line and from each other by double-newlines.  Each glyph has a _subheader
@@ -122,315 +127,96 @@
 //! glyph's image and the virtual left/right edge of the glyph itself when
 //! printing a string.  Color mapping of pixels works the same as for AHI
 //! files.
+//!
+//! A glyph's `<char>` field may instead be a double-quoted string of two or
+//! more characters (e.g. `"fi"` or `"e\u{301}"`), giving a glyph for a
+//! ligature or combining sequence.  When text is drawn, the longest such key
+//! matching at each position is used.
+//!
+//! As in version 1 AHI files, each glyph's subheader line may be preceded by
+//! comment lines starting with `#`, which are attached to that glyph (see
+//! [`Glyph::comment`]).
+//!
+//! Version 1 of the AHF format has a header line of the form
+//! `ahf1 f<flags> p<num_palettes> h<height> b<baseline> s<spacing>
+//! n<num_glyphs>`, where `<flags>` is a hex number and `<spacing>` is the
+//! font's line spacing (the distance between baselines of successive lines).
+//! If flag 1 is set, the header line is followed by a line containing the
+//! font's name as a double-quoted string.  Any palettes come next, in the
+//! same format as for version 1 AHI files.  If flag 2 is set, each glyph's
+//! subheader line is followed by a line with that glyph's string tag (e.g.
+//! `"foo"`); if flag 4 is set, this is followed by a line with the glyph's
+//! list of integer metadata (e.g. `[1, -2, 3]`).  If flag 8 is set, the last
+//! glyph is followed by a blank line and a _kerning table_: a line of the
+//! form `kerning n<num_pairs>`, then one line per pair of the form
+//! `<left> <right> <adjustment>` (e.g. `'A' 'V' -1`), giving the number of
+//! pixels to add between the two characters.  If flag 10 (hex) is set,
+//! every glyph subheader line instead has the form `<char> w<width>
+//! h<height> t<top> l<left> r<right>`, giving the glyph's own image height
+//! and its offset in pixels down from the top of the font's glyph cell (see
+//! [`Glyph::top`]); otherwise, every glyph image is exactly `<height>` rows
+//! tall and sits at the top of the cell.  When writing, the lowest version
+//! able to represent the font is chosen automatically.
+//!
+//! Several related fonts (e.g. the regular, bold, and small versions of a UI
+//! font) can be stored together in an _AHF family file_ (see
+//! [`FontFamily`]).  This starts with a header line of the form `ahff0
+//! n<num_faces>`, followed by a line with the family's name as a
+//! double-quoted string.  Each face then follows after a blank line, as a
+//! line of the form `face <name> f<style>` (where `<name>` is a
+//! double-quoted string and `<style>` is a hex number with bit 1 for bold and
+//! bit 2 for italic), followed by the face's font as a complete AHF file.
+//!
+//! # Other font formats
+//!
+//! Fonts can also be converted to and from other bitmap font formats:
+//!
+//! * AngelCode BMFont text descriptors, with glyph images packed into an
+//!   atlas image (see [`Font::write_bmfont`] and [`Font::read_bmfont`]).
+//! * BDF (Glyph Bitmap Distribution Format) files, whose monochrome glyphs
+//!   are read in a chosen color (see [`Font::read_bdf`] and
+//!   [`Font::write_bdf`]).
+//! * Linux console PSF1 and PSF2 files, including their Unicode tables (see
+//!   [`Font::read_psf`], [`Font::write_psf1`], and [`Font::write_psf2`]).
+//!
+//! Fonts can also be exported (but not imported) as TrueType fonts, with
+//! each glyph's pixels traced into square-cornered outlines (see
+//! [`Font::write_ttf`]).
+//!
+//! # The BHI format
+//!
+//! BHI is a binary encoding of an AHI collection, with image pixels packed
+//! two to a byte, for applications that need to load many images quickly.
+//! A BHI file starts with the four bytes `"\x1Bbhi"`, followed by big-endian
+//! `u16` fields for the version (currently always 0), flags, number of
+//! palettes, and number of images.  The flags have the same meanings as in
+//! version 1 of the AHI format, plus flag 8 (which has no AHI equivalent),
+//! indicating that each image starts with its comment as a length-prefixed
+//! UTF-8 string, so that comments survive conversion to and from BHI.  Use
+//! [`Collection::read_bhi`] and [`Collection::write_bhi`] to work with BHI
+//! files directly; [`Collection::read`] accepts either encoding.
 
 #![warn(missing_docs)]
 
 mod internal;
 
-pub use crate::internal::collect::Collection;
+pub use crate::internal::collect::{Collection, FormatInfo};
 pub use crate::internal::color::Color;
+pub use crate::internal::coverage::{
+    BlockCoverage, CoverageReport, UnicodeBlock,
+};
+pub use crate::internal::error::{ParseError, ParseErrorKind};
+pub use crate::internal::family::{FontFace, FontFamily, FontStyle};
+pub use crate::internal::font::{Chars, Font, Glyph, KerningPairs, Sequences};
 pub use crate::internal::image::Image;
-pub use crate::internal::palette::Palette;
-use crate::internal::util::{
-    read_exactly, read_header_int, read_header_uint, read_quoted_char,
+pub use crate::internal::layout::{
+    Alignment, LayoutOptions, PlacedGlyph, TextLayout, TextLine,
 };
-use std::collections::{btree_map, BTreeMap};
-use std::io::{self, Error, ErrorKind, Read, Write};
-use std::ops::Deref;
-use std::rc::Rc;
-
-// ========================================================================= //
-
-// TODO: Once v1 format Image metadata is implemented, change ahf0-reading API
-// to just return a Collection with the appropriate image metadata.
-
-/// An image for a single character in a font, along with spacing information.
-#[derive(Clone)]
-pub struct Glyph {
-    image: Image,
-    left: i32,
-    right: i32,
-}
-
-impl Glyph {
-    /// Creates a new glyph with the given image and left/right edges.
-    pub fn new(image: Image, left: i32, right: i32) -> Glyph {
-        Glyph { image, left, right }
-    }
-
-    /// Returns the image for this glyph.
-    pub fn image(&self) -> &Image {
-        &self.image
-    }
-
-    /// Returns a mutable reference to the image for this glyph.
-    pub fn image_mut(&mut self) -> &mut Image {
-        &mut self.image
-    }
-
-    /// Returns the left edge of this glyph, in pixels.  This is the distance
-    /// (possibly negative) to the right of the left edge of this glyph's image
-    /// at which this glyph should start.  Normally this is zero, but can be
-    /// positive if the glyph needs extra space on the left, or negative if the
-    /// image should extend to the left (e.g. the tail on a lowercase j).
-    pub fn left_edge(&self) -> i32 {
-        self.left
-    }
-
-    /// Sets the left edge for this glyph.
-    pub fn set_left_edge(&mut self, left: i32) {
-        self.left = left;
-    }
-
-    /// Returns the right edge of this glyph, in pixels.  This is the distance
-    /// (possibly negative) to the right of the left edge of this glyph's image
-    /// at which the next glyph in the text should start, which may be slightly
-    /// different than the width of this glyph's image (e.g. if this is an
-    /// italic glyph whose image should extend a bit over that of the next
-    /// glyph).
-    pub fn right_edge(&self) -> i32 {
-        self.right
-    }
-
-    /// Sets the right edge for this glyph.
-    pub fn set_right_edge(&mut self, right: i32) {
-        self.right = right;
-    }
-}
-
-// ========================================================================= //
-
-/// Represents a mapping from characters to glyphs.
-#[derive(Clone)]
-pub struct Font {
-    glyphs: BTreeMap<char, Rc<Glyph>>,
-    default_glyph: Rc<Glyph>,
-    baseline: i32,
-}
-
-impl Font {
-    /// Creates a new, empty font whose glyphs have the given height, in
-    /// pixels.  The initial default glyph will be a zero-width space, and the
-    /// initial baseline will be equal to the height.
-    pub fn with_glyph_height(height: u32) -> Font {
-        Font {
-            glyphs: BTreeMap::new(),
-            default_glyph: Rc::new(Glyph::new(Image::new(0, height), 0, 0)),
-            baseline: height as i32,
-        }
-    }
-
-    /// Returns the image height of the glyphs in this font, in pixels.
-    pub fn glyph_height(&self) -> u32 {
-        self.default_glyph.image.height()
-    }
-
-    /// Returns the baseline height for this font, measured in pixels down from
-    /// the top of the glyph.  The baseline is the line on which e.g. most
-    /// numerals and capital letters sit, and below which e.g. the tail on a
-    /// lowercase 'g' or 'y' extends.
-    pub fn baseline(&self) -> i32 {
-        self.baseline
-    }
-
-    /// Sets the baseline value for this font, measured in pixels down from the
-    /// top of the glyph.  It is customary for the baseline to be in the range
-    /// (0, `height`], but note that this is not actually required.
-    pub fn set_baseline(&mut self, baseline: i32) {
-        self.baseline = baseline;
-    }
-
-    /// Gets the glyph for the given character, if any.  If you instead want to
-    /// get the default glyph for characters that have no glyph, use the index
-    /// operator.
-    pub fn get_char_glyph(&self, chr: char) -> Option<&Glyph> {
-        match self.glyphs.get(&chr) {
-            Some(glyph) => Some(glyph.deref()),
-            None => None,
-        }
-    }
-
-    /// Gets a mutable reference to the glyph for the given character, if any.
-    pub fn get_char_glyph_mut(&mut self, chr: char) -> Option<&mut Glyph> {
-        match self.glyphs.get_mut(&chr) {
-            Some(glyph) => Some(Rc::make_mut(glyph)),
-            None => None,
-        }
-    }
-
-    /// Sets the glyph for the given character.  Panics if the new glyph's
-    /// height is not equal to the font's glyph height.
-    pub fn set_char_glyph(&mut self, chr: char, glyph: Glyph) {
-        assert_eq!(glyph.image().height(), self.glyph_height());
-        self.glyphs.insert(chr, Rc::new(glyph));
-    }
-
-    /// Removes the glyph for the given character from the font.  After calling
-    /// this, the font's default glyph will be used for this character.
-    pub fn remove_char_glyph(&mut self, chr: char) {
-        self.glyphs.remove(&chr);
-    }
-
-    /// Gets the default glyph for this font, which is used for characters that
-    /// don't have a glyph.
-    pub fn default_glyph(&self) -> &Glyph {
-        &self.default_glyph
-    }
-
-    /// Gets a mutable reference to the default glyph for this font.
-    pub fn default_glyph_mut(&mut self) -> &mut Glyph {
-        Rc::make_mut(&mut self.default_glyph)
-    }
-
-    /// Sets the default glyph for this font.  Panics if the new glyph's height
-    /// is not equal to the font's glyph height.
-    pub fn set_default_glyph(&mut self, glyph: Glyph) {
-        assert_eq!(glyph.image().height(), self.glyph_height());
-        self.default_glyph = Rc::new(glyph);
-    }
-
-    /// Returns an iterator over the characters that have glyphs in this font.
-    pub fn chars(&self) -> Chars {
-        Chars { iter: self.glyphs.keys() }
-    }
-
-    /// Reads a font from an AHF file.
-    pub fn read<R: Read>(mut reader: R) -> io::Result<Font> {
-        read_exactly(reader.by_ref(), b"ahf")?;
-        let version = read_header_uint(reader.by_ref(), b' ')?;
-        if version != 0 {
-            let msg = format!("unsupported AHF version: {}", version);
-            return Err(Error::new(ErrorKind::InvalidData, msg));
-        }
-        read_exactly(reader.by_ref(), b"h")?;
-        let height = read_header_uint(reader.by_ref(), b' ')?;
-        read_exactly(reader.by_ref(), b"b")?;
-        let baseline = read_header_int(reader.by_ref(), b' ')?;
-        read_exactly(reader.by_ref(), b"n")?;
-        let num_glyphs = read_header_uint(reader.by_ref(), b'\n')?;
-
-        read_exactly(reader.by_ref(), b"\ndef ")?;
-        let default_glyph = Font::read_glyph(reader.by_ref(), height)?;
-
-        let mut glyphs = BTreeMap::new();
-        for _ in 0..num_glyphs {
-            read_exactly(reader.by_ref(), b"\n")?;
-            let chr = read_quoted_char(reader.by_ref())?;
-            read_exactly(reader.by_ref(), b" ")?;
-            let glyph = Font::read_glyph(reader.by_ref(), height)?;
-            glyphs.insert(chr, Rc::new(glyph));
-        }
-        Ok(Font { glyphs, default_glyph: Rc::new(default_glyph), baseline })
-    }
-
-    fn read_glyph<R: Read>(mut reader: R, height: u32) -> io::Result<Glyph> {
-        read_exactly(reader.by_ref(), b"w")?;
-        let width = read_header_uint(reader.by_ref(), b' ')?;
-        read_exactly(reader.by_ref(), b"l")?;
-        let left = read_header_int(reader.by_ref(), b' ')?;
-        read_exactly(reader.by_ref(), b"r")?;
-        let right = read_header_int(reader.by_ref(), b'\n')?;
-        let mut row_buffer = vec![0u8; width as usize];
-        let mut pixels = Vec::with_capacity((width * height) as usize);
-        for _ in 0..height {
-            reader.read_exact(&mut row_buffer)?;
-            for &byte in &row_buffer {
-                pixels.push(Color::from_byte(byte)?);
-            }
-            read_exactly(reader.by_ref(), b"\n")?;
-        }
-        let image = Image {
-            tag: String::new(),
-            metadata: Vec::new(),
-            width,
-            height,
-            pixels: pixels.into_boxed_slice(),
-        };
-        Ok(Glyph { image, left, right })
-    }
-
-    /// Writes the font to an AHF file.
-    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
-        let height = self.glyph_height();
-        write!(
-            writer,
-            "ahf0 h{} b{} n{}\n",
-            height,
-            self.baseline(),
-            self.glyphs.len()
-        )?;
-        write!(writer, "\ndef ")?;
-        Font::write_glyph(writer.by_ref(), &self.default_glyph)?;
-        for (chr, glyph) in self.glyphs.iter() {
-            let escaped: String = chr.escape_default().collect();
-            write!(writer, "\n'{}' ", escaped)?;
-            Font::write_glyph(writer.by_ref(), glyph)?;
-        }
-        Ok(())
-    }
-
-    fn write_glyph<W: Write>(mut writer: W, glyph: &Glyph) -> io::Result<()> {
-        let image = glyph.image();
-        let width = image.width();
-        let height = image.height();
-        write!(
-            writer,
-            "w{} l{} r{}\n",
-            width,
-            glyph.left_edge(),
-            glyph.right_edge()
-        )?;
-        for row in 0..height {
-            for col in 0..width {
-                let color = image[(col, row)];
-                writer.write_all(&[color.to_byte()])?;
-            }
-            write!(writer, "\n")?;
-        }
-        Ok(())
-    }
-}
-
-impl std::ops::Index<char> for Font {
-    type Output = Glyph;
-    fn index(&self, index: char) -> &Glyph {
-        self.glyphs.get(&index).unwrap_or(&self.default_glyph)
-    }
-}
-
-impl std::ops::IndexMut<char> for Font {
-    fn index_mut(&mut self, index: char) -> &mut Glyph {
-        Rc::make_mut(match self.glyphs.get_mut(&index) {
-            Some(glyph) => glyph,
-            None => &mut self.default_glyph,
-        })
-    }
-}
-
-// ========================================================================= //
-
-/// An iterator over a the characters that have glyphs in a font.
-pub struct Chars<'a> {
-    iter: btree_map::Keys<'a, char, Rc<Glyph>>,
-}
-
-impl<'a> Iterator for Chars<'a> {
-    type Item = char;
-
-    fn next(&mut self) -> Option<char> {
-        self.iter.next().map(|&chr| chr)
-    }
-
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.iter.size_hint()
-    }
-}
-
-impl<'a> ExactSizeIterator for Chars<'a> {
-    fn len(&self) -> usize {
-        self.iter.len()
-    }
-}
+pub use crate::internal::options::{ReadOptions, WriteOptions};
+pub use crate::internal::palette::Palette;
+pub use crate::internal::rich::Span;
+pub use crate::internal::stack::{FontStack, StackGlyph};
+pub use crate::internal::text::TextMetrics;
 
 // ========================================================================= //
 
@@ -438,82 +224,59 @@ impl<'a> ExactSizeIterator for Chars<'a> {
 mod tests {
     use super::*;
 
+    fn assert_send_sync<T: Send + Sync>() {}
+
     #[test]
-    fn read_font() {
-        let input: &[u8] = b"ahf0 h3 b2 n2\n\
-            \n\
-            def w3 l0 r4\n\
-            101\n\
-            010\n\
-            101\n\
-            \n\
-            '|' w1 l0 r2\n\
-            1\n\
-            1\n\
-            1\n\
-            \n\
-            '\\u{2603}' w2 l0 r4\n\
-            11\n\
-            11\n\
-            00\n";
-        let font = Font::read(input).expect("failed to read font");
-        assert_eq!(font.glyph_height(), 3);
-        assert_eq!(font.baseline(), 2);
-        assert_eq!(font.default_glyph().image().width(), 3);
-        assert_eq!(font.default_glyph().left_edge(), 0);
-        assert_eq!(font.default_glyph().right_edge(), 4);
-        assert_eq!(font['|'].image().width(), 1);
-        assert_eq!(font.chars().len(), 2);
+    fn public_types_are_send_and_sync() {
+        assert_send_sync::<Alignment>();
+        assert_send_sync::<BlockCoverage>();
+        assert_send_sync::<Chars<'static>>();
+        assert_send_sync::<Collection>();
+        assert_send_sync::<Color>();
+        assert_send_sync::<CoverageReport>();
+        assert_send_sync::<Font>();
+        assert_send_sync::<FontFace>();
+        assert_send_sync::<FontFamily>();
+        assert_send_sync::<FontStack>();
+        assert_send_sync::<FontStyle>();
+        assert_send_sync::<FormatInfo>();
+        assert_send_sync::<Glyph>();
+        assert_send_sync::<Image>();
+        assert_send_sync::<KerningPairs<'static>>();
+        assert_send_sync::<LayoutOptions>();
+        assert_send_sync::<Palette>();
+        assert_send_sync::<ParseError>();
+        assert_send_sync::<ParseErrorKind>();
+        assert_send_sync::<PlacedGlyph<'static>>();
+        assert_send_sync::<ReadOptions>();
+        assert_send_sync::<Sequences<'static>>();
+        assert_send_sync::<Span<'static>>();
+        assert_send_sync::<StackGlyph<'static>>();
+        assert_send_sync::<TextLayout<'static>>();
+        assert_send_sync::<TextLine<'static>>();
+        assert_send_sync::<TextMetrics>();
+        assert_send_sync::<UnicodeBlock>();
+        assert_send_sync::<WriteOptions>();
     }
 
     #[test]
-    fn write_font() {
-        let mut font = Font::with_glyph_height(3);
-        font.set_baseline(2);
-
-        let mut img_default = Image::new(3, 3);
-        img_default[(0, 0)] = Color::C1;
-        img_default[(2, 0)] = Color::C1;
-        img_default[(1, 1)] = Color::C1;
-        img_default[(0, 2)] = Color::C1;
-        img_default[(2, 2)] = Color::C1;
-        font.set_default_glyph(Glyph::new(img_default, 0, 4));
-
-        let mut img_snowman = Image::new(2, 3);
-        img_snowman[(0, 0)] = Color::C1;
-        img_snowman[(1, 0)] = Color::C1;
-        img_snowman[(0, 1)] = Color::C1;
-        img_snowman[(1, 1)] = Color::C1;
-        font.set_char_glyph('\u{2603}', Glyph::new(img_snowman, 0, 4));
-
-        let mut img_vbar = Image::new(1, 3);
-        img_vbar[(0, 0)] = Color::C1;
-        img_vbar[(0, 1)] = Color::C1;
-        img_vbar[(0, 2)] = Color::C1;
-        font.set_char_glyph('|', Glyph::new(img_vbar, 0, 2));
-
-        let mut output = Vec::<u8>::new();
-        font.write(&mut output).expect("failed to write font");
-        let mut expected = Vec::<u8>::new();
-        expected.extend_from_slice(
-            b"ahf0 h3 b2 n2\n\
-            \n\
-            def w3 l0 r4\n\
-            101\n\
-            010\n\
-            101\n\
-            \n\
-            '|' w1 l0 r2\n\
-            1\n\
-            1\n\
-            1\n\
-            \n\
-            '\\u{2603}' w2 l0 r4\n\
-            11\n\
-            11\n\
-            00\n",
-        );
-        assert_eq!(output, expected);
+    fn share_font_across_threads() {
+        let mut font = Font::with_glyph_height(2);
+        font.set_char_glyph('a', Glyph::new(Image::new(1, 2), 0, 2));
+        let font = std::sync::Arc::new(font);
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let font = font.clone();
+                std::thread::spawn(move || font.measure("aa").width())
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), 4);
+        }
+        let mut copy = (*font).clone();
+        copy['a'].set_right_edge(5);
+        assert_eq!(copy['a'].right_edge(), 5);
+        assert_eq!(font['a'].right_edge(), 2);
     }
 }
 